@@ -22,6 +22,7 @@ fn main() -> Result<()> {
 
     let mut store = Store::new(settings.notes_dir.clone(), settings.db_path.clone())
         .context("Failed to initialize store")?;
+    store.set_frontmatter_format(settings.frontmatter_format);
 
     match &cli.command {
         Commands::List => {
@@ -48,10 +49,7 @@ fn main() -> Result<()> {
             
             store.create(&path, body.as_deref().unwrap_or(""), Some(Metadata {
                 title: Some(title.clone()),
-                tags: None,
-                created_at: None,
-                updated_at: None,
-                extra: Default::default(),
+                ..Default::default()
             }))?;
             println!("Created note: {}", filename);
         }
@@ -87,8 +85,8 @@ fn main() -> Result<()> {
             let start = start.clamp(0, total_lines);
             let end = end.clamp(start, total_lines);
 
-            for i in start..end {
-                println!("{}", lines[i]);
+            for line in &lines[start..end] {
+                println!("{}", line);
             }
         }
         Commands::Update { name, body, rename } => {
@@ -103,10 +101,10 @@ fn main() -> Result<()> {
                 if !new_filename.ends_with(".md") {
                     new_filename.push_str(".md");
                 }
-                store.move_note(&path, &PathBuf::from(&new_filename))?;
+                store.move_note(&path, &PathBuf::from(&new_filename), None)?;
                 println!("Renamed {} to {}", filename, new_filename);
             } else {
-                 store.update(&path, body.as_deref(), None)?;
+                 store.update(&path, body.as_deref(), None, None)?;
                  println!("Updated note: {}", filename);
             }
         }
@@ -116,7 +114,7 @@ fn main() -> Result<()> {
                 filename.push_str(".md");
             }
             let path = PathBuf::from(&filename);
-            store.delete(&path)?;
+            store.delete(&path, None)?;
             println!("Deleted note: {}", filename);
         }
         Commands::Move { from, to } => {
@@ -130,7 +128,7 @@ fn main() -> Result<()> {
                 to_filename.push_str(".md");
             }
             
-            store.move_note(&PathBuf::from(&from_filename), &PathBuf::from(&to_filename))?;
+            store.move_note(&PathBuf::from(&from_filename), &PathBuf::from(&to_filename), None)?;
             println!("Moved {} to {}", from_filename, to_filename);
         }
         Commands::Sync => {
@@ -0,0 +1,40 @@
+//! Note edits as the MCP and HTTP servers receive them.
+
+use extt_core::Store;
+use extt_core::types::NoteEdit;
+use serde::Deserialize;
+use std::path::Path;
+
+/// In-place edits to one note, shared by the MCP `update` tool and
+/// `PATCH /notes/{path}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct NoteChanges {
+    #[serde(flatten)]
+    pub edit: NoteEdit,
+    pub expected_revision: Option<String>,
+}
+
+impl NoteChanges {
+    /// Applies the changes in one write, checked against
+    /// `expected_revision`, and returns the note's new revision.
+    pub fn apply(&self, store: &mut Store, path: &Path) -> Result<String, Rejected> {
+        if self.edit.content.is_some() && self.edit.body_edit.is_some() {
+            return Err(Rejected::Invalid("`content` and `body_edit` cannot be combined".into()));
+        }
+        Ok(store.edit(path, &self.edit, self.expected_revision.as_deref())?)
+    }
+}
+
+/// Why [`NoteChanges::apply`] did not go through.
+#[derive(Debug)]
+pub enum Rejected {
+    /// The request itself makes no sense.
+    Invalid(String),
+    Store(extt_core::Error),
+}
+
+impl From<extt_core::Error> for Rejected {
+    fn from(err: extt_core::Error) -> Self {
+        Rejected::Store(err)
+    }
+}
@@ -1,22 +1,127 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use extt_core::timestamps;
+use extt_core::types::{Author, SortKey};
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "extt")]
 #[command(about = "A notes system for the terminal", long_about = None)]
 pub struct Cli {
+    /// Output format
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// Who git commits are attributed to; `mcp` defaults to `agent`
+    #[arg(long, global = true, value_enum)]
+    pub author: Option<AuthorArg>,
     #[command(subcommand)]
     pub command: Commands,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// Pretty-printed JSON document
+    Json,
+    /// One compact JSON value per line
+    Jsonl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AuthorArg {
+    /// The user from the repository's git config
+    Human,
+    /// An agent acting for the user
+    Agent,
+}
+
+impl From<AuthorArg> for Author {
+    fn from(author: AuthorArg) -> Self {
+        match author {
+            AuthorArg::Human => Author::Human,
+            AuthorArg::Agent => Author::Agent,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortArg {
+    Path,
+    Title,
+    /// Newest first
+    Created,
+    /// Most recently updated first
+    Updated,
+}
+
+impl From<SortArg> for SortKey {
+    fn from(sort: SortArg) -> Self {
+        match sort {
+            SortArg::Path => SortKey::Path,
+            SortArg::Title => SortKey::Title,
+            SortArg::Created => SortKey::Created,
+            SortArg::Updated => SortKey::Updated,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// List notes in configured folder
     #[command(visible_alias = "ls")]
-    List,
+    List {
+        /// Only notes with this tag or one of its children
+        #[arg(long)]
+        tag: Option<String>,
+        /// Sort order; defaults to path
+        #[arg(long, value_enum)]
+        sort: Option<SortArg>,
+        /// Only notes updated (created with `--sort created`) at or after
+        /// TIME: a date, an RFC 3339 time or an age such as 7d
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        since: Option<i64>,
+        /// Only notes updated (created with `--sort created`) before TIME
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        until: Option<i64>,
+    },
     /// Search notes by name or content
     #[command(visible_alias = "s")]
     Search {
         query: String,
+        /// Only notes with this tag or one of its children
+        #[arg(long)]
+        tag: Option<String>,
+        /// Sort order; best matches first by default
+        #[arg(long, value_enum)]
+        sort: Option<SortArg>,
+        /// Only notes updated (created with `--sort created`) at or after
+        /// TIME: a date, an RFC 3339 time or an age such as 7d
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        since: Option<i64>,
+        /// Only notes updated (created with `--sort created`) before TIME
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        until: Option<i64>,
+    },
+    /// List tags with the number of notes using each
+    Tags,
+    /// List the links in a note, or every link that points nowhere
+    Links {
+        #[arg(required_unless_present = "unresolved")]
+        name: Option<String>,
+        /// Show unresolved links across all notes instead
+        #[arg(long, conflicts_with = "name")]
+        unresolved: bool,
+    },
+    /// List the notes that link to a note
+    Backlinks {
+        name: String,
+    },
+    /// Check the notes folder and the index for problems
+    Doctor {
+        /// Bring the index in line with the notes; notes are never changed
+        #[arg(long)]
+        fix: bool,
     },
     /// Create a new note
     #[command(visible_alias = "n")]
@@ -41,32 +146,125 @@ pub enum Commands {
         /// End at line N
         #[arg(long)]
         to: Option<usize>,
+        /// Shorthand for `--format json`
+        #[arg(long)]
+        json: bool,
     },
     /// Update a note
     #[command(visible_alias = "u")]
     Update {
         name: String,
-        #[arg(long)]
+        /// New body, or the text for `--from/--to`, `--insert` and `--section`
+        #[arg(long, allow_hyphen_values = true)]
         body: Option<String>,
+        /// First body line to replace or delete
+        #[arg(long)]
+        from: Option<usize>,
+        /// Last body line to replace or delete (defaults to `--from`)
+        #[arg(long, requires = "from", conflicts_with = "insert")]
+        to: Option<usize>,
+        /// Insert `--body` before line `--from` (or at the end) instead of replacing
+        #[arg(long, requires = "body", conflicts_with = "delete")]
+        insert: bool,
+        /// Delete lines `--from..--to`
+        #[arg(long, requires = "from", conflicts_with = "body")]
+        delete: bool,
+        /// Replace the content under this heading with `--body`
+        #[arg(long, value_name = "HEADING", requires = "body", conflicts_with_all = ["from", "insert", "delete"])]
+        section: Option<String>,
+        /// Set the frontmatter title
+        #[arg(long)]
+        title: Option<String>,
+        /// Set a frontmatter field, e.g. `--set metadata.status=done`
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        set: Vec<(String, String)>,
+        /// Remove a frontmatter field
+        #[arg(long, value_name = "KEY")]
+        unset: Vec<String>,
+        /// Add a tag
+        #[arg(long, value_name = "TAG")]
+        add_tag: Vec<String>,
+        /// Remove a tag
+        #[arg(long, value_name = "TAG")]
+        remove_tag: Vec<String>,
         #[arg(long)]
         rename: Option<String>,
-        // Partial update not fully implemented in CLI yet, but requested.
-        // For now sticking to full body update as primary.
+        /// Only write if the note is still at this revision (see `read --json`)
+        #[arg(long, value_name = "REV")]
+        if_match: Option<String>,
     },
     /// Delete a note
     #[command(visible_alias = "d")]
     Delete {
         name: String,
+        /// Only delete if the note is still at this revision
+        #[arg(long, value_name = "REV")]
+        if_match: Option<String>,
+        /// Delete for good instead of moving the note to the trash
+        #[arg(long)]
+        permanent: bool,
     },
     /// Move a note
     #[command(visible_alias = "mv")]
     Move {
         from: String,
         to: String,
+        /// Only move if the note is still at this revision
+        #[arg(long, value_name = "REV")]
+        if_match: Option<String>,
+        /// Rewrite wikilinks and markdown links so they keep pointing at the note
+        #[arg(long)]
+        rewrite_links: bool,
+        /// Show the link rewrites without moving anything
+        #[arg(long, requires = "rewrite_links")]
+        dry_run: bool,
+    },
+    /// List the saved versions of a note, newest first
+    History { name: String },
+    /// Show how a note changed since a saved version
+    Diff {
+        name: String,
+        /// Revision from `extt history`; a unique prefix is enough
+        revision: String,
+    },
+    /// Restore a note to a saved version
+    Revert {
+        name: String,
+        /// Revision from `extt history`; a unique prefix is enough
+        revision: String,
+        /// Only revert if the note is still at this revision
+        #[arg(long, value_name = "REV")]
+        if_match: Option<String>,
+    },
+    /// Show the git commits that changed a note, or the whole vault
+    Log { name: Option<String> },
+    /// List, restore or empty deleted notes
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
     },
     /// Sync database
     #[command(visible_alias = "sy")]
-    Sync,
+    Sync {
+        /// Drop the index and rebuild it from the notes on disk
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Watch the notes folder and keep the index up to date
+    #[command(visible_alias = "w")]
+    Watch {
+        /// Milliseconds to wait for more changes before re-indexing
+        #[arg(long, default_value_t = 250)]
+        debounce: u64,
+    },
+    /// Serve notes to AI agents over the Model Context Protocol (stdio)
+    Mcp,
+    /// Serve the notes as a local HTTP/JSON API
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        bind: String,
+    },
     /// Initialize the configuration
     #[command(visible_alias = "i")]
     Init,
@@ -81,6 +279,68 @@ pub enum Commands {
     Upgrade,
 }
 
+#[derive(Subcommand)]
+pub enum TrashCommand {
+    /// List deleted notes, most recent first
+    #[command(visible_alias = "ls")]
+    List,
+    /// Bring a deleted note back
+    Restore {
+        /// Trash id, or the note's name before it was deleted
+        name: String,
+        /// Restore under a different name
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Permanently delete notes in the trash
+    Empty {
+        /// Only notes deleted at least this long ago, e.g. `30d`, `12h`
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Option<Duration>,
+    },
+}
+
+/// `90s`, `45m`, `12h`, `30d` or `2w`.
+fn parse_age(raw: &str) -> Result<Duration, String> {
+    let split = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("expected an age like 30d, got `{raw}`"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit in `{raw}`; use s, m, h, d or w")),
+    };
+    let total = number.checked_mul(seconds).ok_or_else(|| format!("age `{raw}` is too large"))?;
+    // Ages are subtracted from `i64` timestamps later on.
+    if i64::try_from(total).is_err() {
+        return Err(format!("age `{raw}` is too large"));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// A point in time for `--since` / `--until`: a date, an RFC 3339 time, or
+/// an age counted back from now.
+fn parse_time(raw: &str) -> Result<i64, String> {
+    if let Some(time) = timestamps::parse(raw) {
+        return Ok(time);
+    }
+    parse_age(raw)
+        .ok()
+        .and_then(|age| i64::try_from(age.as_secs()).ok())
+        .and_then(|age| timestamps::now().checked_sub(age))
+        .ok_or_else(|| format!("expected a date like 2024-05-01, an RFC 3339 time or an age like 7d, got `{raw}`"))
+}
+
+fn parse_key_value(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{raw}`")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,4 +350,62 @@ mod tests {
     fn verify_cli() {
         Cli::command().debug_assert();
     }
+
+    #[test]
+    fn test_format_is_global() {
+        let cli = Cli::try_parse_from(["extt", "list", "--format", "jsonl"]).unwrap();
+        assert_eq!(cli.format, Format::Jsonl);
+        let cli = Cli::try_parse_from(["extt", "--format", "json", "search", "x"]).unwrap();
+        assert_eq!(cli.format, Format::Json);
+        assert_eq!(Cli::try_parse_from(["extt", "version"]).unwrap().format, Format::Text);
+    }
+
+    #[test]
+    fn test_links_needs_name_or_unresolved() {
+        assert!(Cli::try_parse_from(["extt", "links"]).is_err());
+        assert!(Cli::try_parse_from(["extt", "links", "a", "--unresolved"]).is_err());
+        assert!(Cli::try_parse_from(["extt", "links", "--unresolved"]).is_ok());
+        assert!(Cli::try_parse_from(["extt", "links", "a"]).is_ok());
+    }
+
+    #[test]
+    fn test_update_edit_flags() {
+        assert!(Cli::try_parse_from(["extt", "update", "a", "--from", "2", "--to", "3", "--body", "x"]).is_ok());
+        assert!(Cli::try_parse_from(["extt", "update", "a", "--insert", "--body", "x"]).is_ok());
+        assert!(Cli::try_parse_from(["extt", "update", "a", "--section", "Tasks", "--body", ""]).is_ok());
+        assert!(Cli::try_parse_from(["extt", "update", "a", "--to", "3", "--body", "x"]).is_err());
+        assert!(Cli::try_parse_from(["extt", "update", "a", "--from", "1", "--delete", "--body", "x"]).is_err());
+        assert!(Cli::try_parse_from(["extt", "update", "a", "--section", "Tasks", "--from", "1", "--body", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_key_value() {
+        assert_eq!(parse_key_value("a.b=c=d"), Ok(("a.b".to_string(), "c=d".to_string())));
+        assert_eq!(parse_key_value("status="), Ok(("status".to_string(), String::new())));
+        assert!(parse_key_value("status").is_err());
+        assert!(parse_key_value("=x").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("2024-05-01"), Ok(1_714_521_600));
+        assert_eq!(parse_time("2024-05-01T09:30:00+00:00"), Ok(1_714_555_800));
+        let week_ago = timestamps::now() - 7 * 86400;
+        assert!((parse_time("7d").unwrap() - week_ago).abs() <= 1);
+        assert!(parse_time("last week").is_err());
+        assert!(parse_time("10000000000000000000s").is_err());
+        assert!(parse_time("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("5y").is_err());
+        assert_eq!(parse_age("99999999999999999w"), Err("age `99999999999999999w` is too large".into()));
+        assert_eq!(parse_age("10000000000000000000s"), Err("age `10000000000000000000s` is too large".into()));
+    }
 }
@@ -1,14 +1,107 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use extt_core::{Store, types::Metadata};
+use extt_core::watcher::{self, WatchOptions};
+use extt_core::frontmatter::parse_scalar;
+use extt_core::types::{Author, BodyEdit, GitOptions, ListQuery, Metadata, MetadataEdit, MoveOptions, NoteEdit, Problem};
+use extt_core::Store;
 use extt_settings::Settings;
+use std::io::{self, Write};
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, SystemTime};
 
+mod changes;
 mod cli;
-use cli::{Cli, Commands};
+mod mcp;
+mod output;
+mod paths;
+mod serve;
+use cli::{Cli, Commands, Format, TrashCommand};
+use output::{Action, Finding, Output, Written};
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let format = requested_format(std::env::args());
+            if err.use_stderr() && format != Format::Text {
+                let message = err.to_string();
+                let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+                let _ = Output::new(format, io::stderr()).error("usage", 2, message);
+                return ExitCode::from(2);
+            }
+            err.exit();
+        }
+    };
+    let format = match cli.command {
+        Commands::Read { json: true, .. } => Format::Json,
+        _ => cli.format,
+    };
+
+    match run(&cli, &mut Output::new(format, io::stdout().lock())) {
+        Ok(()) => ExitCode::SUCCESS,
+        // The reader went away (e.g. `extt list | head`); nothing left to say.
+        Err(err) if err.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => {
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            let (exit_code, code) = error_kind(&err);
+            let _ = Output::new(format, io::stderr()).error(code, exit_code, &format!("{:#}", err));
+            ExitCode::from(exit_code)
+        }
+    }
+}
+
+/// Distinct exit codes and names per core error so scripts and agents can
+/// branch on the kind of failure. 1 is any other error; 2 is used by clap for
+/// usage errors.
+fn error_kind(err: &anyhow::Error) -> (u8, &'static str) {
+    let Some(err) = err.chain().find_map(|e| e.downcast_ref::<extt_core::Error>()) else {
+        return (1, "other");
+    };
+    match err {
+        extt_core::Error::NotFound(_) => (3, "not_found"),
+        extt_core::Error::AlreadyExists(_) => (4, "already_exists"),
+        extt_core::Error::PathTraversal(_) => (5, "path_traversal"),
+        extt_core::Error::AbsolutePath(_) => (6, "absolute_path"),
+        extt_core::Error::InvalidFrontmatter { .. } => (7, "invalid_frontmatter"),
+        extt_core::Error::InvalidUtf8(_) => (8, "invalid_utf8"),
+        extt_core::Error::DatabaseLocked => (9, "database_locked"),
+        extt_core::Error::IndexCorrupt => (10, "index_corrupt"),
+        extt_core::Error::SchemaTooNew { .. } => (11, "schema_too_new"),
+        extt_core::Error::Database(_) => (12, "database"),
+        extt_core::Error::Watch(_) => (13, "watch"),
+        extt_core::Error::Io(_) => (14, "io"),
+        extt_core::Error::InvalidRange { .. } => (15, "invalid_range"),
+        extt_core::Error::SectionNotFound { .. } => (16, "section_not_found"),
+        extt_core::Error::Conflict { .. } => (17, "conflict"),
+        extt_core::Error::UnknownRevision { .. } => (18, "unknown_revision"),
+        extt_core::Error::Git(_) => (19, "git"),
+        extt_core::Error::GitDisabled => (20, "git_disabled"),
+        extt_core::Error::VaultBusy => (21, "vault_busy"),
+        _ => (1, "other"),
+    }
+}
+
+/// `--format` as given on a command line that clap rejected, so usage errors
+/// can still be reported in the requested format.
+fn requested_format(args: impl IntoIterator<Item = String>) -> Format {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--format") {
+            Some("") => args.next(),
+            Some(value) => value.strip_prefix('=').map(str::to_string),
+            None => None,
+        };
+        if let Some(format) = value.and_then(|v| <Format as clap::ValueEnum>::from_str(&v, true).ok()) {
+            return format;
+        }
+    }
+    Format::Text
+}
+
+fn run<W: Write>(cli: &Cli, out: &mut Output<W>) -> Result<()> {
     let settings = Settings::load().context("Failed to load settings")?;
     
     // Ensure notes dir and db path exists
@@ -20,21 +113,59 @@ fn main() -> Result<()> {
         std::fs::create_dir_all(&settings.notes_dir).context("Failed to create notes directory")?;
     }
 
-    let mut store = Store::new(settings.notes_dir.clone(), settings.db_path.clone())
+    let (mut store, rebuilt) = Store::open_or_rebuild(settings.notes_dir.clone(), settings.db_path.clone())
         .context("Failed to initialize store")?;
+    if let Some(report) = rebuilt {
+        // Diagnostics go to stderr so they never mix with JSON on stdout.
+        eprintln!("Index database was corrupt and has been rebuilt ({} notes indexed).", report.added);
+    }
+    if settings.git {
+        let author = match (cli.author, &cli.command) {
+            (Some(author), _) => author.into(),
+            (None, Commands::Mcp) => Author::Agent,
+            (None, _) => Author::Human,
+        };
+        store.enable_git(GitOptions { author, push: settings.git_push }).context("Failed to open the notes git repository")?;
+    }
+    store.set_stamp_times(settings.stamp_times);
+    store.set_frontmatter_format(settings.frontmatter_format);
 
     match &cli.command {
-        Commands::List => {
-            let notes = store.list()?;
-            for note in notes {
-                println!("{}", note.path.display());
-            }
+        Commands::List { tag, sort, since, until } => {
+            let query = ListQuery { tag: tag.clone(), sort: sort.map(Into::into), since: *since, until: *until, ..Default::default() };
+            out.list(&store.query(&query)?.items)?;
         }
-        Commands::Search { query } => {
-            let notes = store.search(query)?;
-            for note in notes {
-                println!("{}: {}", note.path.display(), note.title.as_deref().unwrap_or("No Title"));
-            }
+        Commands::Search { query, tag, sort, since, until } => {
+            let filter = ListQuery { tag: tag.clone(), sort: sort.map(Into::into), since: *since, until: *until, ..Default::default() };
+            out.search(&store.search_with(query, &filter)?.items)?;
+        }
+        Commands::Tags => {
+            out.tags(&store.tags()?)?;
+        }
+        Commands::Links { name: Some(name), .. } => {
+            out.links(&store.outgoing_links(&paths::note_path(name))?)?;
+        }
+        Commands::Links { name: None, .. } => {
+            out.links(&store.unresolved_links()?)?;
+        }
+        Commands::Backlinks { name } => {
+            out.links(&store.backlinks(&paths::note_path(name))?)?;
+        }
+        Commands::Doctor { fix } => {
+            let problems = store.check()?;
+            let findings: Vec<Finding> = if *fix && problems.iter().any(Problem::is_fixable) {
+                store.repair(&problems)?;
+                let (fixed, _): (Vec<_>, Vec<_>) = problems.into_iter().partition(Problem::is_fixable);
+                // Check again: the repaired index can change what else is
+                // wrong, such as links to a note that was just indexed.
+                let left = store.check()?;
+                fixed.into_iter().map(|problem| Finding { problem, fixed: true })
+                    .chain(left.into_iter().map(|problem| Finding { problem, fixed: false }))
+                    .collect()
+            } else {
+                problems.into_iter().map(|problem| Finding { problem, fixed: false }).collect()
+            };
+            out.findings(&findings)?;
         }
         Commands::New { title, body } => {
             // Check if title ends with .md or not. 
@@ -48,21 +179,28 @@ fn main() -> Result<()> {
             
             store.create(&path, body.as_deref().unwrap_or(""), Some(Metadata {
                 title: Some(title.clone()),
-                tags: None,
-                created_at: None,
-                updated_at: None,
-                extra: Default::default(),
+                ..Default::default()
             }))?;
-            println!("Created note: {}", filename);
+            out.written(&Written {
+                action: Action::Created,
+                revision: Some(store.get(&path)?.revision),
+                path,
+                from: None,
+            })?;
         }
-        Commands::Read { name, tail, head, from, to } => {
+        Commands::Read { name, tail, head, from, to, json: _ } => {
             let mut filename = name.clone();
             if !filename.ends_with(".md") {
                 filename.push_str(".md");
             }
             let path = PathBuf::from(&filename);
-            let note = store.get(&path)?;
-            
+            let mut note = store.get(&path)?;
+            if out.is_text() {
+                for diagnostic in &note.diagnostics {
+                    eprintln!("warning: {}: {}", path.display(), diagnostic);
+                }
+            }
+
             let lines: Vec<&str> = note.content.lines().collect();
             let total_lines = lines.len();
             
@@ -87,39 +225,133 @@ fn main() -> Result<()> {
             let start = start.clamp(0, total_lines);
             let end = end.clamp(start, total_lines);
 
-            for i in start..end {
-                println!("{}", lines[i]);
+            if (start, end) != (0, total_lines) {
+                note.content = lines[start..end].iter().map(|line| format!("{line}\n")).collect();
             }
+            out.note(&note)?;
         }
-        Commands::Update { name, body, rename } => {
+        Commands::Update {
+            name, body, from, to, insert, delete, section, title, set, unset, add_tag, remove_tag, rename, if_match,
+        } => {
              let mut filename = name.clone();
             if !filename.ends_with(".md") {
                 filename.push_str(".md");
             }
             let path = PathBuf::from(&filename);
-            
+
+            let metadata: Vec<MetadataEdit> = set
+                .iter()
+                .map(|(key, value)| MetadataEdit::Set { key: key.clone(), value: parse_scalar(value) })
+                .chain(unset.iter().map(|key| MetadataEdit::Unset { key: key.clone() }))
+                .chain(add_tag.iter().map(|tag| MetadataEdit::Append { key: "tags".into(), value: tag.as_str().into() }))
+                .chain(remove_tag.iter().map(|tag| MetadataEdit::Remove { key: "tags".into(), value: tag.as_str().into() }))
+                .collect();
+            let content = body.clone().unwrap_or_default();
+            let body_edit = if let Some(heading) = section {
+                Some(BodyEdit::ReplaceSection { heading: heading.clone(), content })
+            } else if *insert {
+                let at = match from {
+                    Some(at) => *at,
+                    None => store.get(&path)?.content.lines().count() + 1,
+                };
+                Some(BodyEdit::Insert { at, content })
+            } else if let Some(from) = *from {
+                let to = to.unwrap_or(from);
+                Some(if *delete {
+                    BodyEdit::Delete { from, to }
+                } else {
+                    BodyEdit::Replace { from, to, content }
+                })
+            } else {
+                None
+            };
+            let content = if body_edit.is_some() { None } else { body.clone() };
+            let edit = NoteEdit { content, body_edit, title: title.clone(), metadata };
+
+            // A rename after an edit must find the note as the edit left it.
+            let edited = !edit.is_empty();
+            let revision = if edited { Some(store.edit(&path, &edit, if_match.as_deref())?) } else { None };
+            let if_match = revision.as_deref().or(if_match.as_deref());
+
+            let mut written = Written { action: Action::Updated, path, from: None, revision: None };
             if let Some(new_name) = rename {
                  let mut new_filename = new_name.clone();
                 if !new_filename.ends_with(".md") {
                     new_filename.push_str(".md");
                 }
-                store.move_note(&path, &PathBuf::from(&new_filename))?;
-                println!("Renamed {} to {}", filename, new_filename);
-            } else {
-                 store.update(&path, body.as_deref(), None)?;
-                 println!("Updated note: {}", filename);
+                let new_path = PathBuf::from(&new_filename);
+                store.move_note(&written.path, &new_path, if_match)?;
+                if !edited {
+                    written.action = Action::Moved;
+                }
+                written.from = Some(std::mem::replace(&mut written.path, new_path));
+            } else if !edited {
+                return Ok(());
             }
+            written.revision = Some(store.get(&written.path)?.revision);
+            out.written(&written)?;
         }
-        Commands::Delete { name } => {
+        Commands::Delete { name, if_match, permanent } => {
              let mut filename = name.clone();
             if !filename.ends_with(".md") {
                 filename.push_str(".md");
             }
             let path = PathBuf::from(&filename);
-            store.delete(&path)?;
-            println!("Deleted note: {}", filename);
+            let action = if *permanent {
+                store.delete_permanently(&path, if_match.as_deref())?;
+                Action::Deleted
+            } else {
+                store.delete(&path, if_match.as_deref())?;
+                Action::Trashed
+            };
+            out.written(&Written { action, path, from: None, revision: None })?;
+        }
+        Commands::Trash { command } => match command {
+            TrashCommand::List => {
+                out.trash(&store.trash()?, SystemTime::now())?;
+            }
+            TrashCommand::Restore { name, to } => {
+                let to = to.as_deref().map(paths::note_path);
+                let path = store.restore(name, to.as_deref())?;
+                out.written(&Written {
+                    action: Action::Restored,
+                    revision: Some(store.get(&path)?.revision),
+                    path,
+                    from: None,
+                })?;
+            }
+            TrashCommand::Empty { older_than } => {
+                let removed = store.empty_trash(*older_than)?;
+                out.value(&serde_json::json!({ "removed": removed }), |out| {
+                    writeln!(out, "Removed {removed} notes from the trash")
+                })?;
+            }
+        },
+        Commands::History { name } => {
+            out.history(&store.history(&paths::note_path(name))?, SystemTime::now())?;
         }
-        Commands::Move { from, to } => {
+        Commands::Log { name } => {
+            let path = name.as_deref().map(paths::note_path);
+            out.commits(&store.git_log(path.as_deref())?, SystemTime::now())?;
+        }
+        Commands::Diff { name, revision } => {
+            let path = paths::note_path(name);
+            let diff = store.diff(&path, revision)?;
+            out.value(&serde_json::json!({ "path": path, "revision": revision, "diff": diff }), |out| {
+                write!(out, "{diff}")
+            })?;
+        }
+        Commands::Revert { name, revision, if_match } => {
+            let path = paths::note_path(name);
+            store.revert(&path, revision, if_match.as_deref())?;
+            out.written(&Written {
+                action: Action::Reverted,
+                revision: Some(store.get(&path)?.revision),
+                path,
+                from: None,
+            })?;
+        }
+        Commands::Move { from, to, if_match, rewrite_links, dry_run } => {
              let mut from_filename = from.clone();
             if !from_filename.ends_with(".md") {
                 from_filename.push_str(".md");
@@ -130,31 +362,93 @@ fn main() -> Result<()> {
                 to_filename.push_str(".md");
             }
             
-            store.move_note(&PathBuf::from(&from_filename), &PathBuf::from(&to_filename))?;
-            println!("Moved {} to {}", from_filename, to_filename);
+            let (from_path, to_path) = (PathBuf::from(&from_filename), PathBuf::from(&to_filename));
+            let options = MoveOptions { rewrite_links: *rewrite_links, dry_run: *dry_run };
+            let rewrites = store.move_note_with(&from_path, &to_path, if_match.as_deref(), &options)?;
+            if *dry_run {
+                out.rewrites(&rewrites)?;
+                return Ok(());
+            }
+            let written = Written {
+                action: Action::Moved,
+                revision: Some(store.get(&to_path)?.revision),
+                path: to_path,
+                from: Some(from_path),
+            };
+            out.moved(&written, &rewrites)?;
         }
-        Commands::Sync => {
-            store.sync()?;
-            println!("Database synced.");
+        Commands::Sync { rebuild } => {
+            let report = if *rebuild {
+                drop(store);
+                let (_, report) = Store::rebuild(settings.notes_dir.clone(), settings.db_path.clone())
+                    .context("Failed to rebuild index")?;
+                report
+            } else {
+                store.sync()?
+            };
+            out.synced(&report)?;
+        }
+        Commands::Watch { debounce } => {
+            let report = store.sync()?;
+            out.watch_started(&report, &settings.notes_dir)?;
+
+            let options = WatchOptions { debounce: Duration::from_millis(*debounce) };
+            let stop = AtomicBool::new(false);
+            watcher::run(&mut store, &options, &stop, |changes, report| {
+                // A closed stdout is not worth stopping the watcher for.
+                let _ = out.watch_changed(changes, report);
+            })?;
+        }
+        Commands::Mcp => {
+            mcp::serve(&mut store, io::stdin().lock(), io::stdout())?;
+        }
+        Commands::Serve { bind } => {
+            let token = match &settings.api_token {
+                Some(token) => token.clone(),
+                None => {
+                    let token = generate_token()?;
+                    let settings = Settings { api_token: Some(token.clone()), ..settings.clone() };
+                    settings.save().context("Failed to save the generated API token")?;
+                    eprintln!("Generated an API token and saved it to {}", Settings::get_path()?.display());
+                    token
+                }
+            };
+            let url = format!("http://{bind}");
+            out.value(&serde_json::json!({ "url": url }), |out| writeln!(out, "Serving notes on {url} (Ctrl-C to stop)"))?;
+            out.flush()?;
+            serve::serve(&mut store, bind, &token)?;
         }
         Commands::Init => {
             let path = Settings::get_path()?;
-            if path.exists() {
-                println!("Configuration already exists at: {:?}", path);
-            } else {
+            let created = !path.exists();
+            let settings = if created {
                 let settings = Settings::default();
                 settings.save()?;
-                println!("Initialized configuration at: {:?}", path);
-                println!("Notes directory set to: {:?}", settings.notes_dir);
-            }
+                settings
+            } else {
+                settings
+            };
+            let result = serde_json::json!({ "config_path": path, "created": created, "notes_dir": settings.notes_dir });
+            out.value(&result, |out| {
+                if created {
+                    writeln!(out, "Initialized configuration at: {:?}", path)?;
+                    writeln!(out, "Notes directory set to: {:?}", settings.notes_dir)
+                } else {
+                    writeln!(out, "Configuration already exists at: {:?}", path)
+                }
+            })?;
         }
         Commands::CheckConfig => {
-            println!("Config loaded from default location.");
-            println!("Notes Dir: {}", settings.notes_dir.display());
-            println!("DB Path: {}", settings.db_path.display());
+            let result = serde_json::json!({ "notes_dir": settings.notes_dir, "db_path": settings.db_path });
+            out.value(&result, |out| {
+                writeln!(out, "Config loaded from default location.")?;
+                writeln!(out, "Notes Dir: {}", settings.notes_dir.display())?;
+                writeln!(out, "DB Path: {}", settings.db_path.display())
+            })?;
         }
         Commands::Version => {
-            println!("extt-cli {}", env!("CARGO_PKG_VERSION"));
+            let version = env!("CARGO_PKG_VERSION");
+            out.value(&serde_json::json!({ "version": version }), |out| writeln!(out, "extt-cli {}", version))?;
         }
         Commands::Upgrade => {
             let target = get_target()?;
@@ -163,18 +457,26 @@ fn main() -> Result<()> {
                 .repo_name("extt")
                 .bin_name("extt")
                 .target(&target)
-                .show_download_progress(true)
+                .show_download_progress(out.is_text())
                 .no_confirm(true)
                 .current_version(env!("CARGO_PKG_VERSION"))
                 .build()?
                 .update()?;
-            println!("Update status: `{}`!", status.version());
+            let result = serde_json::json!({ "version": status.version(), "updated": status.updated() });
+            out.value(&result, |out| writeln!(out, "Update status: `{}`!", status.version()))?;
         }
     }
 
     Ok(())
 }
 
+/// 256 random bits, hex-encoded.
+fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow::anyhow!("Failed to generate API token: {e}"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
 fn get_target() -> Result<String> {
     let os = std::env::consts::OS;
     let arch = std::env::consts::ARCH;
@@ -186,3 +488,28 @@ fn get_target() -> Result<String> {
         _ => anyhow::bail!("Unsupported OS/Arch: {}/{}", os, arch),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_error_kinds() {
+        let not_found = Err::<(), _>(extt_core::Error::NotFound("a.md".into()))
+            .context("Failed to read note")
+            .unwrap_err();
+        assert_eq!(error_kind(&not_found), (3, "not_found"));
+        assert_eq!(error_kind(&anyhow::Error::from(extt_core::Error::DatabaseLocked)), (9, "database_locked"));
+        assert_eq!(error_kind(&anyhow::anyhow!("something else")), (1, "other"));
+    }
+
+    #[test]
+    fn test_requested_format() {
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(requested_format(args(&["extt", "--format", "json", "bogus"])), Format::Json);
+        assert_eq!(requested_format(args(&["extt", "ls", "--format=JSONL"])), Format::Jsonl);
+        assert_eq!(requested_format(args(&["extt", "--format", "yaml"])), Format::Text);
+        assert_eq!(requested_format(args(&["extt", "--format"])), Format::Text);
+    }
+}
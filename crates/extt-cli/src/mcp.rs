@@ -0,0 +1,637 @@
+//! `extt mcp`: a Model Context Protocol server over stdio.
+//!
+//! Messages are newline-delimited JSON-RPC 2.0. Store operations are exposed
+//! as tools, and every note is a resource at `extt:///<relative path>`.
+//! Tool results carry the same JSON shapes as `--format json` (see
+//! `docs/json-output.md`).
+
+use crate::changes::{NoteChanges, Rejected};
+use crate::output::{Action, Written};
+use crate::paths::{decode_path, encode_path, note_path};
+use extt_core::{Store, timestamps};
+use extt_core::types::{ListQuery, Metadata, MoveOptions, SortKey};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Protocol revisions this server speaks, newest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const URI_PREFIX: &str = "extt:///";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// MCP's code for a resource URI that does not resolve to a note.
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+/// Serves requests from `input` until it is closed.
+pub fn serve(store: &mut Store, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(store, message),
+            Err(err) => Some(failure(Value::Null, PARSE_ERROR, &err.to_string())),
+        };
+        if let Some(response) = response {
+            serde_json::to_writer(&mut output, &response)?;
+            writeln!(output)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles one message, returning the response for requests and `None` for
+/// notifications.
+fn handle(store: &mut Store, message: Value) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        return Some(failure(id.unwrap_or(Value::Null), INVALID_REQUEST, "Expected a JSON-RPC request"));
+    };
+    let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+
+    let result = match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => call_tool(store, params),
+        "resources/list" => list_resources(store),
+        "resources/read" => read_resource(store, params),
+        _ if method.starts_with("notifications/") => return None,
+        _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {method}"))),
+    };
+    // Notifications never get a response, even when they fail.
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => failure(id, code, &message),
+    })
+}
+
+fn failure(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| Some(**v) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {}, "resources": {} },
+        "serverInfo": { "name": "extt", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn tools() -> Value {
+    let path = json!({ "type": "string", "description": "Note path relative to the notes folder, e.g. `inbox/idea.md`" });
+    let expected = json!({
+        "type": "string",
+        "description": "Fail with a conflict unless the note is still at this revision (from `get`)",
+    });
+    let tag = json!({ "type": "string", "description": "Only notes with this tag or one of its children, e.g. `project`" });
+    let sort = json!({
+        "type": "string",
+        "enum": ["path", "title", "created", "updated"],
+        "description": "Sort order; `created` and `updated` put the newest first",
+    });
+    let since = json!({
+        "type": "string",
+        "description": "Only notes updated (created, when sorting by `created`) at or after this RFC 3339 time or date",
+    });
+    let until = json!({ "type": "string", "description": "Only notes updated (or created) before this RFC 3339 time or date" });
+    json!([
+        {
+            "name": "list",
+            "description": "List all notes with their titles.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "tag": tag, "sort": sort, "since": since, "until": until },
+            },
+        },
+        {
+            "name": "search",
+            "description": "Full-text search over note paths, titles and bodies, best matches first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "query": { "type": "string" }, "tag": tag, "sort": sort, "since": since, "until": until },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "tags",
+            "description": "List tags with the number of notes using each. Parent tags count their children's notes.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "get",
+            "description": "Read a note: frontmatter metadata, body and revision.",
+            "inputSchema": { "type": "object", "properties": { "path": path }, "required": ["path"] },
+        },
+        {
+            "name": "create",
+            "description": "Create a new note.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": path,
+                    "content": { "type": "string", "description": "Body in markdown" },
+                    "title": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "update",
+            "description": "Edit a note in place. Frontmatter text outside the edited keys is kept as is.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": path,
+                    "content": { "type": "string", "description": "Replaces the whole body" },
+                    "title": { "type": "string" },
+                    "body_edit": body_edit_schema(),
+                    "metadata": {
+                        "type": "array",
+                        "description": "Frontmatter edits applied in order. Keys are dot-separated paths.",
+                        "items": metadata_edit_schema(),
+                    },
+                    "expected_revision": expected,
+                },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "move",
+            "description": "Move or rename a note.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "from": path,
+                    "to": path,
+                    "expected_revision": expected,
+                    "rewrite_links": {
+                        "type": "boolean",
+                        "description": "Rewrite links in other notes (and relative links in this one) so they keep resolving",
+                    },
+                    "dry_run": { "type": "boolean", "description": "Only report the link rewrites; move nothing" },
+                },
+                "required": ["from", "to"],
+            },
+        },
+        {
+            "name": "delete",
+            "description": "Move a note to the trash, from where a user can restore it.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "path": path, "expected_revision": expected },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "sync",
+            "description": "Re-index notes that changed on disk.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+    ])
+}
+
+fn body_edit_schema() -> Value {
+    let line = json!({ "type": "integer", "minimum": 1, "description": "1-based line of the body" });
+    json!({
+        "description": "Partial body edit. Line numbers are inclusive and count from the first body line.",
+        "oneOf": [
+            {
+                "type": "object",
+                "properties": {
+                    "op": { "const": "replace" }, "from": line, "to": line, "content": { "type": "string" },
+                },
+                "required": ["op", "from", "to", "content"],
+            },
+            {
+                "type": "object",
+                "properties": { "op": { "const": "insert" }, "at": line, "content": { "type": "string" } },
+                "required": ["op", "at", "content"],
+            },
+            {
+                "type": "object",
+                "properties": { "op": { "const": "delete" }, "from": line, "to": line },
+                "required": ["op", "from", "to"],
+            },
+            {
+                "type": "object",
+                "properties": {
+                    "op": { "const": "replace_section" },
+                    "heading": { "type": "string", "description": "Heading text, optionally with its `#` markers" },
+                    "content": { "type": "string" },
+                },
+                "required": ["op", "heading", "content"],
+            },
+        ],
+    })
+}
+
+fn metadata_edit_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "op": { "enum": ["set", "unset", "append", "remove"] },
+            "key": { "type": "string", "description": "Dot-separated key path, e.g. `metadata.status`" },
+            "value": { "description": "Required for set, append and remove" },
+        },
+        "required": ["op", "key"],
+    })
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    query: String,
+    #[serde(flatten)]
+    filter: ListArgs,
+}
+
+#[derive(Deserialize)]
+struct ListArgs {
+    tag: Option<String>,
+    sort: Option<SortKey>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+impl ListArgs {
+    fn query(self) -> Result<ListQuery, (i64, String)> {
+        let time = |name: &str, value: Option<String>| match value {
+            None => Ok(None),
+            Some(value) => timestamps::parse(&value)
+                .map(Some)
+                .ok_or_else(|| (INVALID_PARAMS, format!("`{name}` must be an RFC 3339 time or a date, got `{value}`"))),
+        };
+        Ok(ListQuery {
+            tag: self.tag,
+            sort: self.sort,
+            since: time("since", self.since)?,
+            until: time("until", self.until)?,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PathArgs {
+    path: String,
+    expected_revision: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CreateArgs {
+    path: String,
+    #[serde(default)]
+    content: String,
+    title: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct UpdateArgs {
+    path: String,
+    #[serde(flatten)]
+    changes: NoteChanges,
+}
+
+#[derive(Deserialize)]
+struct MoveArgs {
+    from: String,
+    to: String,
+    expected_revision: Option<String>,
+    #[serde(flatten)]
+    options: MoveOptions,
+}
+
+fn call_tool(store: &mut Store, params: Value) -> Result<Value, (i64, String)> {
+    let call: ToolCall = arguments(params)?;
+    let args = if call.arguments.is_null() { json!({}) } else { call.arguments };
+
+    let result = match call.name.as_str() {
+        "list" => {
+            let args: ListArgs = arguments(args)?;
+            store.query(&args.query()?).map(|page| json!(page.items))
+        }
+        "search" => {
+            let args: SearchArgs = arguments(args)?;
+            let filter = args.filter.query()?;
+            store.search_with(&args.query, &filter).map(|page| json!(page.items))
+        }
+        "tags" => store.tags().map(|tags| json!(tags)),
+        "get" => {
+            let args: PathArgs = arguments(args)?;
+            store.get(&note_path(&args.path)).map(|note| json!(note))
+        }
+        "create" => {
+            let args: CreateArgs = arguments(args)?;
+            let path = note_path(&args.path);
+            let title = args.title.or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()));
+            let metadata = Metadata { title, tags: args.tags, ..Default::default() };
+            store
+                .create(&path, &args.content, Some(metadata))
+                .and_then(|()| written(store, Action::Created, path, None))
+        }
+        "update" => {
+            let args: UpdateArgs = arguments(args)?;
+            let path = note_path(&args.path);
+            match args.changes.apply(store, &path) {
+                Err(Rejected::Invalid(message)) => return Err((INVALID_PARAMS, message)),
+                Err(Rejected::Store(err)) => Err(err),
+                Ok(_) => written(store, Action::Updated, path, None),
+            }
+        }
+        "move" => {
+            let args: MoveArgs = arguments(args)?;
+            let (from, to) = (note_path(&args.from), note_path(&args.to));
+            match store.move_note_with(&from, &to, args.expected_revision.as_deref(), &args.options) {
+                Ok(rewrites) if args.options.dry_run => Ok(json!({ "rewrites": rewrites })),
+                Ok(rewrites) => written(store, Action::Moved, to, Some(from)).map(|mut value| {
+                    if !rewrites.is_empty() {
+                        value["rewrites"] = json!(rewrites);
+                    }
+                    value
+                }),
+                Err(err) => Err(err),
+            }
+        }
+        "delete" => {
+            let args: PathArgs = arguments(args)?;
+            let path = note_path(&args.path);
+            store.delete(&path, args.expected_revision.as_deref()).map(|()| {
+                json!(Written { action: Action::Trashed, path, from: None, revision: None })
+            })
+        }
+        "sync" => store.sync().map(|report| json!(report)),
+        name => return Err((INVALID_PARAMS, format!("Unknown tool: {name}"))),
+    };
+
+    // Store failures are tool errors, which the model gets to see and react
+    // to, rather than protocol errors.
+    Ok(match result {
+        Ok(value) => json!({
+            "content": [{ "type": "text", "text": serde_json::to_string_pretty(&value).unwrap_or_default() }],
+            "structuredContent": if value.is_object() { value } else { json!({ "items": value }) },
+            "isError": false,
+        }),
+        Err(err) => {
+            let err = anyhow::Error::from(err);
+            let (exit_code, code) = crate::error_kind(&err);
+            let error = json!({ "error": { "code": code, "exit_code": exit_code, "message": format!("{err:#}") } });
+            json!({
+                "content": [{ "type": "text", "text": error.to_string() }],
+                "structuredContent": error,
+                "isError": true,
+            })
+        }
+    })
+}
+
+fn written(store: &Store, action: Action, path: PathBuf, from: Option<PathBuf>) -> extt_core::Result<Value> {
+    let revision = store.get(&path)?.revision;
+    Ok(json!(Written { action, path, from, revision: Some(revision) }))
+}
+
+fn list_resources(store: &Store) -> Result<Value, (i64, String)> {
+    let notes = store.list().map_err(internal)?;
+    let resources: Vec<Value> = notes
+        .iter()
+        .map(|note| {
+            let path = note.path.to_string_lossy();
+            json!({
+                "uri": format!("{URI_PREFIX}{}", encode_path(&path)),
+                "name": path,
+                "title": note.title,
+                "mimeType": "text/markdown",
+            })
+        })
+        .collect();
+    Ok(json!({ "resources": resources }))
+}
+
+fn read_resource(store: &Store, params: Value) -> Result<Value, (i64, String)> {
+    #[derive(Deserialize)]
+    struct ReadArgs {
+        uri: String,
+    }
+    let args: ReadArgs = arguments(params)?;
+    let path = args
+        .uri
+        .strip_prefix(URI_PREFIX)
+        .and_then(decode_path)
+        .ok_or_else(|| (INVALID_PARAMS, format!("Not an extt note URI: {}", args.uri)))?;
+    let text = store.read_raw(Path::new(&path)).map_err(|err| match err {
+        extt_core::Error::NotFound(_) => (RESOURCE_NOT_FOUND, err.to_string()),
+        err => internal(err),
+    })?;
+    Ok(json!({ "contents": [{ "uri": args.uri, "mimeType": "text/markdown", "text": text }] }))
+}
+
+fn arguments<T: DeserializeOwned>(value: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(value).map_err(|err| (INVALID_PARAMS, err.to_string()))
+}
+
+fn internal(err: extt_core::Error) -> (i64, String) {
+    (-32603, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    /// Runs a scripted session and returns the responses, one per line.
+    fn session(store: &mut Store, requests: &[Value]) -> Vec<Value> {
+        let input: String = requests.iter().map(|r| format!("{r}\n")).collect();
+        let mut output = Vec::new();
+        serve(store, input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    fn call(id: i64, name: &str, arguments: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": { "name": name, "arguments": arguments } })
+    }
+
+    fn store() -> (tempfile::TempDir, Store) {
+        let dir = tempdir().unwrap();
+        let notes = dir.path().join("notes");
+        fs::create_dir(&notes).unwrap();
+        fs::write(notes.join("groceries.md"), "---\ntitle: Groceries\n---\nmilk and eggs\n").unwrap();
+        let mut store = Store::new(notes, dir.path().join("index.db")).unwrap();
+        store.sync().unwrap();
+        (dir, store)
+    }
+
+    #[test]
+    fn test_handshake_and_tool_list() {
+        let (_dir, mut store) = store();
+        let responses = session(&mut store, &[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                "protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": { "name": "test", "version": "0" },
+            }}),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "ping" }),
+        ]);
+
+        assert_eq!(responses.len(), 3, "notifications get no response");
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "extt");
+        let names: Vec<&str> = responses[1]["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["list", "search", "tags", "get", "create", "update", "move", "delete", "sync"]);
+        assert!(responses[1]["result"]["tools"].as_array().unwrap().iter().all(|t| t["inputSchema"]["type"] == "object"));
+        assert_eq!(responses[2], json!({ "jsonrpc": "2.0", "id": 3, "result": {} }));
+    }
+
+    #[test]
+    fn test_move_rewrites_links() {
+        let (dir, mut store) = store();
+        let responses = session(&mut store, &[
+            call(1, "create", json!({ "path": "list", "content": "Buy [[groceries]]\n" })),
+            call(2, "move", json!({ "from": "groceries", "to": "shop/food", "rewrite_links": true, "dry_run": true })),
+            call(3, "move", json!({ "from": "groceries", "to": "shop/food", "rewrite_links": true })),
+        ]);
+        let result = |i: usize| responses[i]["result"]["structuredContent"].clone();
+
+        assert_eq!(result(1)["rewrites"][0]["after"], "Buy [[food]]");
+        assert_eq!(result(2)["action"], "moved");
+        assert_eq!(result(2)["rewrites"], result(1)["rewrites"]);
+        let file = fs::read_to_string(dir.path().join("notes/list.md")).unwrap();
+        assert!(file.ends_with("Buy [[food]]\n"), "{file}");
+    }
+
+    #[test]
+    fn test_tools_round_trip() {
+        let (dir, mut store) = store();
+        let responses = session(&mut store, &[
+            call(1, "create", json!({ "path": "plans/q3", "content": "## Tasks\n- draft\n", "tags": ["work"] })),
+            call(2, "search", json!({ "query": "draft" })),
+            call(3, "update", json!({
+                "path": "plans/q3.md",
+                "body_edit": { "op": "replace_section", "heading": "Tasks", "content": "- ship" },
+                "metadata": [{ "op": "set", "key": "status", "value": "active" }],
+            })),
+            call(4, "get", json!({ "path": "plans/q3.md" })),
+            call(5, "move", json!({ "from": "plans/q3.md", "to": "q3.md" })),
+            call(6, "delete", json!({ "path": "groceries.md" })),
+            call(7, "list", json!({})),
+            call(8, "list", json!({ "tag": "work" })),
+            call(9, "tags", json!({})),
+            call(10, "list", json!({ "sort": "created", "until": "2000-01-01" })),
+            call(11, "search", json!({ "query": "ship", "since": "soon" })),
+        ]);
+        let result = |i: usize| {
+            assert_eq!(responses[i]["result"]["isError"], false, "{}", responses[i]);
+            responses[i]["result"]["structuredContent"].clone()
+        };
+
+        assert_eq!(result(0)["action"], "created");
+        assert_eq!(result(0)["path"], "plans/q3.md");
+        assert_eq!(result(1)["items"][0]["path"], "plans/q3.md");
+        assert_eq!(result(2)["action"], "updated");
+        let note = result(3);
+        assert!(note["content"].as_str().unwrap().starts_with("## Tasks\n- ship"));
+        assert_eq!(note["metadata"]["status"], "active");
+        assert_eq!(note["revision"], result(2)["revision"]);
+        assert_eq!(result(4)["from"], "plans/q3.md");
+        assert_eq!(result(5), json!({ "action": "trashed", "path": "groceries.md" }));
+        assert_eq!(result(6)["items"].as_array().unwrap().len(), 1);
+        assert_eq!(result(6)["items"][0]["title"], "q3");
+
+        // The text content mirrors the structured result for older clients.
+        let text: Value = serde_json::from_str(responses[6]["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(text, result(6)["items"]);
+        assert_eq!(result(7)["items"], result(6)["items"]);
+        assert_eq!(result(8)["items"], json!([{ "tag": "work", "count": 1 }]));
+        assert_eq!(result(9)["items"], json!([]));
+        assert_eq!(responses[10]["error"]["code"], INVALID_PARAMS);
+
+        let file = fs::read_to_string(dir.path().join("notes/q3.md")).unwrap();
+        assert!(file.ends_with("---\n## Tasks\n- ship\n"), "{file}");
+    }
+
+    #[test]
+    fn test_store_errors_are_tool_errors() {
+        let (_dir, mut store) = store();
+        let responses = session(&mut store, &[
+            call(1, "get", json!({ "path": "missing.md" })),
+            call(2, "update", json!({ "path": "groceries.md", "content": "x", "expected_revision": "stale" })),
+            call(3, "get", json!({ "path": "../outside.md" })),
+            call(4, "update", json!({ "path": "groceries.md", "expected_revision": "stale" })),
+        ]);
+
+        let error = |i: usize| {
+            assert_eq!(responses[i]["result"]["isError"], true);
+            responses[i]["result"]["structuredContent"]["error"]["code"].clone()
+        };
+        assert_eq!(error(0), "not_found");
+        assert_eq!(error(1), "conflict");
+        assert_eq!(error(2), "path_traversal");
+        assert_eq!(error(3), "conflict");
+        assert_eq!(store.read_raw(Path::new("groceries.md")).unwrap(), "---\ntitle: Groceries\n---\nmilk and eggs\n");
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let (_dir, mut store) = store();
+        let input = "not json\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"nope\"}\n".to_string()
+            + &call(2, "get", json!({})).to_string()
+            + "\n"
+            + &call(3, "explode", json!({})).to_string()
+            + "\n";
+        let mut output = Vec::new();
+        serve(&mut store, input.as_bytes(), &mut output).unwrap();
+        let codes: Vec<i64> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["error"]["code"].as_i64().unwrap())
+            .collect();
+        assert_eq!(codes, [PARSE_ERROR, METHOD_NOT_FOUND, INVALID_PARAMS, INVALID_PARAMS]);
+    }
+
+    #[test]
+    fn test_resources() {
+        let (dir, mut store) = store();
+        fs::write(dir.path().join("notes/to do.md"), "- [ ] call\n").unwrap();
+        store.sync().unwrap();
+
+        let responses = session(&mut store, &[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "resources/list" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "resources/read", "params": { "uri": "extt:///to%20do.md" } }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "resources/read", "params": { "uri": "extt:///gone.md" } }),
+        ]);
+
+        let resources = responses[0]["result"]["resources"].as_array().unwrap();
+        let uris: Vec<&str> = resources.iter().map(|r| r["uri"].as_str().unwrap()).collect();
+        assert_eq!(uris, ["extt:///groceries.md", "extt:///to%20do.md"]);
+        assert_eq!(resources[0]["title"], "Groceries");
+        assert_eq!(responses[1]["result"]["contents"][0]["text"], "- [ ] call\n");
+        assert_eq!(responses[2]["error"]["code"], RESOURCE_NOT_FOUND);
+    }
+}
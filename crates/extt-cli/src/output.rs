@@ -0,0 +1,588 @@
+//! Rendering of command results as text or JSON.
+//!
+//! The JSON shapes are a public interface, documented in
+//! `docs/json-output.md`. Add fields freely, but never rename or remove one.
+
+use crate::cli::Format;
+use extt_core::types::{
+    Diagnostic, GitCommit, HistoryEntry, Link, LinkKind, LinkRewrite, Note, NoteSummary, Problem, SyncReport, TagCount, TrashEntry,
+};
+use extt_core::watcher::Change;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Output<W> {
+    format: Format,
+    out: W,
+}
+
+/// What a write command did to a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Created,
+    Updated,
+    Moved,
+    /// Moved to the trash.
+    Trashed,
+    Restored,
+    Reverted,
+    Deleted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Written {
+    pub action: Action,
+    /// Path of the note after the write.
+    pub path: PathBuf,
+    /// Previous path, for moves and renames.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+    /// Revision after the write; absent for deletes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+/// A problem `doctor` found, and whether `--fix` repaired it.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    #[serde(flatten)]
+    pub problem: Problem,
+    pub fixed: bool,
+}
+
+/// `move` result: the write plus the lines whose links were rewritten.
+#[derive(Serialize)]
+struct Moved<'a> {
+    #[serde(flatten)]
+    written: &'a Written,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    rewrites: &'a [LinkRewrite],
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum WatchEvent<'a> {
+    Synced { report: &'a SyncReport },
+    Changed { changes: Vec<ChangeView<'a>>, report: &'a SyncReport },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ChangeView<'a> {
+    Upsert { path: &'a Path },
+    Remove { path: &'a Path },
+    Rename { from: &'a Path, to: &'a Path },
+}
+
+impl<'a> From<&'a Change> for ChangeView<'a> {
+    fn from(change: &'a Change) -> Self {
+        match change {
+            Change::Upsert(path) => ChangeView::Upsert { path },
+            Change::Remove(path) => ChangeView::Remove { path },
+            Change::Rename { from, to } => ChangeView::Rename { from, to },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorView<'a> {
+    error: ErrorBody<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    exit_code: u8,
+    message: &'a str,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(format: Format, out: W) -> Self {
+        Self { format, out }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    pub fn list(&mut self, notes: &[NoteSummary]) -> io::Result<()> {
+        self.items(notes, |out, note| writeln!(out, "{}: {}", note.path.display(), note.title.as_deref().unwrap_or("No Title")))
+    }
+
+    pub fn search(&mut self, notes: &[NoteSummary]) -> io::Result<()> {
+        self.items(notes, |out, note| {
+            writeln!(out, "{}: {}", note.path.display(), note.title.as_deref().unwrap_or("No Title"))?;
+            if let Some(snippet) = &note.snippet {
+                writeln!(out, "    {}", snippet)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn tags(&mut self, tags: &[TagCount]) -> io::Result<()> {
+        self.items(tags, |out, tag| writeln!(out, "{}\t{}", tag.tag, tag.count))
+    }
+
+    pub fn links(&mut self, links: &[Link]) -> io::Result<()> {
+        self.items(links, |out, link| {
+            let written = match link.kind {
+                LinkKind::Wiki => format!("[[{}]]", link.target),
+                LinkKind::Markdown => format!("({})", link.target),
+            };
+            match &link.resolved {
+                Some(target) => writeln!(out, "{}:{}: {} -> {}", link.source.display(), link.line, written, target.display()),
+                None => writeln!(out, "{}:{}: {} (unresolved)", link.source.display(), link.line, written),
+            }
+        })
+    }
+
+    pub fn findings(&mut self, findings: &[Finding]) -> io::Result<()> {
+        self.items(findings, |out, finding| {
+            match &finding.problem {
+                Problem::Unindexed { path } => write!(out, "{}: not in the index", path.display())?,
+                Problem::Outdated { path } => write!(out, "{}: changed since it was indexed", path.display())?,
+                Problem::Stale { path } => write!(out, "{}: in the index but not on disk", path.display())?,
+                Problem::InvalidUtf8 { path } => write!(out, "{}: not valid UTF-8, so not indexed", path.display())?,
+                Problem::InvalidFrontmatter { path, diagnostic: Diagnostic { message, line, column } } => match (line, column) {
+                    (Some(line), Some(column)) => write!(out, "{}:{}:{}: {}", path.display(), line, column, message)?,
+                    (Some(line), None) => write!(out, "{}:{}: {}", path.display(), line, message)?,
+                    _ => write!(out, "{}: {}", path.display(), message)?,
+                },
+                Problem::BrokenLink { source, line, target } => {
+                    write!(out, "{}:{}: link to `{}` points at no note", source.display(), line, target)?
+                }
+                Problem::AmbiguousName { name, paths } => write!(out, "[[{}]] could mean {}", name, list(paths))?,
+                Problem::CaseCollision { paths } => write!(out, "paths differ only in case: {}", list(paths))?,
+                Problem::OutsideRoot { path, target } => {
+                    write!(out, "{}: symlink to notes outside the notes folder ({})", path.display(), target.display())?
+                }
+            }
+            if finding.fixed {
+                write!(out, " (fixed)")?;
+            }
+            writeln!(out)
+        })
+    }
+
+    pub fn note(&mut self, note: &Note) -> io::Result<()> {
+        self.value(note, |out| {
+            for line in note.content.lines() {
+                writeln!(out, "{}", line)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn written(&mut self, written: &Written) -> io::Result<()> {
+        self.value(written, |out| {
+            let path = written.path.display();
+            match (written.action, &written.from) {
+                (Action::Created, _) => writeln!(out, "Created note: {}", path),
+                (Action::Updated, None) => writeln!(out, "Updated note: {}", path),
+                (Action::Updated, Some(from)) => {
+                    writeln!(out, "Updated note: {}", from.display())?;
+                    writeln!(out, "Renamed {} to {}", from.display(), path)
+                }
+                (Action::Moved, Some(from)) => writeln!(out, "Moved {} to {}", from.display(), path),
+                (Action::Moved, None) => writeln!(out, "Moved note: {}", path),
+                (Action::Trashed, _) => writeln!(out, "Moved note to trash: {}", path),
+                (Action::Restored, _) => writeln!(out, "Restored note: {}", path),
+                (Action::Reverted, _) => writeln!(out, "Reverted note: {}", path),
+                (Action::Deleted, _) => writeln!(out, "Deleted note: {}", path),
+            }
+        })
+    }
+
+    pub fn moved(&mut self, written: &Written, rewrites: &[LinkRewrite]) -> io::Result<()> {
+        if self.format != Format::Text {
+            return self.value(&Moved { written, rewrites }, |_| Ok(()));
+        }
+        self.written(written)?;
+        if !rewrites.is_empty() {
+            let mut notes: Vec<String> = rewrites.iter().map(|r| r.path.display().to_string()).collect();
+            notes.dedup();
+            writeln!(self.out, "Rewrote links in {}", notes.join(", "))?;
+        }
+        Ok(())
+    }
+
+    /// Link rewrites a `move --dry-run` would make.
+    pub fn rewrites(&mut self, rewrites: &[LinkRewrite]) -> io::Result<()> {
+        self.items(rewrites, |out, rewrite| {
+            writeln!(out, "{}:{}", rewrite.path.display(), rewrite.line)?;
+            writeln!(out, "  - {}", rewrite.before)?;
+            writeln!(out, "  + {}", rewrite.after)
+        })
+    }
+
+    /// Trash listing; text output shows how long ago each note was deleted.
+    pub fn trash(&mut self, entries: &[TrashEntry], now: SystemTime) -> io::Result<()> {
+        let now = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        self.items(entries, |out, entry| {
+            writeln!(out, "{}  {}  ({} ago)", entry.id, entry.path.display(), age(now - entry.deleted_at))
+        })
+    }
+
+    /// Versions from `extt history`; text output shows when each was saved
+    /// and where the note was if it has moved since.
+    pub fn history(&mut self, entries: &[HistoryEntry], now: SystemTime) -> io::Result<()> {
+        let now = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        let current = entries.first().map(|e| e.path.clone());
+        self.items(entries, |out, entry| {
+            write!(out, "{}  {:>5} bytes  ({} ago)", &entry.revision[..12], entry.size, age(now - entry.saved_at))?;
+            if Some(&entry.path) != current.as_ref() {
+                write!(out, "  {}", entry.path.display())?;
+            }
+            writeln!(out)
+        })
+    }
+
+    /// Commits from `extt log`.
+    pub fn commits(&mut self, commits: &[GitCommit], now: SystemTime) -> io::Result<()> {
+        let now = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        self.items(commits, |out, commit| {
+            writeln!(out, "{}  {}  ({}, {} ago)", &commit.id[..8], commit.summary, commit.author, age(now - commit.time))
+        })
+    }
+
+    pub fn synced(&mut self, report: &SyncReport) -> io::Result<()> {
+        self.value(report, |out| {
+            writeln!(
+                out,
+                "Database synced: {} added, {} updated, {} removed, {} unchanged.",
+                report.added, report.updated, report.removed, report.unchanged
+            )?;
+            if report.skipped > 0 {
+                writeln!(out, "Skipped {} note(s) that are not valid UTF-8; see `extt doctor`.", report.skipped)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// First event of `extt watch`. Watch output is a stream, so JSON
+    /// formats always print one compact event per line.
+    pub fn watch_started(&mut self, report: &SyncReport, root: &Path) -> io::Result<()> {
+        if self.format == Format::Text {
+            self.synced(report)?;
+            writeln!(self.out, "Watching {} (Ctrl-C to stop)", root.display())?;
+        } else {
+            self.line(&WatchEvent::Synced { report })?;
+        }
+        self.out.flush()
+    }
+
+    pub fn watch_changed(&mut self, changes: &[Change], report: &SyncReport) -> io::Result<()> {
+        if self.format == Format::Text {
+            for change in changes {
+                match change {
+                    Change::Upsert(path) => writeln!(self.out, "changed {}", path.display())?,
+                    Change::Remove(path) => writeln!(self.out, "removed {}", path.display())?,
+                    Change::Rename { from, to } => writeln!(self.out, "moved {} -> {}", from.display(), to.display())?,
+                }
+            }
+            writeln!(
+                self.out,
+                "Index updated: {} added, {} updated, {} removed.",
+                report.added, report.updated, report.removed
+            )?;
+            if report.skipped > 0 {
+                writeln!(self.out, "Skipped {} change(s) that could not be indexed.", report.skipped)?;
+            }
+        } else {
+            let changes = changes.iter().map(ChangeView::from).collect();
+            self.line(&WatchEvent::Changed { changes, report })?;
+        }
+        self.out.flush()
+    }
+
+    /// Prints an error. `code` is a stable snake_case name for the kind of
+    /// failure, matching `exit_code`.
+    pub fn error(&mut self, code: &str, exit_code: u8, message: &str) -> io::Result<()> {
+        if self.format == Format::Text {
+            return writeln!(self.out, "Error: {}", message);
+        }
+        self.line(&ErrorView { error: ErrorBody { code, exit_code, message } })
+    }
+
+    /// Prints a single result: pretty JSON, one JSON line, or `text`.
+    pub fn value<T: Serialize>(&mut self, value: &T, text: impl FnOnce(&mut W) -> io::Result<()>) -> io::Result<()> {
+        match self.format {
+            Format::Text => text(&mut self.out),
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut self.out, value)?;
+                writeln!(self.out)
+            }
+            Format::Jsonl => self.line(value),
+        }
+    }
+
+    /// Prints a collection: a JSON array, one JSON line per item, or `text`
+    /// per item.
+    fn items<T: Serialize>(&mut self, items: &[T], mut text: impl FnMut(&mut W, &T) -> io::Result<()>) -> io::Result<()> {
+        match self.format {
+            Format::Text => items.iter().try_for_each(|item| text(&mut self.out, item)),
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut self.out, items)?;
+                writeln!(self.out)
+            }
+            Format::Jsonl => items.iter().try_for_each(|item| self.line(item)),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn line<T: Serialize + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, value)?;
+        writeln!(self.out)
+    }
+}
+
+/// `42s`, `5m`, `3h` or `12d`.
+fn age(seconds: i64) -> String {
+    match seconds.max(0) {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+/// Comma-separated paths, for problems that involve several notes.
+fn list(paths: &[PathBuf]) -> String {
+    paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use extt_core::types::{FrontmatterFormat, Metadata};
+
+    fn name(prefix: &str, format: Format) -> String {
+        format!("{prefix}_{format:?}").to_lowercase()
+    }
+
+    fn render(format: Format, f: impl FnOnce(&mut Output<Vec<u8>>) -> io::Result<()>) -> String {
+        let mut output = Output::new(format, Vec::new());
+        f(&mut output).unwrap();
+        String::from_utf8(output.out).unwrap()
+    }
+
+    fn summaries() -> Vec<NoteSummary> {
+        vec![
+            NoteSummary {
+                path: "groceries.md".into(),
+                title: Some("Groceries".into()),
+                snippet: Some("buy **milk**".into()),
+                created_at: Some("2024-05-01T09:30:00Z".into()),
+                updated_at: Some("2024-05-02T18:00:00Z".into()),
+            },
+            NoteSummary { path: "inbox/untitled.md".into(), title: None, snippet: None, created_at: None, updated_at: None },
+        ]
+    }
+
+    fn note() -> Note {
+        let mut extra = std::collections::BTreeMap::new();
+        extra.insert("status".to_string(), serde_json::json!("draft"));
+        extra.insert("priority".to_string(), serde_json::json!(2));
+        Note {
+            path: "plan.md".into(),
+            metadata: Metadata {
+                title: Some("Plan".into()),
+                tags: Some(vec!["work".into()]),
+                created_at: None,
+                updated_at: None,
+                extra,
+            },
+            frontmatter: Some("title: Plan\ntags: [work]\nstatus: draft\npriority: 2\n".into()),
+            frontmatter_format: Some(FrontmatterFormat::Yaml),
+            content: "First line\nSecond line\n".into(),
+            revision: "5d41402abc4b2a76b9719d911017c592".into(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_list_and_search() {
+        for format in [Format::Text, Format::Json, Format::Jsonl] {
+            insta::assert_snapshot!(name("list", format), render(format, |o| o.list(&summaries())));
+            insta::assert_snapshot!(name("search", format), render(format, |o| o.search(&summaries())));
+        }
+    }
+
+    #[test]
+    fn test_tags() {
+        let tags = [
+            TagCount { tag: "project".into(), count: 2 },
+            TagCount { tag: "project/alpha".into(), count: 1 },
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(name("tags", format), render(format, |o| o.tags(&tags)));
+        }
+    }
+
+    #[test]
+    fn test_links() {
+        let links = [
+            Link { source: "index.md".into(), line: 1, kind: LinkKind::Wiki, target: "Plan".into(), resolved: Some("work/plan.md".into()) },
+            Link { source: "index.md".into(), line: 3, kind: LinkKind::Markdown, target: "gone.md".into(), resolved: None },
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(name("links", format), render(format, |o| o.links(&links)));
+        }
+    }
+
+    #[test]
+    fn test_findings() {
+        let finding = |problem, fixed| Finding { problem, fixed };
+        let findings = [
+            finding(Problem::Unindexed { path: "new.md".into() }, true),
+            finding(Problem::Stale { path: "gone.md".into() }, true),
+            finding(Problem::InvalidUtf8 { path: "binary.md".into() }, false),
+            finding(
+                Problem::InvalidFrontmatter {
+                    path: "bad.md".into(),
+                    diagnostic: Diagnostic { message: "`tags`: expected a list of tags".into(), line: Some(3), column: Some(1) },
+                },
+                false,
+            ),
+            finding(Problem::BrokenLink { source: "index.md".into(), line: 3, target: "Nowhere".into() }, false),
+            finding(Problem::AmbiguousName { name: "plan".into(), paths: vec!["plan.md".into(), "work/plan.md".into()] }, false),
+            finding(Problem::CaseCollision { paths: vec!["Ideas.md".into(), "ideas.md".into()] }, false),
+            finding(Problem::OutsideRoot { path: "shared".into(), target: "/home/me/shared".into() }, false),
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(name("doctor", format), render(format, |o| o.findings(&findings)));
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        for format in [Format::Text, Format::Json, Format::Jsonl] {
+            insta::assert_snapshot!(name("read", format), render(format, |o| o.note(&note())));
+        }
+    }
+
+    #[test]
+    fn test_writes() {
+        let writes = [
+            Written { action: Action::Created, path: "a.md".into(), from: None, revision: Some("r1".into()) },
+            Written { action: Action::Updated, path: "b.md".into(), from: Some("a.md".into()), revision: Some("r2".into()) },
+            Written { action: Action::Moved, path: "c.md".into(), from: Some("b.md".into()), revision: Some("r2".into()) },
+            Written { action: Action::Trashed, path: "c.md".into(), from: None, revision: None },
+            Written { action: Action::Restored, path: "c (restored).md".into(), from: None, revision: Some("r2".into()) },
+            Written { action: Action::Reverted, path: "c (restored).md".into(), from: None, revision: Some("r1".into()) },
+            Written { action: Action::Deleted, path: "c.md".into(), from: None, revision: None },
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(
+                name("writes", format),
+                render(format, |o| writes.iter().try_for_each(|w| o.written(w)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_move_with_rewrites() {
+        let written = Written { action: Action::Moved, path: "work/plan.md".into(), from: Some("plan.md".into()), revision: Some("r1".into()) };
+        let rewrites = [
+            LinkRewrite { path: "index.md".into(), line: 2, before: "See [[plan]]".into(), after: "See [[work/plan]]".into() },
+            LinkRewrite { path: "index.md".into(), line: 5, before: "[p](plan.md)".into(), after: "[p](work/plan.md)".into() },
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(
+                name("move", format),
+                render(format, |o| {
+                    o.rewrites(&rewrites)?;
+                    o.moved(&written, &rewrites)?;
+                    o.moved(&written, &[])
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_trash() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let entries = [
+            TrashEntry { id: "999000-1a2b3c4d".into(), path: "inbox/a.md".into(), deleted_at: 999_000 },
+            TrashEntry { id: "700000-5e6f7a8b".into(), path: "b.md".into(), deleted_at: 700_000 },
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(name("trash", format), render(format, |o| o.trash(&entries, now)));
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let entry = |revision: &str, path: &str, saved_at, size, moved_from: Option<&str>| HistoryEntry {
+            revision: revision.repeat(64),
+            path: path.into(),
+            saved_at,
+            size,
+            moved_from: moved_from.map(Into::into),
+        };
+        let entries = [
+            entry("c", "done/plan.md", 999_990, 1200, Some("plan.md")),
+            entry("b", "plan.md", 990_000, 1180, None),
+            entry("a", "plan.md", 700_000, 40, None),
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(name("history", format), render(format, |o| o.history(&entries, now)));
+        }
+    }
+
+    #[test]
+    fn test_commits() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let commit = |id: &str, summary: &str, author: &str, email: &str, time| GitCommit {
+            id: id.repeat(40),
+            summary: summary.into(),
+            author: author.into(),
+            email: email.into(),
+            time,
+        };
+        let commits = [
+            commit("b", "move: plan.md -> done/plan.md", "extt agent", "agent@extt.invalid", 999_700),
+            commit("a", "create: plan.md", "Ada", "ada@example.com", 900_000),
+        ];
+        for format in [Format::Text, Format::Jsonl] {
+            insta::assert_snapshot!(name("commits", format), render(format, |o| o.commits(&commits, now)));
+        }
+    }
+
+    #[test]
+    fn test_sync_and_watch() {
+        let report = SyncReport { added: 1, updated: 2, removed: 0, unchanged: 7, skipped: 0 };
+        let changes = [
+            Change::Upsert("a.md".into()),
+            Change::Remove("b.md".into()),
+            Change::Rename { from: "c.md".into(), to: "d/c.md".into() },
+        ];
+        for format in [Format::Text, Format::Json] {
+            insta::assert_snapshot!(
+                name("watch", format),
+                render(format, |o| {
+                    o.synced(&report)?;
+                    o.watch_started(&report, Path::new("/notes"))?;
+                    o.watch_changed(&changes, &report)
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        for format in [Format::Text, Format::Json] {
+            insta::assert_snapshot!(
+                name("error", format),
+                render(format, |o| o.error("not_found", 3, "Note not found: a.md"))
+            );
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Note paths as they arrive from users and URLs.
+
+use std::path::PathBuf;
+
+/// Same rule as the CLI: a name without `.md` gets it appended.
+pub fn note_path(name: &str) -> PathBuf {
+    if name.ends_with(".md") { PathBuf::from(name) } else { PathBuf::from(format!("{name}.md")) }
+}
+
+/// Percent-encodes everything but unreserved characters and `/`.
+pub fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Reverses [`encode_path`]. `None` for malformed escapes or invalid UTF-8.
+pub fn decode_path(encoded: &str) -> Option<String> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_paths() {
+        assert_eq!(encode_path("a b/ü.md"), "a%20b/%C3%BC.md");
+        assert_eq!(decode_path("a%20b/%C3%BC.md").as_deref(), Some("a b/ü.md"));
+        assert_eq!(decode_path("bad%2"), None);
+    }
+
+    #[test]
+    fn test_note_path() {
+        assert_eq!(note_path("inbox/idea"), PathBuf::from("inbox/idea.md"));
+        assert_eq!(note_path("idea.md"), PathBuf::from("idea.md"));
+    }
+}
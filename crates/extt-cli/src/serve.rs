@@ -0,0 +1,514 @@
+//! `extt serve`: a local HTTP/JSON API over the store.
+//!
+//! One process owns the index and serves requests one at a time. Routing
+//! works on plain [`Request`] and [`Response`] values, so it is tested without
+//! opening sockets; [`serve`] only adapts them to `tiny_http`.
+//!
+//! | Method | Path | |
+//! | ------ | ---- | - |
+//! | GET | `/notes?offset=&limit=&tag=` | Page of note summaries |
+//! | POST | `/notes` | Create a note |
+//! | GET | `/notes/{path}` | Read a note |
+//! | PATCH | `/notes/{path}` | Edit a note in place, optionally moving it |
+//! | DELETE | `/notes/{path}` | Move a note to the trash |
+//! | GET | `/search?q=&offset=&limit=&tag=` | Page of search results |
+//! | GET | `/tags` | Tags with note counts |
+//! | POST | `/sync` | Re-index changed files |
+//!
+//! Every request needs `Authorization: Bearer <api_token>`. Reads return an
+//! `ETag` holding the note's revision, and writes honor `If-Match`.
+
+use crate::changes::{NoteChanges, Rejected};
+use crate::output::{Action, Written};
+use crate::paths::{decode_path, encode_path, note_path};
+use anyhow::Context;
+use extt_core::{Store, timestamps};
+use extt_core::types::{ListQuery, Metadata, MoveOptions, NoteSummary, SortKey};
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Page size when the client does not ask for one.
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+const MAX_BODY: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    /// Path and query string, e.g. `/notes?limit=10`.
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        let mut body = serde_json::to_vec_pretty(value).unwrap_or_default();
+        body.push(b'\n');
+        Response { status, headers: vec![("Content-Type", "application/json".into())], body }
+    }
+
+    fn error(status: u16, code: &str, message: &str) -> Self {
+        Response::json(status, &json!({ "error": { "code": code, "message": message } }))
+    }
+
+    fn header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn etag(self, revision: &str) -> Self {
+        self.header("ETag", format!("\"{revision}\""))
+    }
+}
+
+#[derive(Serialize)]
+struct PageView<'a> {
+    items: &'a [NoteSummary],
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+#[derive(Deserialize)]
+struct CreateBody {
+    path: String,
+    #[serde(default)]
+    content: String,
+    title: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct PatchBody {
+    #[serde(flatten)]
+    changes: NoteChanges,
+    /// Moves the note after the edits are applied.
+    move_to: Option<String>,
+    /// With `move_to`, rewrites links so they keep pointing at the note.
+    #[serde(default)]
+    rewrite_links: bool,
+}
+
+/// Serves the API on `bind` until the process is stopped.
+pub fn serve(store: &mut Store, bind: &str, token: &str) -> anyhow::Result<()> {
+    let server = tiny_http::Server::http(bind).map_err(|e| anyhow::anyhow!(e)).with_context(|| format!("Failed to bind {bind}"))?;
+    for mut request in server.incoming_requests() {
+        let mut body = Vec::new();
+        if let Err(err) = request.as_reader().take(MAX_BODY).read_to_end(&mut body) {
+            let _ = request.respond(tiny_http::Response::from_string(err.to_string()).with_status_code(400));
+            continue;
+        }
+        let req = Request {
+            method: request.method().as_str().to_string(),
+            url: request.url().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|h| (h.field.as_str().as_str().to_string(), h.value.as_str().to_string()))
+                .collect(),
+            body,
+        };
+
+        let response = handle(store, token, &req);
+        let mut reply = tiny_http::Response::from_data(response.body).with_status_code(response.status);
+        for (name, value) in response.headers {
+            if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+                reply.add_header(header);
+            }
+        }
+        // The client hanging up early is its own problem.
+        let _ = request.respond(reply);
+    }
+    Ok(())
+}
+
+/// Routes one request.
+pub fn handle(store: &mut Store, token: &str, request: &Request) -> Response {
+    if !authorized(request, token) {
+        return Response::error(401, "unauthorized", "Missing or invalid bearer token")
+            .header("WWW-Authenticate", "Bearer".into());
+    }
+
+    let (path, query) = request.url.split_once('?').unwrap_or((&request.url, ""));
+    let query = parse_query(query);
+    let method = request.method.as_str();
+
+    let result = match path {
+        "/notes" => match method {
+            "GET" => list(store, &query),
+            "POST" => create(store, request),
+            _ => return not_allowed("GET, POST"),
+        },
+        "/search" => match method {
+            "GET" => search(store, &query),
+            _ => return not_allowed("GET"),
+        },
+        "/tags" => match method {
+            "GET" => store.tags().map(|tags| Response::json(200, &tags)).map_err(Rejected::Store),
+            _ => return not_allowed("GET"),
+        },
+        "/sync" => match method {
+            "POST" => store.sync().map(|report| Response::json(200, &report)).map_err(Rejected::Store),
+            _ => return not_allowed("POST"),
+        },
+        _ => {
+            let Some(note) = path.strip_prefix("/notes/").filter(|p| !p.is_empty()).and_then(decode_path) else {
+                return Response::error(404, "no_route", &format!("No route for {path}"));
+            };
+            let note = note_path(&note);
+            match method {
+                "GET" => read(store, &note, request),
+                "PATCH" => patch(store, &note, request),
+                "DELETE" => store
+                    .delete(&note, if_match(request).as_deref())
+                    .map(|()| Response::json(200, &Written { action: Action::Trashed, path: note, from: None, revision: None }))
+                    .map_err(Rejected::Store),
+                _ => return not_allowed("GET, PATCH, DELETE"),
+            }
+        }
+    };
+
+    result.unwrap_or_else(|rejected| match rejected {
+        Rejected::Invalid(message) => Response::error(400, "bad_request", &message),
+        Rejected::Store(err) => store_error(err),
+    })
+}
+
+fn list(store: &Store, query: &[(String, String)]) -> Result<Response, Rejected> {
+    let (offset, limit) = page_params(query)?;
+    let page = store.query(&list_query(query, offset, limit)?)?;
+    Ok(Response::json(200, &PageView { items: &page.items, total: page.total, offset, limit }))
+}
+
+fn search(store: &Store, query: &[(String, String)]) -> Result<Response, Rejected> {
+    let (offset, limit) = page_params(query)?;
+    let text = param(query, "q").unwrap_or_default();
+    let page = store.search_with(text, &list_query(query, offset, limit)?)?;
+    Ok(Response::json(200, &PageView { items: &page.items, total: page.total, offset, limit }))
+}
+
+fn read(store: &Store, path: &Path, request: &Request) -> Result<Response, Rejected> {
+    let note = store.get(path)?;
+    if let Some(tags) = request.header("If-None-Match")
+        && etag_values(tags).any(|tag| tag == "*" || tag == note.revision)
+    {
+        return Ok(Response { status: 304, headers: Vec::new(), body: Vec::new() }.etag(&note.revision));
+    }
+    Ok(Response::json(200, &note).etag(&note.revision))
+}
+
+fn create(store: &mut Store, request: &Request) -> Result<Response, Rejected> {
+    let body: CreateBody = json_body(request)?;
+    let path = note_path(&body.path);
+    let title = body.title.or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()));
+    let metadata = Metadata { title, tags: body.tags, ..Default::default() };
+    store.create(&path, &body.content, Some(metadata))?;
+    let location = format!("/notes/{}", encode_path(&path.to_string_lossy()));
+    Ok(written(store, Action::Created, path, None, 201)?.header("Location", location))
+}
+
+fn patch(store: &mut Store, path: &Path, request: &Request) -> Result<Response, Rejected> {
+    let mut body: PatchBody = json_body(request)?;
+    if let Some(expected) = if_match(request) {
+        body.changes.expected_revision = Some(expected);
+    }
+    let edited = !body.changes.edit.is_empty();
+    // A move after an edit must find the note as the edit left it.
+    let mut expected = body.changes.expected_revision.clone();
+    if edited || body.move_to.is_none() {
+        expected = Some(body.changes.apply(store, path)?);
+    }
+
+    let Some(move_to) = body.move_to else {
+        return written(store, Action::Updated, path.to_path_buf(), None, 200);
+    };
+    let to = note_path(&move_to);
+    let options = MoveOptions { rewrite_links: body.rewrite_links, dry_run: false };
+    store.move_note_with(path, &to, expected.as_deref(), &options)?;
+    let action = if edited { Action::Updated } else { Action::Moved };
+    written(store, action, to, Some(path.to_path_buf()), 200)
+}
+
+fn written(store: &Store, action: Action, path: PathBuf, from: Option<PathBuf>, status: u16) -> Result<Response, Rejected> {
+    let revision = store.get(&path)?.revision;
+    let response = Response::json(status, &Written { action, path, from, revision: Some(revision.clone()) });
+    Ok(response.etag(&revision))
+}
+
+/// Maps a store error to a status code and the same error object the CLI
+/// prints with `--format json`.
+fn store_error(err: extt_core::Error) -> Response {
+    let err = anyhow::Error::from(err);
+    let (exit_code, code) = crate::error_kind(&err);
+    let status = match code {
+        "not_found" => 404,
+        "already_exists" => 409,
+        "conflict" => 412,
+        "path_traversal" | "absolute_path" => 400,
+        "invalid_range" | "section_not_found" | "invalid_frontmatter" | "invalid_utf8" => 422,
+        "database_locked" | "vault_busy" => 503,
+        _ => 500,
+    };
+    Response::json(status, &json!({ "error": { "code": code, "exit_code": exit_code, "message": format!("{err:#}") } }))
+}
+
+fn not_allowed(allow: &str) -> Response {
+    Response::error(405, "method_not_allowed", "Method not allowed").header("Allow", allow.into())
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let Some(given) = request.header("Authorization").and_then(|h| h.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    // Compare in constant time so the token cannot be guessed byte by byte.
+    let (given, token) = (given.trim().as_bytes(), token.as_bytes());
+    given.len() == token.len() && given.iter().zip(token).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Revision from `If-Match`. `*` only requires the note to exist, which
+/// every write checks anyway.
+fn if_match(request: &Request) -> Option<String> {
+    let header = request.header("If-Match")?;
+    etag_values(header).find(|tag| *tag != "*").map(str::to_string)
+}
+
+/// Entity tags in an `If-Match`/`If-None-Match` list, unquoted.
+fn etag_values(header: &str) -> impl Iterator<Item = &str> {
+    header.split(',').map(|tag| tag.trim().trim_start_matches("W/").trim_matches('"'))
+}
+
+fn json_body<T: serde::de::DeserializeOwned>(request: &Request) -> Result<T, Rejected> {
+    serde_json::from_slice(&request.body).map_err(|err| Rejected::Invalid(format!("Invalid JSON body: {err}")))
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((decode_path(&key.replace('+', " "))?, decode_path(&value.replace('+', " "))?))
+        })
+        .collect()
+}
+
+fn param<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+fn page_params(query: &[(String, String)]) -> Result<(usize, usize), Rejected> {
+    let number = |name: &str, default: usize| match param(query, name) {
+        None => Ok(default),
+        Some(value) => value.parse().map_err(|_| Rejected::Invalid(format!("`{name}` must be a non-negative integer"))),
+    };
+    Ok((number("offset", 0)?, number("limit", DEFAULT_LIMIT)?.min(MAX_LIMIT)))
+}
+
+fn list_query(query: &[(String, String)], offset: usize, limit: usize) -> Result<ListQuery, Rejected> {
+    let sort = match param(query, "sort") {
+        None => None,
+        Some(value) => Some(
+            serde_json::from_value::<SortKey>(serde_json::Value::String(value.to_string()))
+                .map_err(|_| Rejected::Invalid("`sort` must be one of path, title, created or updated".to_string()))?,
+        ),
+    };
+    let time = |name: &str| match param(query, name) {
+        None => Ok(None),
+        Some(value) => timestamps::parse(value)
+            .map(Some)
+            .ok_or_else(|| Rejected::Invalid(format!("`{name}` must be an RFC 3339 time or a date"))),
+    };
+    Ok(ListQuery {
+        offset,
+        limit: Some(limit),
+        tag: param(query, "tag").map(str::to_string),
+        sort,
+        since: time("since")?,
+        until: time("until")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const TOKEN: &str = "secret";
+
+    fn store() -> (tempfile::TempDir, Store) {
+        let dir = tempdir().unwrap();
+        let notes = dir.path().join("notes");
+        fs::create_dir(&notes).unwrap();
+        for name in ["alpha", "beta", "gamma"] {
+            fs::write(notes.join(format!("{name}.md")), format!("---\ntitle: {name}\n---\n{name} body\n")).unwrap();
+        }
+        let mut store = Store::new(notes, dir.path().join("index.db")).unwrap();
+        store.sync().unwrap();
+        (dir, store)
+    }
+
+    fn request(method: &str, url: &str, body: Option<Value>, headers: &[(&str, &str)]) -> Request {
+        let mut all = vec![("Authorization".to_string(), format!("Bearer {TOKEN}"))];
+        all.extend(headers.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        Request {
+            method: method.into(),
+            url: url.into(),
+            headers: all,
+            body: body.map(|b| b.to_string().into_bytes()).unwrap_or_default(),
+        }
+    }
+
+    fn send(store: &mut Store, request: Request) -> (u16, Value, Option<String>) {
+        let response = handle(store, TOKEN, &request);
+        let etag = response.headers.iter().find(|(name, _)| *name == "ETag").map(|(_, v)| v.clone());
+        let body = if response.body.is_empty() { Value::Null } else { serde_json::from_slice(&response.body).unwrap() };
+        (response.status, body, etag)
+    }
+
+    #[test]
+    fn test_requires_token() {
+        let (_dir, mut store) = store();
+        let mut req = request("GET", "/notes", None, &[]);
+        req.headers[0].1 = "Bearer wrong".into();
+        let response = handle(&mut store, TOKEN, &req);
+        assert_eq!(response.status, 401);
+        assert!(response.headers.contains(&("WWW-Authenticate", "Bearer".into())));
+
+        req.headers.clear();
+        assert_eq!(handle(&mut store, TOKEN, &req).status, 401);
+    }
+
+    #[test]
+    fn test_list_and_search_pages() {
+        let (_dir, mut store) = store();
+        let (status, body, _) = send(&mut store, request("GET", "/notes?offset=1&limit=1", None, &[]));
+        assert_eq!(status, 200);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+        assert_eq!((&body["items"][0]["path"], &body["items"][0]["title"]), (&json!("beta.md"), &json!("beta")));
+        assert!(body["items"][0]["updated_at"].is_string());
+
+        let (_, body, _) = send(&mut store, request("GET", "/notes?sort=updated&since=2000-01-01&until=2000-01-02", None, &[]));
+        assert_eq!(body["total"], 0);
+        let (status, _, _) = send(&mut store, request("GET", "/notes?sort=size", None, &[]));
+        assert_eq!(status, 400);
+        let (status, _, _) = send(&mut store, request("GET", "/notes?since=yesterday", None, &[]));
+        assert_eq!(status, 400);
+
+        let (_, body, _) = send(&mut store, request("GET", "/search?q=gamma+body", None, &[]));
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["items"][0]["path"], "gamma.md");
+
+        let (status, body, _) = send(&mut store, request("GET", "/notes?limit=-1", None, &[]));
+        assert_eq!(status, 400);
+        assert_eq!(body["error"]["code"], "bad_request");
+    }
+
+    #[test]
+    fn test_tag_filters() {
+        let (_dir, mut store) = store();
+        store.create(Path::new("delta.md"), "delta body #project/delta", None).unwrap();
+
+        let (_, body, _) = send(&mut store, request("GET", "/notes?tag=project", None, &[]));
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["items"][0]["path"], "delta.md");
+
+        let (_, body, _) = send(&mut store, request("GET", "/search?q=body&tag=other", None, &[]));
+        assert_eq!(body["total"], 0);
+
+        let (status, body, _) = send(&mut store, request("GET", "/tags", None, &[]));
+        assert_eq!(status, 200);
+        assert_eq!(body, json!([{ "tag": "project", "count": 1 }, { "tag": "project/delta", "count": 1 }]));
+    }
+
+    #[test]
+    fn test_crud_with_etags() {
+        let (dir, mut store) = store();
+
+        let (status, body, etag) =
+            send(&mut store, request("POST", "/notes", Some(json!({ "path": "inbox/new idea", "content": "hello\n" })), &[]));
+        assert_eq!(status, 201);
+        assert_eq!(body["path"], "inbox/new idea.md");
+        let created = handle(&mut store, TOKEN, &request("GET", "/notes/inbox/new%20idea.md", None, &[]));
+        assert_eq!(created.status, 200);
+        let etag = etag.unwrap();
+
+        let (status, note, read_etag) = send(&mut store, request("GET", "/notes/inbox/new%20idea.md", None, &[]));
+        assert_eq!(status, 200);
+        assert_eq!(note["metadata"]["title"], "new idea");
+        assert_eq!(read_etag.as_deref(), Some(etag.as_str()));
+        let (status, _, _) = send(&mut store, request("GET", "/notes/inbox/new%20idea.md", None, &[("If-None-Match", &etag)]));
+        assert_eq!(status, 304);
+
+        let patch = json!({ "title": "Idea", "metadata": [{ "op": "append", "key": "tags", "value": "later" }] });
+        let (status, body, new_etag) =
+            send(&mut store, request("PATCH", "/notes/inbox/new%20idea.md", Some(patch.clone()), &[("If-Match", &etag)]));
+        assert_eq!(status, 200);
+        assert_eq!(body["action"], "updated");
+        assert_ne!(new_etag.as_deref(), Some(etag.as_str()));
+
+        // A second writer still holding the old ETag is refused.
+        let (status, body, _) = send(&mut store, request("PATCH", "/notes/inbox/new%20idea.md", Some(patch), &[("If-Match", &etag)]));
+        assert_eq!(status, 412);
+        assert_eq!(body["error"]["code"], "conflict");
+
+        let (status, body, _) =
+            send(&mut store, request("PATCH", "/notes/inbox/new%20idea.md", Some(json!({ "move_to": "idea" })), &[]));
+        assert_eq!(status, 200);
+        assert_eq!((body["action"].as_str(), body["from"].as_str()), (Some("moved"), Some("inbox/new idea.md")));
+        assert!(dir.path().join("notes/idea.md").exists());
+
+        let (status, body, _) = send(&mut store, request("DELETE", "/notes/idea.md", None, &[("If-Match", "\"stale\"")]));
+        assert_eq!((status, body["error"]["code"].as_str()), (412, Some("conflict")));
+        let (status, _, _) = send(&mut store, request("DELETE", "/notes/idea.md", None, &[]));
+        assert_eq!(status, 200);
+        let (status, body, _) = send(&mut store, request("GET", "/notes/idea.md", None, &[]));
+        assert_eq!((status, body["error"]["code"].as_str()), (404, Some("not_found")));
+    }
+
+    #[test]
+    fn test_errors() {
+        let (_dir, mut store) = store();
+        let cases = [
+            (request("POST", "/notes", Some(json!({ "path": "alpha" })), &[]), 409, "already_exists"),
+            (request("GET", "/notes/..%2Fescape.md", None, &[]), 400, "path_traversal"),
+            (request("PATCH", "/notes/alpha.md", Some(json!({ "body_edit": { "op": "delete", "from": 5, "to": 9 } })), &[]), 422, "invalid_range"),
+            (request("POST", "/notes", Some(json!({ "content": "no path" })), &[]), 400, "bad_request"),
+            (request("PUT", "/notes/alpha.md", None, &[]), 405, "method_not_allowed"),
+            (request("GET", "/elsewhere", None, &[]), 404, "no_route"),
+        ];
+        for (req, status, code) in cases {
+            let url = req.url.clone();
+            let (got, body, _) = send(&mut store, req);
+            assert_eq!((got, body["error"]["code"].as_str()), (status, Some(code)), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_sync_route() {
+        let (dir, mut store) = store();
+        fs::write(dir.path().join("notes/delta.md"), "new").unwrap();
+        let (status, body, _) = send(&mut store, request("POST", "/sync", None, &[]));
+        assert_eq!(status, 200);
+        assert_eq!(body["added"], 1);
+    }
+}
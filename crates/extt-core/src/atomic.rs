@@ -0,0 +1,154 @@
+//! Crash-safe note writes.
+//!
+//! New content goes to a hidden temp file next to the note, is flushed to
+//! disk, and only then renamed over the note. A crash or a full disk at any
+//! point leaves either the old note or the new one, never a truncated file.
+//! The temp file is named `.<name>.extt-tmp`, which sync skips because it
+//! does not end in `.md`.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A step of an atomic write, for fault injection in tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    Create,
+    Write,
+    Sync,
+    Rename,
+}
+
+#[cfg(test)]
+thread_local! {
+    static FAULT: std::cell::Cell<Option<Step>> = const { std::cell::Cell::new(None) };
+}
+
+/// Makes every atomic write on this thread fail at `step`, until cleared
+/// with `None`. A failed [`Step::Write`] leaves half the content behind, like
+/// a full disk would.
+#[cfg(test)]
+pub(crate) fn inject_fault(step: Option<Step>) {
+    FAULT.with(|fault| fault.set(step));
+}
+
+fn fail_at(step: Step) -> io::Result<()> {
+    #[cfg(test)]
+    if FAULT.with(|fault| fault.get()) == Some(step) {
+        return Err(io::Error::other(format!("injected fault at {step:?}")));
+    }
+    let _ = step;
+    Ok(())
+}
+
+/// Writes `bytes` to `path` atomically, keeping the permissions of the file
+/// it replaces.
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    Staged::new(path, bytes)?.commit()
+}
+
+/// New content for `path`, written and synced to a temp file but not yet in
+/// place. Dropping it without [`Staged::commit`] removes the temp file.
+pub(crate) struct Staged {
+    temp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl Staged {
+    pub(crate) fn new(path: &Path, bytes: &[u8]) -> io::Result<Self> {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let temp = path.with_file_name(format!(".{name}.extt-tmp"));
+        fail_at(Step::Create)?;
+        let mut file = File::create(&temp)?;
+        // From here on, dropping `staged` cleans up the temp file.
+        let staged = Self { temp, path: path.to_path_buf(), committed: false };
+
+        if fail_at(Step::Write).is_err() {
+            file.write_all(&bytes[..bytes.len() / 2])?;
+            return Err(io::Error::new(io::ErrorKind::StorageFull, "injected fault at Write"));
+        }
+        file.write_all(bytes)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        fail_at(Step::Sync)?;
+        file.sync_all()?;
+        Ok(staged)
+    }
+
+    /// The temp file holding the new content.
+    pub(crate) fn temp(&self) -> &Path {
+        &self.temp
+    }
+
+    /// Renames the temp file over the destination.
+    pub(crate) fn commit(mut self) -> io::Result<()> {
+        fail_at(Step::Rename)?;
+        fs::rename(&self.temp, &self.path)?;
+        self.committed = true;
+        sync_dir(&self.path);
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Makes a rename durable. Best effort: not every platform or filesystem
+/// can sync a directory, and the rename itself already happened.
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faults_leave_the_original() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("note.md");
+        fs::write(&path, "original")?;
+
+        for step in [Step::Create, Step::Write, Step::Sync, Step::Rename] {
+            inject_fault(Some(step));
+            let result = write(&path, b"replacement that never lands");
+            inject_fault(None);
+
+            assert!(result.is_err(), "{step:?}");
+            assert_eq!(fs::read_to_string(&path)?, "original", "{step:?}");
+            assert_eq!(fs::read_dir(dir.path())?.count(), 1, "temp file left behind at {step:?}");
+        }
+
+        write(&path, b"replacement")?;
+        assert_eq!(fs::read_to_string(&path)?, "replacement");
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keeps_permissions() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("private.md");
+        fs::write(&path, "secret")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        write(&path, b"still secret")?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Partial edits to a note body.
+//!
+//! Line numbers are 1-based and inclusive, counted from the first line of the
+//! body (after the frontmatter), the same way `extt read --from/--to` counts
+//! them. Sections are ATX headings (`## Tasks`) and run until the next
+//! heading of the same or a higher level; headings inside fenced code blocks
+//! are ignored.
+
+use crate::types::BodyEdit;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BodyEditError {
+    InvalidRange { from: usize, to: usize, lines: usize },
+    SectionNotFound(String),
+}
+
+/// Returns `body` with `edit` applied.
+pub(crate) fn apply(body: &str, edit: &BodyEdit) -> Result<String, BodyEditError> {
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+    let eol = if body.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let (start, end, content) = match edit {
+        BodyEdit::Replace { from, to, content } => {
+            check_range(*from, *to, lines.len())?;
+            (from - 1, *to, content.as_str())
+        }
+        BodyEdit::Delete { from, to } => {
+            check_range(*from, *to, lines.len())?;
+            (from - 1, *to, "")
+        }
+        BodyEdit::Insert { at, content } => {
+            if *at == 0 || *at > lines.len() + 1 {
+                return Err(BodyEditError::InvalidRange { from: *at, to: *at, lines: lines.len() });
+            }
+            (at - 1, at - 1, content.as_str())
+        }
+        BodyEdit::ReplaceSection { heading, content } => {
+            let (start, end) = find_section(&lines, heading)
+                .ok_or_else(|| BodyEditError::SectionNotFound(heading.clone()))?;
+            (start, end, content.as_str())
+        }
+    };
+
+    let mut out = String::with_capacity(body.len() + content.len());
+    for line in &lines[..start] {
+        out.push_str(line);
+    }
+    if !content.is_empty() {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push_str(eol);
+        }
+        out.push_str(content);
+        // Keep the next line on its own line, and keep the final newline if
+        // the replaced lines had one.
+        let had_newline = end > start && lines[end - 1].ends_with('\n');
+        if !content.ends_with('\n') && (end < lines.len() || had_newline) {
+            out.push_str(eol);
+        }
+    } else if end == lines.len() && end > start && !lines[end - 1].ends_with('\n') && out.ends_with('\n') {
+        // Deleting an unterminated last line leaves the new last line as it was.
+        out.truncate(out.len() - if out.ends_with("\r\n") { 2 } else { 1 });
+    }
+    for line in &lines[end..] {
+        out.push_str(line);
+    }
+    Ok(out)
+}
+
+fn check_range(from: usize, to: usize, lines: usize) -> Result<(), BodyEditError> {
+    if from == 0 || from > to || to > lines {
+        return Err(BodyEditError::InvalidRange { from, to, lines });
+    }
+    Ok(())
+}
+
+/// Line range holding the content of the section under `heading`, excluding
+/// the heading itself and the blank lines before the next heading.
+///
+/// `heading` may be given with or without its `#` markers; with them, only
+/// a heading of that level matches.
+fn find_section(lines: &[&str], heading: &str) -> Option<(usize, usize)> {
+    let wanted = parse_heading(heading.trim());
+    let wanted_text = wanted.map_or(heading.trim(), |(_, text)| text);
+    let wanted_level = wanted.map(|(level, _)| level);
+
+    let headings = headings(lines);
+    let index = headings.iter().position(|&(_, level, text)| {
+        text == wanted_text && wanted_level.is_none_or(|wanted| wanted == level)
+    })?;
+    let (line, level, _) = headings[index];
+
+    let mut end = headings[index + 1..]
+        .iter()
+        .find(|&&(_, other, _)| other <= level)
+        .map_or(lines.len(), |&(line, _, _)| line);
+    if end < lines.len() {
+        while end > line + 1 && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+    }
+    Some((line + 1, end))
+}
+
+/// `(line index, level, text)` of every ATX heading outside code fences.
+fn headings<'a>(lines: &[&'a str]) -> Vec<(usize, usize, &'a str)> {
+    let mut fence: Option<&str> = None;
+    let mut found = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            (None, None) => {
+                if let Some((level, text)) = parse_heading(line.trim_end()) {
+                    found.push((index, level, text));
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// `(line index, byte offset, line)` of every body line outside fenced code
+/// blocks, without the fence lines themselves or line endings.
+pub(crate) fn prose_lines(body: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    body.split_inclusive('\n').enumerate().filter_map(move |(index, raw)| {
+        let start = offset;
+        offset += raw.len();
+        let line = raw.strip_suffix('\n').map_or(raw, |l| l.strip_suffix('\r').unwrap_or(l));
+        let trimmed = line.trim_start();
+        match (fence, ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m))) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            (None, None) => return Some((index, start, line)),
+            _ => {}
+        }
+        None
+    })
+}
+
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let text = rest.trim();
+    // Optional closing sequence: `## Tasks ##`.
+    let text = match text.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+        _ => text,
+    };
+    Some((level, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(from: usize, to: usize, content: &str) -> BodyEdit {
+        BodyEdit::Replace { from, to, content: content.into() }
+    }
+
+    fn section(heading: &str, content: &str) -> BodyEdit {
+        BodyEdit::ReplaceSection { heading: heading.into(), content: content.into() }
+    }
+
+    #[test]
+    fn test_line_edits() {
+        let body = "one\ntwo\nthree\n";
+        assert_eq!(apply(body, &replace(2, 2, "TWO")).unwrap(), "one\nTWO\nthree\n");
+        assert_eq!(apply(body, &replace(1, 3, "all")).unwrap(), "all\n");
+        assert_eq!(apply(body, &replace(2, 3, "a\nb\nc\n")).unwrap(), "one\na\nb\nc\n");
+        assert_eq!(apply(body, &BodyEdit::Delete { from: 1, to: 2 }).unwrap(), "three\n");
+        assert_eq!(apply(body, &BodyEdit::Insert { at: 1, content: "zero".into() }).unwrap(), "zero\none\ntwo\nthree\n");
+        assert_eq!(apply(body, &BodyEdit::Insert { at: 4, content: "four".into() }).unwrap(), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn test_line_edits_without_trailing_newline() {
+        let body = "one\ntwo";
+        assert_eq!(apply(body, &replace(2, 2, "TWO")).unwrap(), "one\nTWO");
+        assert_eq!(apply(body, &BodyEdit::Delete { from: 2, to: 2 }).unwrap(), "one");
+        assert_eq!(apply(body, &BodyEdit::Insert { at: 3, content: "three".into() }).unwrap(), "one\ntwo\nthree");
+        assert_eq!(apply("a\r\nb", &BodyEdit::Insert { at: 2, content: "x".into() }).unwrap(), "a\r\nx\r\nb");
+        assert_eq!(apply("", &BodyEdit::Insert { at: 1, content: "first".into() }).unwrap(), "first");
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        let body = "one\ntwo\n";
+        for edit in [replace(0, 1, "x"), replace(2, 1, "x"), replace(1, 3, "x"), BodyEdit::Insert { at: 4, content: "x".into() }] {
+            assert!(matches!(apply(body, &edit), Err(BodyEditError::InvalidRange { lines: 2, .. })), "{edit:?}");
+        }
+    }
+
+    #[test]
+    fn test_replace_section() {
+        let body = "# Plan\nintro\n\n## Tasks\n- old\n- older\n\n## Notes\nkeep\n";
+        assert_eq!(
+            apply(body, &section("Tasks", "- new")).unwrap(),
+            "# Plan\nintro\n\n## Tasks\n- new\n\n## Notes\nkeep\n"
+        );
+        assert_eq!(apply(body, &section("## Notes", "gone\n")).unwrap(), "# Plan\nintro\n\n## Tasks\n- old\n- older\n\n## Notes\ngone\n");
+        // A top-level section runs over its subsections.
+        assert_eq!(apply(body, &section("# Plan", "all")).unwrap(), "# Plan\nall\n");
+        assert_eq!(apply(body, &section("Tasks", "")).unwrap(), "# Plan\nintro\n\n## Tasks\n\n## Notes\nkeep\n");
+    }
+
+    #[test]
+    fn test_section_lookup() {
+        let body = "```\n# Tasks\n```\n## Tasks ##\nreal\n";
+        assert_eq!(apply(body, &section("Tasks", "done")).unwrap(), "```\n# Tasks\n```\n## Tasks ##\ndone\n");
+        assert_eq!(apply(body, &section("# Tasks", "x")), Err(BodyEditError::SectionNotFound("# Tasks".into())));
+        assert_eq!(apply(body, &section("Missing", "x")), Err(BodyEditError::SectionNotFound("Missing".into())));
+        assert_eq!(parse_heading("#hashtag"), None);
+        assert_eq!(parse_heading("### C# ###"), Some((3, "C#")));
+    }
+}
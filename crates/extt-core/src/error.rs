@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+/// Errors returned by extt-core.
+///
+/// Variants are part of the public API: the CLI maps each one to its own exit
+/// code, so scripts and agents can branch on them. New variants may be added,
+/// but existing ones keep their meaning.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Note not found: {}", .0.display())]
+    NotFound(PathBuf),
+    #[error("Note already exists: {}", .0.display())]
+    AlreadyExists(PathBuf),
+    #[error("Path traversal detected: {}", .0.display())]
+    PathTraversal(PathBuf),
+    #[error("Absolute paths are not allowed: {}", .0.display())]
+    AbsolutePath(PathBuf),
+    #[error("Invalid frontmatter in {}: {message}", path.display())]
+    InvalidFrontmatter { path: PathBuf, message: String },
+    #[error("Lines {from}-{to} are out of range in {} ({lines} lines)", path.display())]
+    InvalidRange { path: PathBuf, from: usize, to: usize, lines: usize },
+    #[error("Section not found in {}: {heading}", path.display())]
+    SectionNotFound { path: PathBuf, heading: String },
+    #[error("{} was changed by someone else (expected revision {expected}, found {actual})", path.display())]
+    Conflict { path: PathBuf, expected: String, actual: String },
+    #[error("No revision {revision} in the history of {}", path.display())]
+    UnknownRevision { path: PathBuf, revision: String },
+    #[error("File is not valid UTF-8: {}", .0.display())]
+    InvalidUtf8(PathBuf),
+    #[error("Index database is locked by another process")]
+    DatabaseLocked,
+    #[error("The vault is busy with another extt operation (sync, rebuild or move); try again")]
+    VaultBusy,
+    #[error("Index database is corrupt; run `extt sync --rebuild` to recreate it")]
+    IndexCorrupt,
+    #[error("Index database has schema version {found} but this extt supports up to {supported}; please upgrade extt")]
+    SchemaTooNew { found: i64, supported: i64 },
+    #[error("Database error: {0}")]
+    Database(rusqlite::Error),
+    #[error("Git error: {}", .0.message())]
+    Git(git2::Error),
+    #[error("Git integration is not enabled for this vault")]
+    GitDisabled,
+    #[error("Watch error: {0}")]
+    Watch(#[from] notify::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => Error::DatabaseLocked,
+            Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase) => Error::IndexCorrupt,
+            _ => Error::Database(err),
+        }
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(err: git2::Error) -> Self {
+        Error::Git(err)
+    }
+}
+
+impl From<walkdir::Error> for Error {
+    fn from(err: walkdir::Error) -> Self {
+        Error::Io(err.into())
+    }
+}
+
+impl Error {
+    /// Maps an I/O error on a note file to [`Error::NotFound`] or
+    /// [`Error::InvalidUtf8`] where that is what it means.
+    pub(crate) fn from_io(err: std::io::Error, relative_path: impl Into<PathBuf>) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => Error::NotFound(relative_path.into()),
+            std::io::ErrorKind::InvalidData => Error::InvalidUtf8(relative_path.into()),
+            _ => Error::Io(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_errors_are_classified() {
+        let busy = rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY), None);
+        assert!(matches!(Error::from(busy), Error::DatabaseLocked));
+
+        let corrupt = rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_NOTADB), None);
+        assert!(matches!(Error::from(corrupt), Error::IndexCorrupt));
+
+        assert!(matches!(Error::from(rusqlite::Error::QueryReturnedNoRows), Error::Database(_)));
+    }
+
+    #[test]
+    fn test_io_errors_keep_note_path() {
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(matches!(Error::from_io(missing, "a.md"), Error::NotFound(p) if p == std::path::Path::new("a.md")));
+
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(matches!(Error::from_io(denied, "a.md"), Error::Io(_)));
+    }
+}
@@ -0,0 +1,849 @@
+//! Lossless editing of note files.
+//!
+//! [`NoteFile`] splits a note into its frontmatter block and body while
+//! keeping every original byte, so writing it back without changes is an
+//! identity. Replacing the body leaves the frontmatter text untouched, and key
+//! edits only rewrite the key that changed, keeping comments, key order and
+//! quoting everywhere else.
+//!
+//! Frontmatter is YAML between `---` lines, TOML between `+++` lines, or a
+//! JSON object at the start of the note. Edits are written back in the
+//! format the note already uses. YAML and TOML are edited in place; JSON has
+//! no comments to keep, so it is re-serialized in its original key order.
+
+use crate::types::{FrontmatterFormat, MetadataEdit};
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct FrontmatterError(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteFile {
+    frontmatter: Option<Block>,
+    /// Blank lines between the frontmatter and the body. Kept apart from the
+    /// body so that body line numbers match what readers display.
+    gap: String,
+    body: String,
+    /// Format of the block added if a note without frontmatter gains a key.
+    default_format: FrontmatterFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Block {
+    format: FrontmatterFormat,
+    open: String,
+    text: String,
+    close: String,
+}
+
+impl Block {
+    fn new(format: FrontmatterFormat, text: String) -> Self {
+        let (open, close) = match format {
+            FrontmatterFormat::Yaml => ("---\n", "---\n"),
+            FrontmatterFormat::Toml => ("+++\n", "+++\n"),
+            FrontmatterFormat::Json => ("", "\n"),
+        };
+        Self { format, open: open.into(), text, close: close.into() }
+    }
+}
+
+impl NoteFile {
+    /// Splits a note on a leading `---` or `+++` delimited block, or a
+    /// leading JSON object. A block that is never closed is treated as part
+    /// of the body.
+    pub fn parse(text: &str) -> Self {
+        let (frontmatter, rest) = match split_block(text) {
+            Some((block, rest)) => (Some(block), rest),
+            None => (None, text),
+        };
+        let (gap, body) = split_gap(rest);
+        Self { frontmatter, gap: gap.to_string(), body: body.to_string(), default_format: FrontmatterFormat::Yaml }
+    }
+
+    /// Sets the format of the block added if the note has no frontmatter and
+    /// gains a key. YAML unless set.
+    pub fn with_default_format(mut self, format: FrontmatterFormat) -> Self {
+        self.default_format = format;
+        self
+    }
+
+    /// Raw frontmatter text, if the note has frontmatter. That is everything
+    /// between the delimiter lines, or the whole object for JSON.
+    pub fn frontmatter(&self) -> Option<&str> {
+        self.frontmatter.as_ref().map(|b| b.text.as_str())
+    }
+
+    /// Format of the frontmatter, if the note has any.
+    pub fn format(&self) -> Option<FrontmatterFormat> {
+        self.frontmatter.as_ref().map(|b| b.format)
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    pub fn set_body(&mut self, body: &str) {
+        self.body = body.to_string();
+    }
+
+    /// Value at a key path such as `["metadata", "status"]`.
+    pub fn get(&self, path: &[&str]) -> Result<Option<Value>, FrontmatterError> {
+        let mapping = parse_mapping(self.edit_format(), self.frontmatter().unwrap_or(""))?;
+        Ok(get_in(&mapping, path).cloned())
+    }
+
+    /// Sets the value at a key path, rewriting only that key.
+    ///
+    /// Missing parents are created as nested mappings, and a frontmatter block
+    /// is added if the note has none. Fails if the existing frontmatter is not
+    /// a valid mapping, since there is no way to edit it without risking the
+    /// user's data.
+    pub fn set(&mut self, path: &[&str], value: &Value) -> Result<(), FrontmatterError> {
+        let format = self.edit_format();
+        let text = self.frontmatter().unwrap_or("");
+        let mut expected = parse_mapping(format, text)?;
+        set_in(&mut expected, path, value.clone())?;
+
+        let edited = edit_text(format, text, path, Some(value)).unwrap_or_default();
+        self.replace_text(edited, &expected)
+    }
+
+    /// Removes the value at a key path. Returns whether it was present.
+    pub fn remove(&mut self, path: &[&str]) -> Result<bool, FrontmatterError> {
+        let Some(text) = self.frontmatter() else {
+            return Ok(false);
+        };
+        let format = self.edit_format();
+        let mut expected = parse_mapping(format, text)?;
+        if remove_in(&mut expected, path).is_none() {
+            return Ok(false);
+        }
+
+        let edited = edit_text(format, text, path, None).unwrap_or_default();
+        self.replace_text(edited, &expected)?;
+        Ok(true)
+    }
+
+    /// Applies a [`MetadataEdit`], whose key is a dot-separated path.
+    pub fn apply(&mut self, edit: &MetadataEdit) -> Result<(), FrontmatterError> {
+        let path: Vec<&str> = edit.key().split('.').collect();
+        match edit {
+            MetadataEdit::Set { value, .. } => self.set(&path, &to_yaml(value)?),
+            MetadataEdit::Unset { .. } => self.remove(&path).map(|_| ()),
+            MetadataEdit::Append { value, .. } => {
+                let value = to_yaml(value)?;
+                let mut items = self.list_at(&path)?;
+                if !items.contains(&value) {
+                    items.push(value);
+                    self.set(&path, &Value::Sequence(items))?;
+                }
+                Ok(())
+            }
+            MetadataEdit::Remove { value, .. } => {
+                let value = to_yaml(value)?;
+                let mut items = self.list_at(&path)?;
+                let before = items.len();
+                items.retain(|item| item != &value);
+                if items.is_empty() {
+                    self.remove(&path)?;
+                } else if items.len() != before {
+                    self.set(&path, &Value::Sequence(items))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Current value at `path` as a list. A lone scalar such as `tags: foo`
+    /// counts as a one-element list.
+    fn list_at(&self, path: &[&str]) -> Result<Vec<Value>, FrontmatterError> {
+        Ok(match self.get(path)? {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Sequence(items)) => items,
+            Some(other) => vec![other],
+        })
+    }
+
+    /// The format of the existing block, or of the block an edit would add.
+    fn edit_format(&self) -> FrontmatterFormat {
+        self.format().unwrap_or(self.default_format)
+    }
+
+    /// Installs an edited frontmatter text, falling back to re-serializing the
+    /// whole mapping if the in-place edit did not produce the intended data
+    /// (for example with flow-style YAML mappings or anchors).
+    fn replace_text(&mut self, edited: String, expected: &Mapping) -> Result<(), FrontmatterError> {
+        let format = self.edit_format();
+        let text = match parse_mapping(format, &edited) {
+            Ok(mapping) if &mapping == expected => edited,
+            _ => render(format, expected)?,
+        };
+        match &mut self.frontmatter {
+            Some(block) => block.text = text,
+            None => self.frontmatter = Some(Block::new(format, text)),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NoteFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(block) = &self.frontmatter {
+            write!(f, "{}{}{}", block.open, block.text, block.close)?;
+        }
+        write!(f, "{}{}", self.gap, self.body)
+    }
+}
+
+fn split_gap(text: &str) -> (&str, &str) {
+    let body = text.trim_start_matches(['\n', '\r']);
+    (&text[..text.len() - body.len()], body)
+}
+
+/// The frontmatter block at the start of `text` and what follows it.
+fn split_block(text: &str) -> Option<(Block, &str)> {
+    if text.starts_with('{') {
+        return split_json(text);
+    }
+    let (first, rest) = text.split_once('\n')?;
+    let delimiter = first.trim_end();
+    let format = match delimiter {
+        "---" => FrontmatterFormat::Yaml,
+        "+++" => FrontmatterFormat::Toml,
+        _ => return None,
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            let block = Block {
+                format,
+                open: text[..first.len() + 1].to_string(),
+                text: rest[..offset].to_string(),
+                close: line.to_string(),
+            };
+            return Some((block, &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// A JSON object at the start of `text`. Nothing else may follow it on its
+/// closing line.
+fn split_json(text: &str) -> Option<(Block, &str)> {
+    let mut values = serde_json::Deserializer::from_str(text).into_iter::<serde_json::Value>();
+    let Some(Ok(serde_json::Value::Object(_))) = values.next() else {
+        return None;
+    };
+    let end = values.byte_offset();
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i + 1);
+    if !text[end..line_end].trim().is_empty() {
+        return None;
+    }
+    let block = Block {
+        format: FrontmatterFormat::Json,
+        open: String::new(),
+        text: text[..end].to_string(),
+        close: text[end..line_end].to_string(),
+    };
+    Some((block, &text[line_end..]))
+}
+
+/// Frontmatter text that does not parse.
+pub(crate) struct SyntaxError {
+    pub message: String,
+    /// 1-based line and column within the frontmatter text.
+    pub location: Option<(usize, usize)>,
+}
+
+/// Parses frontmatter text in any format into YAML's data model, which keeps
+/// key order. TOML dates and times become strings.
+pub(crate) fn parse_value(format: FrontmatterFormat, text: &str) -> Result<Value, SyntaxError> {
+    if text.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    match format {
+        FrontmatterFormat::Yaml => serde_yaml::from_str(text).map_err(|err| SyntaxError {
+            message: err.to_string(),
+            location: err.location().map(|l| (l.line(), l.column())),
+        }),
+        FrontmatterFormat::Json => serde_json::from_str(text).map_err(|err| SyntaxError {
+            message: err.to_string(),
+            location: Some((err.line(), err.column())),
+        }),
+        FrontmatterFormat::Toml => match text.parse::<DocumentMut>() {
+            Ok(doc) => Ok(Value::Mapping(from_toml_table(doc.as_table()))),
+            Err(err) => Err(SyntaxError {
+                message: err.message().trim_end().to_string(),
+                location: err.span().map(|span| line_column(text, span.start)),
+            }),
+        },
+    }
+}
+
+fn parse_mapping(format: FrontmatterFormat, text: &str) -> Result<Mapping, FrontmatterError> {
+    match parse_value(format, text) {
+        Ok(Value::Mapping(mapping)) => Ok(mapping),
+        Ok(Value::Null) => Ok(Mapping::new()),
+        Ok(_) => Err(FrontmatterError("frontmatter is not a mapping".into())),
+        Err(err) => Err(FrontmatterError(err.message)),
+    }
+}
+
+/// A complete frontmatter block, delimiters included, holding `value`.
+pub(crate) fn render_block<T: Serialize>(format: FrontmatterFormat, value: &T) -> Result<String, FrontmatterError> {
+    let mapping = match serde_yaml::to_value(value) {
+        Ok(Value::Mapping(mapping)) => mapping,
+        Ok(_) => return Err(FrontmatterError("frontmatter is not a mapping".into())),
+        Err(err) => return Err(FrontmatterError(err.to_string())),
+    };
+    let block = Block::new(format, render(format, &mapping)?);
+    Ok(format!("{}{}{}", block.open, block.text, block.close))
+}
+
+/// Serializes a whole mapping as frontmatter text.
+fn render(format: FrontmatterFormat, mapping: &Mapping) -> Result<String, FrontmatterError> {
+    match format {
+        FrontmatterFormat::Yaml => serde_yaml::to_string(mapping).map_err(|e| FrontmatterError(e.to_string())),
+        FrontmatterFormat::Json => serde_json::to_string_pretty(mapping).map_err(|e| FrontmatterError(e.to_string())),
+        FrontmatterFormat::Toml => {
+            let mut doc = DocumentMut::new();
+            fill_toml_table(doc.as_table_mut(), mapping)
+                .ok_or_else(|| FrontmatterError("frontmatter has values TOML cannot hold, such as null".into()))?;
+            Ok(doc.to_string())
+        }
+    }
+}
+
+/// Rewrites only the part of `text` holding `path`. `None` when the format or
+/// layout calls for re-serializing the whole block instead.
+fn edit_text(format: FrontmatterFormat, text: &str, path: &[&str], value: Option<&Value>) -> Option<String> {
+    match format {
+        FrontmatterFormat::Yaml => edit_lines(text, path, value),
+        FrontmatterFormat::Toml => edit_toml(text, path, value),
+        FrontmatterFormat::Json => None,
+    }
+}
+
+/// Sets or removes `path` in a TOML document, keeping the comments and
+/// formatting of everything else, including around a replaced value.
+fn edit_toml(text: &str, path: &[&str], value: Option<&Value>) -> Option<String> {
+    let mut doc = text.parse::<DocumentMut>().ok()?;
+    let (key, parents) = path.split_last()?;
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for parent in parents {
+        table = table.entry(parent).or_insert(toml_edit::table()).as_table_like_mut()?;
+    }
+    match value {
+        Some(value) => {
+            let mut value = to_toml(value)?;
+            match table.get_mut(key).and_then(Item::as_value_mut) {
+                Some(existing) => {
+                    *value.decor_mut() = existing.decor().clone();
+                    *existing = value;
+                }
+                None => {
+                    table.insert(key, toml_edit::value(value));
+                }
+            }
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+    Some(doc.to_string())
+}
+
+fn from_toml_table(table: &dyn TableLike) -> Mapping {
+    table.iter().filter_map(|(key, item)| Some((Value::String(key.to_string()), from_toml_item(item)?))).collect()
+}
+
+fn from_toml_item(item: &Item) -> Option<Value> {
+    match item {
+        Item::None => None,
+        Item::Value(value) => Some(from_toml_value(value)),
+        Item::Table(table) => Some(Value::Mapping(from_toml_table(table))),
+        Item::ArrayOfTables(tables) => Some(Value::Sequence(tables.iter().map(|t| Value::Mapping(from_toml_table(t))).collect())),
+    }
+}
+
+fn from_toml_value(value: &toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(s) => Value::String(s.value().clone()),
+        toml_edit::Value::Integer(i) => (*i.value()).into(),
+        toml_edit::Value::Float(f) => (*f.value()).into(),
+        toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+        toml_edit::Value::Datetime(d) => Value::String(d.value().to_string()),
+        toml_edit::Value::Array(items) => Value::Sequence(items.iter().map(from_toml_value).collect()),
+        toml_edit::Value::InlineTable(table) => Value::Mapping(from_toml_table(table)),
+    }
+}
+
+/// `None` for what TOML cannot represent: nulls, tagged values and
+/// non-string keys.
+fn to_toml(value: &Value) -> Option<toml_edit::Value> {
+    match value {
+        Value::Bool(b) => Some((*b).into()),
+        Value::Number(n) => n.as_i64().map(Into::into).or_else(|| n.as_f64().map(Into::into)),
+        Value::String(s) => Some(s.as_str().into()),
+        Value::Sequence(items) => items.iter().map(to_toml).collect::<Option<toml_edit::Array>>().map(Into::into),
+        Value::Mapping(mapping) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in mapping {
+                table.insert(key.as_str()?, to_toml(value)?);
+            }
+            Some(table.into())
+        }
+        Value::Null | Value::Tagged(_) => None,
+    }
+}
+
+/// Fills a TOML table, writing nested mappings as tables of their own.
+fn fill_toml_table(table: &mut toml_edit::Table, mapping: &Mapping) -> Option<()> {
+    for (key, value) in mapping {
+        let key = key.as_str()?;
+        match value {
+            Value::Mapping(child) => {
+                let mut child_table = toml_edit::Table::new();
+                fill_toml_table(&mut child_table, child)?;
+                table.insert(key, Item::Table(child_table));
+            }
+            _ => {
+                table.insert(key, toml_edit::value(to_toml(value)?));
+            }
+        }
+    }
+    Some(())
+}
+
+/// 1-based line and column of a byte offset.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Interprets a value typed on the command line the way YAML would, so
+/// `3` is a number, `true` a boolean and `[a, b]` a list. Anything that does
+/// not parse is kept as a plain string.
+pub fn parse_scalar(raw: &str) -> serde_json::Value {
+    if raw.trim().is_empty() {
+        return serde_json::Value::String(raw.to_string());
+    }
+    serde_yaml::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()))
+}
+
+fn to_yaml(value: &serde_json::Value) -> Result<Value, FrontmatterError> {
+    serde_yaml::to_value(value).map_err(|e| FrontmatterError(e.to_string()))
+}
+
+fn get_in<'a>(mapping: &'a Mapping, path: &[&str]) -> Option<&'a Value> {
+    let (key, rest) = path.split_first()?;
+    let value = mapping.get(*key)?;
+    if rest.is_empty() {
+        return Some(value);
+    }
+    match value {
+        Value::Mapping(child) => get_in(child, rest),
+        _ => None,
+    }
+}
+
+fn set_in(mapping: &mut Mapping, path: &[&str], value: Value) -> Result<(), FrontmatterError> {
+    let Some((key, rest)) = path.split_first() else {
+        return Err(FrontmatterError("empty frontmatter key".into()));
+    };
+    if rest.is_empty() {
+        mapping.insert(Value::String(key.to_string()), value);
+        return Ok(());
+    }
+
+    let child = mapping
+        .entry(Value::String(key.to_string()))
+        .or_insert_with(|| Value::Mapping(Mapping::new()));
+    if child.is_null() {
+        *child = Value::Mapping(Mapping::new());
+    }
+    match child {
+        Value::Mapping(child) => set_in(child, rest, value),
+        _ => Err(FrontmatterError(format!("`{}` is not a mapping", key))),
+    }
+}
+
+fn remove_in(mapping: &mut Mapping, path: &[&str]) -> Option<Value> {
+    let (key, rest) = path.split_first()?;
+    if rest.is_empty() {
+        return mapping.shift_remove(*key);
+    }
+    match mapping.get_mut(*key)? {
+        Value::Mapping(child) => remove_in(child, rest),
+        _ => None,
+    }
+}
+
+/// Renders `path: value` as nested block mappings, indented by `indent`.
+fn render_entry(path: &[&str], value: &Value, indent: usize) -> Option<String> {
+    let mut nested = value.clone();
+    for key in path.iter().rev() {
+        let mut mapping = Mapping::new();
+        mapping.insert(Value::String(key.to_string()), nested);
+        nested = Value::Mapping(mapping);
+    }
+    let rendered = serde_yaml::to_string(&nested).ok()?;
+    let pad = " ".repeat(indent);
+    Some(rendered.split_inclusive('\n').map(|line| format!("{pad}{line}")).collect())
+}
+
+/// Rewrites only the lines holding `path`, setting it to `value` or removing
+/// it when `value` is `None`.
+///
+/// Returns `None` when the layout is not something this line editor
+/// understands, e.g. a parent written as a flow mapping; the caller then
+/// re-serializes the whole block instead.
+fn edit_lines(yaml: &str, path: &[&str], value: Option<&Value>) -> Option<String> {
+    let lines: Vec<&str> = yaml.split_inclusive('\n').collect();
+    let (mut lo, mut hi, mut indent) = (0, lines.len(), 0);
+
+    for (depth, key) in path.iter().enumerate() {
+        let Some((start, end)) = find_entry(&lines, lo, hi, indent, key) else {
+            // Missing key: insert it, with any missing parents, at the end of
+            // the enclosing mapping.
+            let entry = render_entry(&path[depth..], value?, indent)?;
+            return Some(splice(&lines, hi, hi, &entry));
+        };
+
+        if depth + 1 == path.len() {
+            let entry = match value {
+                Some(value) => render_entry(&path[depth..], value, indent)?,
+                None => String::new(),
+            };
+            return Some(splice(&lines, start, end, &entry));
+        }
+
+        match child_indent(&lines, start + 1, end, indent) {
+            Some(child) => (lo, hi, indent) = (start + 1, end, child),
+            // `key:` with nothing after it is null; nest the rest beneath it.
+            None if lines[start].trim_end().ends_with(':') => {
+                let entry = render_entry(&path[depth + 1..], value?, indent + 2)?;
+                return Some(splice(&lines, end, end, &entry));
+            }
+            None => return None,
+        }
+    }
+    None
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_sequence_item(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed == "-" || trimmed.starts_with("- ")
+}
+
+/// Returns the key a mapping line starts, if any. `line` must already have
+/// its indentation stripped.
+fn line_key(line: &str) -> Option<&str> {
+    let line = line.trim_end();
+    let first = line.chars().next()?;
+    if first.is_whitespace() || first == '#' || is_sequence_item(line) {
+        return None;
+    }
+
+    if first == '"' || first == '\'' {
+        let close = line[1..].find(first)? + 1;
+        return line[close + 1..].starts_with(':').then(|| &line[1..close]);
+    }
+
+    let colon = line.find(": ").or_else(|| line.ends_with(':').then(|| line.len() - 1))?;
+    Some(line[..colon].trim_end())
+}
+
+/// Line range `[start, end)` holding `key` and its value among the entries
+/// at `indent` within `lines[lo..hi]`.
+///
+/// The value extends over more deeply indented lines and compact sequence
+/// items. Blank and comment lines only belong to it when more of the value
+/// follows, so comments above the next key stay with that key.
+fn find_entry(lines: &[&str], lo: usize, hi: usize, indent: usize, key: &str) -> Option<(usize, usize)> {
+    let start = (lo..hi).find(|&i| indent_of(lines[i]) == indent && line_key(&lines[i][indent..]) == Some(key))?;
+    let mut end = start + 1;
+    for (i, line) in lines.iter().enumerate().take(hi).skip(start + 1) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let line_indent = indent_of(line);
+        if line_indent > indent || (line_indent == indent && is_sequence_item(line)) {
+            end = i + 1;
+            continue;
+        }
+        break;
+    }
+    Some((start, end))
+}
+
+/// Indentation of the block mapping nested in `lines[lo..hi]`, if the value
+/// there is one.
+fn child_indent(lines: &[&str], lo: usize, hi: usize, parent: usize) -> Option<usize> {
+    let line = lines[lo..hi].iter().find(|line| {
+        let trimmed = line.trim();
+        !trimmed.is_empty() && !trimmed.starts_with('#')
+    })?;
+    let indent = indent_of(line);
+    (indent > parent && line_key(&line[indent..]).is_some()).then_some(indent)
+}
+
+/// Replaces lines `[start, end)`. Every line of a frontmatter block ends in a
+/// newline because the closing delimiter sits on its own line, and so does
+/// every rendered entry.
+fn splice(lines: &[&str], start: usize, end: usize, replacement: &str) -> String {
+    let mut out: String = lines[..start].concat();
+    out.push_str(replacement);
+    out.push_str(&lines[end..].concat());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_splits_blocks() {
+        let file = NoteFile::parse("---\ntitle: Hello\n---\n\n# Body\n");
+        assert_eq!(file.frontmatter(), Some("title: Hello\n"));
+        assert_eq!(file.body(), "# Body\n");
+
+        let file = NoteFile::parse("# Just a body\n---\nwith a rule");
+        assert_eq!(file.frontmatter(), None);
+        assert_eq!(file.body(), "# Just a body\n---\nwith a rule");
+
+        // An unclosed block is not frontmatter.
+        let file = NoteFile::parse("---\ntitle: Hello\nno close");
+        assert_eq!(file.frontmatter(), None);
+    }
+
+    #[test]
+    fn test_set_body_keeps_frontmatter_bytes() {
+        let original = "---\n# keep me\ntitle:   'Quoted'\ntags: [b, a]\n---\n\nOld body\n";
+        let mut file = NoteFile::parse(original);
+        file.set_body("New body\n");
+        assert_eq!(file.to_string(), "---\n# keep me\ntitle:   'Quoted'\ntags: [b, a]\n---\n\nNew body\n");
+
+        let mut file = NoteFile::parse("No frontmatter");
+        file.set_body("Still none");
+        assert_eq!(file.to_string(), "Still none");
+    }
+
+    #[test]
+    fn test_set_rewrites_only_that_key() {
+        let mut file = NoteFile::parse(
+            "---\n# comment\ntitle: 'Old'  # trailing\ntags:\n- a\n- b\n\n# about status\nstatus: draft\n---\nBody",
+        );
+        file.set(&["tags"], &serde_yaml::to_value(["x"]).unwrap()).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "---\n# comment\ntitle: 'Old'  # trailing\ntags:\n- x\n\n# about status\nstatus: draft\n---\nBody"
+        );
+
+        file.set(&["status"], &Value::String("done".into())).unwrap();
+        file.set(&["priority"], &Value::Number(2.into())).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "---\n# comment\ntitle: 'Old'  # trailing\ntags:\n- x\n\n# about status\nstatus: done\npriority: 2\n---\nBody"
+        );
+
+        assert!(file.remove(&["tags"]).unwrap());
+        assert!(!file.remove(&["tags"]).unwrap());
+        assert_eq!(
+            file.to_string(),
+            "---\n# comment\ntitle: 'Old'  # trailing\n\n# about status\nstatus: done\npriority: 2\n---\nBody"
+        );
+    }
+
+    #[test]
+    fn test_set_nested_paths() {
+        let mut file = NoteFile::parse(
+            "---\ntitle: Plan\nmetadata:\n  # workflow\n  status: draft\n  owner: 'sam'\nafter: 1\n---\nBody",
+        );
+        file.set(&["metadata", "status"], &Value::String("done".into())).unwrap();
+        file.set(&["metadata", "review", "by"], &Value::String("kim".into())).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "---\ntitle: Plan\nmetadata:\n  # workflow\n  status: done\n  owner: 'sam'\n  review:\n    by: kim\nafter: 1\n---\nBody"
+        );
+
+        assert!(file.remove(&["metadata", "owner"]).unwrap());
+        assert!(!file.remove(&["metadata", "missing"]).unwrap());
+        file.set(&["new", "deep"], &Value::Bool(true)).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "---\ntitle: Plan\nmetadata:\n  # workflow\n  status: done\n  review:\n    by: kim\nafter: 1\nnew:\n  deep: true\n---\nBody"
+        );
+        assert_eq!(file.get(&["metadata", "review", "by"]).unwrap(), Some(Value::String("kim".into())));
+
+        // Cannot nest under a scalar.
+        assert!(file.set(&["title", "sub"], &Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_apply_list_edits() {
+        let mut file = NoteFile::parse("---\ntitle: T\ntags: solo\n---\nBody");
+        file.apply(&MetadataEdit::Append { key: "tags".into(), value: "rust".into() }).unwrap();
+        file.apply(&MetadataEdit::Append { key: "tags".into(), value: "rust".into() }).unwrap();
+        assert_eq!(file.to_string(), "---\ntitle: T\ntags:\n- solo\n- rust\n---\nBody");
+
+        file.apply(&MetadataEdit::Remove { key: "tags".into(), value: "solo".into() }).unwrap();
+        file.apply(&MetadataEdit::Remove { key: "tags".into(), value: "rust".into() }).unwrap();
+        assert_eq!(file.to_string(), "---\ntitle: T\n---\nBody");
+
+        file.apply(&MetadataEdit::Set { key: "meta.priority".into(), value: 3.into() }).unwrap();
+        file.apply(&MetadataEdit::Unset { key: "title".into() }).unwrap();
+        assert_eq!(file.to_string(), "---\nmeta:\n  priority: 3\n---\nBody");
+    }
+
+    #[test]
+    fn test_parse_scalar() {
+        assert_eq!(parse_scalar("3"), serde_json::json!(3));
+        assert_eq!(parse_scalar("true"), serde_json::json!(true));
+        assert_eq!(parse_scalar("[a, b]"), serde_json::json!(["a", "b"]));
+        assert_eq!(parse_scalar("hello world"), serde_json::json!("hello world"));
+        assert_eq!(parse_scalar(""), serde_json::json!(""));
+        assert_eq!(parse_scalar("a: b: c"), serde_json::json!("a: b: c"));
+    }
+
+    #[test]
+    fn test_set_adds_block_when_missing() {
+        let mut file = NoteFile::parse("Body only\n");
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        assert_eq!(file.to_string(), "---\ntitle: New\n---\nBody only\n");
+    }
+
+    #[test]
+    fn test_set_falls_back_for_flow_mappings() {
+        let mut file = NoteFile::parse("---\n{title: Old, tags: [a]}\n---\nBody");
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        let yaml: Value = serde_yaml::from_str(file.frontmatter().unwrap()).unwrap();
+        assert_eq!(yaml["title"], Value::String("New".into()));
+        assert_eq!(yaml["tags"][0], Value::String("a".into()));
+        assert!(file.to_string().ends_with("---\nBody"));
+    }
+
+    #[test]
+    fn test_set_refuses_invalid_yaml() {
+        let mut file = NoteFile::parse("---\ntitle: : bad\n---\nBody");
+        assert!(file.set(&["title"], &Value::String("New".into())).is_err());
+        assert_eq!(file.to_string(), "---\ntitle: : bad\n---\nBody");
+    }
+
+    #[test]
+    fn test_toml_blocks() {
+        let original = "+++\n# Hugo\ntitle = 'Old' # keep\ndate = 2024-01-01T10:00:00Z\n\n[params]\nstatus = \"draft\"\n+++\n\nBody\n";
+        let mut file = NoteFile::parse(original);
+        assert_eq!(file.format(), Some(FrontmatterFormat::Toml));
+        assert_eq!(file.body(), "Body\n");
+        assert_eq!(file.to_string(), original);
+        assert_eq!(file.get(&["date"]).unwrap(), Some(Value::String("2024-01-01T10:00:00Z".into())));
+        assert_eq!(file.get(&["params", "status"]).unwrap(), Some(Value::String("draft".into())));
+
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        file.set(&["params", "status"], &Value::String("done".into())).unwrap();
+        file.apply(&MetadataEdit::Append { key: "tags".into(), value: "rust".into() }).unwrap();
+        assert!(file.remove(&["date"]).unwrap());
+        assert_eq!(
+            file.to_string(),
+            "+++\n# Hugo\ntitle = \"New\" # keep\ntags = [\"rust\"]\n\n[params]\nstatus = \"done\"\n+++\n\nBody\n"
+        );
+
+        // TOML has no null.
+        assert!(file.set(&["title"], &Value::Null).is_err());
+        assert!(NoteFile::parse("+++\ntitle = \n+++\nBody").set(&["title"], &Value::String("x".into())).is_err());
+    }
+
+    #[test]
+    fn test_json_blocks() {
+        let original = "{\n  \"title\": \"Old\",\n  \"draft\": true\n}\n\nBody {not json}\n";
+        let mut file = NoteFile::parse(original);
+        assert_eq!(file.format(), Some(FrontmatterFormat::Json));
+        assert_eq!(file.frontmatter(), Some("{\n  \"title\": \"Old\",\n  \"draft\": true\n}"));
+        assert_eq!(file.body(), "Body {not json}\n");
+        assert_eq!(file.to_string(), original);
+
+        // Keys keep their order.
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        file.set(&["tags"], &serde_yaml::to_value(["a"]).unwrap()).unwrap();
+        assert_eq!(file.to_string(), "{\n  \"title\": \"New\",\n  \"draft\": true,\n  \"tags\": [\n    \"a\"\n  ]\n}\n\nBody {not json}\n");
+
+        for text in ["{not json}\nBody", "{\"a\": 1} trailing\nBody", "[1, 2]\nBody"] {
+            assert_eq!(NoteFile::parse(text).format(), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_added_block_uses_default_format() {
+        let mut file = NoteFile::parse("Body\n").with_default_format(FrontmatterFormat::Toml);
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        assert_eq!(file.to_string(), "+++\ntitle = \"New\"\n+++\nBody\n");
+
+        let mut file = NoteFile::parse("Body\n").with_default_format(FrontmatterFormat::Json);
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        assert_eq!(file.to_string(), "{\n  \"title\": \"New\"\n}\nBody\n");
+
+        // An existing block keeps its format.
+        let mut file = NoteFile::parse("---\ntitle: Old\n---\nBody").with_default_format(FrontmatterFormat::Toml);
+        file.set(&["title"], &Value::String("New".into())).unwrap();
+        assert_eq!(file.to_string(), "---\ntitle: New\n---\nBody");
+    }
+
+    fn yaml_line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            ("[a-z_]{1,8}", "[ -~&&[^:#'\"]]{0,12}").prop_map(|(k, v)| format!("{k}: {v}")),
+            "[a-z]{1,8}".prop_map(|k| format!("'{k}': \"quoted\"")),
+            "[ -~]{0,16}".prop_map(|c| format!("# {c}")),
+            Just("list:\n- one\n  - two".to_string()),
+            Just(String::new()),
+        ]
+    }
+
+    fn note() -> impl Strategy<Value = String> {
+        (
+            proptest::option::of(proptest::collection::vec(yaml_line(), 0..6)),
+            "(\r?\n){0,2}",
+            any::<String>(),
+        )
+            .prop_map(|(frontmatter, gap, body)| match frontmatter {
+                Some(lines) => format!("---\n{}---\n{gap}{body}", lines.iter().map(|l| format!("{l}\n")).collect::<String>()),
+                None => format!("{gap}{body}"),
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_load_save_is_identity(text in note()) {
+            prop_assert_eq!(NoteFile::parse(&text).to_string(), text);
+        }
+
+        #[test]
+        fn prop_arbitrary_text_is_identity(text in any::<String>()) {
+            prop_assert_eq!(NoteFile::parse(&text).to_string(), text);
+        }
+
+        #[test]
+        fn prop_body_edit_keeps_frontmatter(text in note(), body in any::<String>()) {
+            let original = NoteFile::parse(&text);
+            let mut edited = original.clone();
+            edited.set_body(&body);
+            prop_assert_eq!(edited.frontmatter(), original.frontmatter());
+            let rendered = edited.to_string();
+            prop_assert!(rendered.ends_with(&body));
+            let prefix = &text[..text.len() - original.body().len()];
+            prop_assert!(rendered.starts_with(prefix));
+        }
+    }
+}
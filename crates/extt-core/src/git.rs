@@ -0,0 +1,230 @@
+//! Commits every change made through the store to the git repository the
+//! vault lives in.
+//!
+//! Only the notes a change touched are committed, built on top of `HEAD`, so
+//! whatever else the user has modified or staged stays out of extt's commits.
+//! `.extt` is added to the repository's `info/exclude` so extt's own history
+//! and trash never show up as untracked files.
+
+use crate::error::Result;
+use crate::types::{Author, GitCommit, GitOptions};
+use crate::vault::META_DIR;
+use git2::build::TreeUpdateBuilder;
+use git2::{FileMode, Oid, Repository, Signature, Sort};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name and email of commits made with [`Author::Agent`].
+const AGENT: (&str, &str) = ("extt agent", "agent@extt.invalid");
+/// Used when the repository has no `user.name` / `user.email` configured.
+const FALLBACK: (&str, &str) = ("extt", "extt@localhost");
+
+pub(crate) struct Git {
+    repo: Repository,
+    /// The vault root relative to the repository's working directory.
+    prefix: PathBuf,
+    root: PathBuf,
+    options: GitOptions,
+}
+
+impl Git {
+    /// Opens the repository containing `root`, or creates one there.
+    pub(crate) fn open(root: &Path, options: GitOptions) -> Result<Self> {
+        let repo = match Repository::discover(root) {
+            Ok(repo) if repo.workdir().is_some() => repo,
+            _ => Repository::init(root)?,
+        };
+        let workdir = fs::canonicalize(repo.workdir().unwrap_or(root))?;
+        let prefix = fs::canonicalize(root)?.strip_prefix(&workdir).map(Path::to_path_buf).unwrap_or_default();
+        let git = Self { repo, prefix, root: root.to_path_buf(), options };
+        git.exclude_meta_dir()?;
+        Ok(git)
+    }
+
+    fn exclude_meta_dir(&self) -> Result<()> {
+        let pattern = format!("/{}\n", self.prefix.join(META_DIR).to_string_lossy().replace('\\', "/"));
+        let path = self.repo.path().join("info").join("exclude");
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        if existing.lines().any(|line| format!("{line}\n") == pattern) {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        if !existing.is_empty() && !existing.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+        file.write_all(pattern.as_bytes())?;
+        Ok(())
+    }
+
+    /// Commits the current state of `paths` (relative to the vault) on top of
+    /// `HEAD`. Paths that no longer exist are removed. Does nothing if none
+    /// of them changed.
+    pub(crate) fn commit(&self, message: &str, paths: &[&Path]) -> Result<()> {
+        let parent = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(err) if err.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(err) => return Err(err.into()),
+        };
+        let base = match &parent {
+            Some(commit) => commit.tree()?,
+            None => self.repo.find_tree(self.repo.treebuilder(None)?.write()?)?,
+        };
+
+        let mut index = self.repo.index()?;
+        let mut update = TreeUpdateBuilder::new();
+        for path in paths {
+            let in_repo = self.prefix.join(path);
+            let file = self.root.join(path);
+            if file.is_file() {
+                update.upsert(in_repo.as_path(), self.repo.blob_path(&file)?, FileMode::Blob);
+                index.add_path(&in_repo)?;
+            } else {
+                update.remove(in_repo.as_path());
+                // Not being in the index is fine: it was never committed.
+                let _ = index.remove_path(&in_repo);
+            }
+        }
+        let tree = self.repo.find_tree(update.create_updated(&self.repo, &base)?)?;
+        if tree.id() == base.id() {
+            return Ok(());
+        }
+        // Keep the index in step so `git status` does not show extt's
+        // changes as staged reversals.
+        index.write()?;
+
+        let signature = self.signature()?;
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+        if self.options.push {
+            self.push()?;
+        }
+        Ok(())
+    }
+
+    fn signature(&self) -> Result<Signature<'static>> {
+        let signature = match self.options.author {
+            Author::Agent => Signature::now(AGENT.0, AGENT.1)?,
+            Author::Human => match self.repo.signature() {
+                Ok(signature) => signature,
+                Err(_) => Signature::now(FALLBACK.0, FALLBACK.1)?,
+            },
+        };
+        Ok(signature)
+    }
+
+    fn push(&self) -> Result<()> {
+        let head = self.repo.head()?;
+        let Some(branch) = head.name() else {
+            return Ok(());
+        };
+        let mut remote = self.repo.find_remote("origin")?;
+        remote.push(&[format!("{branch}:{branch}")], None)?;
+        Ok(())
+    }
+
+    /// Commits on `HEAD`, newest first; with `path`, only those that changed
+    /// that note.
+    pub(crate) fn log(&self, path: Option<&Path>) -> Result<Vec<GitCommit>> {
+        match self.repo.head() {
+            Ok(_) => {}
+            Err(err) if err.code() == git2::ErrorCode::UnbornBranch => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        }
+        let mut walk = self.repo.revwalk()?;
+        walk.push_head()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+        let in_repo = path.map(|p| self.prefix.join(p));
+        let mut commits = Vec::new();
+        for id in walk {
+            let commit = self.repo.find_commit(id?)?;
+            if let Some(in_repo) = &in_repo {
+                let blob = |tree: git2::Tree| tree.get_path(in_repo).ok().map(|entry| entry.id());
+                let before: Option<Oid> = match commit.parent(0) {
+                    Ok(parent) => blob(parent.tree()?),
+                    Err(_) => None,
+                };
+                if blob(commit.tree()?) == before {
+                    continue;
+                }
+            }
+            let author = commit.author();
+            commits.push(GitCommit {
+                id: commit.id().to_string(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                author: author.name().unwrap_or_default().to_string(),
+                email: author.email().unwrap_or_default().to_string(),
+                time: commit.time().seconds(),
+            });
+        }
+        Ok(commits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(author: Author, push: bool) -> GitOptions {
+        GitOptions { author, push }
+    }
+
+    #[test]
+    fn test_commit_only_touched_paths() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("notes");
+        fs::create_dir_all(root.join(META_DIR))?;
+        fs::write(root.join(META_DIR).join("history.log"), "ignored")?;
+        Repository::init(dir.path())?;
+        let git = Git::open(&root, options(Author::Agent, false))?;
+        assert_eq!(git.prefix, Path::new("notes"));
+        assert!(git.log(None)?.is_empty());
+
+        fs::write(root.join("a.md"), "one")?;
+        fs::write(root.join("untouched.md"), "not mine")?;
+        git.commit("create: a.md", &[Path::new("a.md")])?;
+        // Nothing changed, so no commit.
+        git.commit("update: a.md", &[Path::new("a.md")])?;
+        fs::remove_file(root.join("a.md"))?;
+        git.commit("delete: a.md", &[Path::new("a.md")])?;
+
+        let log = git.log(None)?;
+        assert_eq!(log.iter().map(|c| c.summary.as_str()).collect::<Vec<_>>(), ["delete: a.md", "create: a.md"]);
+        assert_eq!(log[0].author, AGENT.0);
+        let head = git.repo.head()?.peel_to_tree()?;
+        assert!(head.get_path(Path::new("notes/untouched.md")).is_err());
+        assert_eq!(git.log(Some(Path::new("untouched.md")))?.len(), 0);
+
+        let status = git.repo.statuses(None)?;
+        let untracked: Vec<_> = status
+            .iter()
+            .filter(|s| s.status().is_wt_new())
+            .filter_map(|s| s.path().map(str::to_string))
+            .collect();
+        assert_eq!(untracked, ["notes/untouched.md"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_to_bare_remote() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let bare = Repository::init_bare(dir.path().join("remote.git"))?;
+        let root = dir.path().join("notes");
+        let repo = Repository::init(&root)?;
+        repo.remote("origin", &dir.path().join("remote.git").to_string_lossy())?;
+
+        let git = Git::open(&root, options(Author::Human, true))?;
+        fs::write(root.join("a.md"), "one")?;
+        git.commit("create: a.md", &[Path::new("a.md")])?;
+
+        let branch = git.repo.head()?.name().unwrap().to_string();
+        let pushed = bare.find_reference(&branch)?.peel_to_commit()?;
+        assert_eq!(pushed.summary(), Some("create: a.md"));
+        assert_eq!(pushed.id().to_string(), git.log(Some(Path::new("a.md")))?[0].id);
+        Ok(())
+    }
+}
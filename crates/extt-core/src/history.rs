@@ -0,0 +1,269 @@
+//! Per-note version history under `.extt/history`.
+//!
+//! Every version of a note that extt writes or overwrites is kept once,
+//! zlib-compressed, as `objects/<2 hex>/<rest of hash>`, named by its content
+//! hash (the note's revision). `log.jsonl` records which note had which
+//! version and when, one [`HistoryEntry`] per line, oldest first.
+//! `heads/<hash of path>` holds the latest revision of each note, so saving a
+//! version does not have to read the log.
+
+use crate::error::{Error, Result};
+use crate::store::content_hash;
+use crate::timestamps::now;
+use crate::types::HistoryEntry;
+use crate::vault::META_DIR;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Shortest revision prefix accepted by [`find`].
+pub(crate) const MIN_PREFIX: usize = 4;
+
+fn dir(root: &Path) -> PathBuf {
+    root.join(META_DIR).join("history")
+}
+
+fn object_path(root: &Path, revision: &str) -> PathBuf {
+    dir(root).join("objects").join(&revision[..2]).join(&revision[2..])
+}
+
+fn heads_dir(root: &Path) -> PathBuf {
+    dir(root).join("heads")
+}
+
+/// Where the head of `relative_path` is kept, building the heads first if
+/// the log predates them.
+fn head_path(root: &Path, relative_path: &Path) -> Result<PathBuf> {
+    if !heads_dir(root).exists() {
+        build_heads(root)?;
+    }
+    Ok(heads_dir(root).join(content_hash(relative_path.to_string_lossy().as_bytes())))
+}
+
+/// Saves `bytes` as the current version of `relative_path`, unless it
+/// already is.
+pub(crate) fn record(root: &Path, relative_path: &Path, bytes: &[u8]) -> Result<()> {
+    let revision = content_hash(bytes);
+    let head = head_path(root, relative_path)?;
+    if read_head(&head)?.as_deref() == Some(revision.as_str()) {
+        return Ok(());
+    }
+    save(root, &revision, bytes)?;
+    append(root, &new_entry(revision.clone(), relative_path, bytes, None))?;
+    fs::write(head, revision)?;
+    Ok(())
+}
+
+/// Notes that `from` became `to`, so the history of `to` continues with
+/// that of `from`.
+pub(crate) fn record_move(root: &Path, from: &Path, to: &Path, bytes: &[u8]) -> Result<()> {
+    let revision = content_hash(bytes);
+    let (from_head, to_head) = (head_path(root, from)?, head_path(root, to)?);
+    save(root, &revision, bytes)?;
+    append(root, &new_entry(revision.clone(), to, bytes, Some(from)))?;
+    fs::write(to_head, revision)?;
+    // Whatever is created at `from` next starts a history of its own.
+    match fs::remove_file(from_head) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The revision a head file holds, the same as `entries(..).first()` for
+/// its note.
+fn read_head(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(revision) => Ok(Some(revision)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes the heads of a log kept before they existed, replaying it once.
+fn build_heads(root: &Path) -> Result<()> {
+    let mut heads = HashMap::new();
+    for entry in read_log(root)? {
+        if let Some(from) = &entry.moved_from {
+            heads.remove(from);
+        }
+        heads.insert(entry.path, entry.revision);
+    }
+    // Built aside and renamed into place so a crash cannot leave a partial
+    // set that would be taken as complete.
+    let temp = dir(root).join("heads.tmp");
+    if temp.exists() {
+        fs::remove_dir_all(&temp)?;
+    }
+    fs::create_dir_all(&temp)?;
+    for (path, revision) in heads {
+        fs::write(temp.join(content_hash(path.to_string_lossy().as_bytes())), revision)?;
+    }
+    fs::rename(&temp, heads_dir(root))?;
+    Ok(())
+}
+
+fn new_entry(revision: String, path: &Path, bytes: &[u8], moved_from: Option<&Path>) -> HistoryEntry {
+    HistoryEntry {
+        revision,
+        path: path.to_path_buf(),
+        saved_at: now(),
+        size: bytes.len() as u64,
+        moved_from: moved_from.map(Path::to_path_buf),
+    }
+}
+
+/// Versions of the note at `relative_path`, newest first, following it back
+/// through moves.
+pub(crate) fn entries(root: &Path, relative_path: &Path) -> Result<Vec<HistoryEntry>> {
+    let mut current = relative_path.to_path_buf();
+    let mut found = Vec::new();
+    for entry in read_log(root)?.into_iter().rev() {
+        if entry.path != current {
+            // Before this, whatever lived at `current` was another note.
+            if entry.moved_from.as_ref() == Some(&current) {
+                break;
+            }
+            continue;
+        }
+        if let Some(from) = &entry.moved_from {
+            current = from.clone();
+        }
+        found.push(entry);
+    }
+    Ok(found)
+}
+
+/// The version of `relative_path` whose revision starts with `revision`.
+pub(crate) fn find(root: &Path, relative_path: &Path, revision: &str) -> Result<HistoryEntry> {
+    let unknown = || Error::UnknownRevision { path: relative_path.to_path_buf(), revision: revision.to_string() };
+    if revision.len() < MIN_PREFIX {
+        return Err(unknown());
+    }
+    let mut matches = entries(root, relative_path)?.into_iter().filter(|e| e.revision.starts_with(revision));
+    let first = matches.next().ok_or_else(unknown)?;
+    // A prefix shared by two different versions does not say which one.
+    if matches.any(|e| e.revision != first.revision) {
+        return Err(unknown());
+    }
+    Ok(first)
+}
+
+/// Content of a saved version.
+pub(crate) fn load(root: &Path, revision: &str) -> Result<Vec<u8>> {
+    let compressed = fs::read(object_path(root, revision))?;
+    let mut bytes = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut bytes)?;
+    if content_hash(&bytes) != revision {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, format!("history object {revision} is corrupt")).into());
+    }
+    Ok(bytes)
+}
+
+fn save(root: &Path, revision: &str, bytes: &[u8]) -> Result<()> {
+    let path = object_path(root, revision);
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    // Written under another name first so a crash never leaves a truncated
+    // object behind the real one.
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, encoder.finish()?)?;
+    fs::rename(&temp, &path)?;
+    Ok(())
+}
+
+fn append(root: &Path, entry: &HistoryEntry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry).map_err(std::io::Error::other)?;
+    line.push(b'\n');
+    let mut log = OpenOptions::new().create(true).append(true).open(dir(root).join("log.jsonl"))?;
+    log.write_all(&line)?;
+    Ok(())
+}
+
+fn read_log(root: &Path) -> Result<Vec<HistoryEntry>> {
+    let text = match fs::read_to_string(dir(root).join("log.jsonl")) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    // A line cut short by a crash mid-append is skipped, not fatal.
+    Ok(text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_dedupes_and_follows_moves() -> Result<()> {
+        let vault = tempfile::tempdir()?;
+        let root = vault.path();
+        let (a, b) = (Path::new("a.md"), Path::new("dir/b.md"));
+
+        record(root, a, b"one")?;
+        record(root, a, b"one")?;
+        record(root, a, b"two")?;
+        record_move(root, a, b, b"two")?;
+        record(root, b, b"three")?;
+        record(root, a, b"someone else")?;
+
+        let revisions: Vec<Vec<u8>> =
+            entries(root, b)?.iter().map(|e| load(root, &e.revision)).collect::<Result<_>>()?;
+        assert_eq!(revisions, [&b"three"[..], b"two", b"two", b"one"]);
+        assert_eq!(entries(root, a)?.len(), 1);
+
+        let one = content_hash(b"one");
+        assert_eq!(find(root, b, &one[..8])?.revision, one);
+        assert!(matches!(find(root, b, &one[..2]), Err(Error::UnknownRevision { .. })));
+        assert!(matches!(find(root, a, &one), Err(Error::UnknownRevision { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heads_are_built_from_the_log() -> Result<()> {
+        let vault = tempfile::tempdir()?;
+        let root = vault.path();
+        let (a, b) = (Path::new("a.md"), Path::new("b.md"));
+
+        record(root, a, b"one")?;
+        record(root, b, b"other")?;
+        record(root, a, b"one")?;
+        record_move(root, a, b, b"one")?;
+        assert_eq!(entries(root, b)?.len(), 2);
+
+        // A log from before heads existed gives the same answers.
+        fs::remove_dir_all(heads_dir(root))?;
+        record(root, b, b"one")?;
+        record(root, a, b"one")?;
+        assert_eq!(entries(root, b)?.len(), 2);
+        assert_eq!(entries(root, a)?.len(), 1);
+        assert_eq!(read_log(root)?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_objects_are_compressed_once() -> Result<()> {
+        let vault = tempfile::tempdir()?;
+        let root = vault.path();
+        let text = "same line\n".repeat(1000);
+
+        record(root, Path::new("a.md"), text.as_bytes())?;
+        record(root, Path::new("b.md"), text.as_bytes())?;
+
+        let revision = content_hash(text.as_bytes());
+        let stored = fs::metadata(object_path(root, &revision))?.len();
+        assert!(stored < text.len() as u64 / 10);
+        assert_eq!(fs::read_dir(dir(root).join("objects"))?.count(), 1);
+        assert_eq!(load(root, &revision)?, text.as_bytes());
+        Ok(())
+    }
+}
@@ -1,9 +1,23 @@
+pub mod error;
+mod atomic;
+mod body;
 pub mod vault;
-pub mod document;
+pub mod frontmatter;
+mod git;
+mod history;
+mod links;
+mod lock;
+mod parse;
 pub mod store;
 pub mod types;
+pub mod migrations;
+pub mod tags;
+pub mod timestamps;
+mod trash;
+pub mod watcher;
 
+pub use error::{Error, Result};
 pub use vault::Vault;
-pub use document::Document;
 pub use store::Store;
+pub use watcher::Watcher;
 pub use types::{Note, Metadata};
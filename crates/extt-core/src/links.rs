@@ -0,0 +1,345 @@
+//! Links between notes: `[[wikilinks]]` and relative markdown links.
+//!
+//! Wikilinks name a note the way Obsidian does. `[[Plan]]` matches any
+//! `Plan.md` in the vault, preferring one next to the linking note and then
+//! the shortest path. `[[work/Plan]]` matches by path from the vault root,
+//! or failing that by path suffix. Markdown links only count when they point
+//! at a `.md` file, and resolve relative to the linking note (or the vault
+//! root when they start with `/`). Matching ignores case.
+//!
+//! Links inside code blocks and code spans are ignored.
+
+use crate::body::prose_lines;
+use crate::types::LinkKind;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A link found in a note body, before resolution.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ParsedLink {
+    pub kind: LinkKind,
+    /// 1-based body line.
+    pub line: usize,
+    /// Target without alias or `#heading`, percent-decoded for markdown links.
+    pub target: String,
+    /// Byte range of the target as written in the body.
+    pub span: Range<usize>,
+}
+
+/// Every link in `body`, in reading order.
+pub(crate) fn parse(body: &str) -> Vec<ParsedLink> {
+    let mut links = Vec::new();
+    for (index, offset, line) in prose_lines(body) {
+        scan_line(line, index + 1, offset, &mut links);
+    }
+    links
+}
+
+fn scan_line(line: &str, number: usize, offset: usize, links: &mut Vec<ParsedLink>) {
+    let bytes = line.as_bytes();
+    let mut in_code = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'`' => in_code = !in_code,
+            b'[' if !in_code && bytes.get(i + 1) == Some(&b'[') => {
+                let inner = &line[i + 2..];
+                if let Some(end) = inner.find("]]") {
+                    let range = wiki_target(&inner[..end]);
+                    if !range.is_empty() {
+                        let target = inner[range.clone()].to_string();
+                        let start = offset + i + 2;
+                        let span = start + range.start..start + range.end;
+                        links.push(ParsedLink { kind: LinkKind::Wiki, line: number, target, span });
+                    }
+                    i += end + 4;
+                    continue;
+                }
+            }
+            b']' if !in_code && bytes.get(i + 1) == Some(&b'(') => {
+                let rest = &line[i + 2..];
+                if let Some((range, len)) = destination(rest) {
+                    if let Some((target, path_len)) = markdown_target(&rest[range.clone()]) {
+                        let start = offset + i + 2 + range.start;
+                        let span = start..start + path_len;
+                        links.push(ParsedLink { kind: LinkKind::Markdown, line: number, target, span });
+                    }
+                    i += len + 2;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Where `Note` is in `Note#Heading|Alias`, without surrounding spaces.
+fn wiki_target(inner: &str) -> Range<usize> {
+    let page = inner.split(['|', '#']).next().unwrap_or_default();
+    let start = page.len() - page.trim_start().len();
+    start..page.trim_end().len().max(start)
+}
+
+/// Where the destination of an inline link is in `rest`, the text right
+/// after `](`, and the number of bytes up to and including the closing `)`.
+fn destination(rest: &str) -> Option<(Range<usize>, usize)> {
+    if let Some(inner) = rest.strip_prefix('<') {
+        let end = inner.find('>')?;
+        let close = inner[end..].find(')')?;
+        return Some((1..1 + end, 1 + end + close + 1));
+    }
+    let mut depth = 0usize;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                // A link title may follow the destination: `(a.md "Title")`.
+                let inner = &rest[..i];
+                let start = inner.len() - inner.trim_start().len();
+                let len = inner[start..].find(char::is_whitespace).unwrap_or(inner.len() - start);
+                return Some((start..start + len, i + 1));
+            }
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The note path a markdown link destination points at, if it is a
+/// relative link to a `.md` file, and the length of that path as written.
+fn markdown_target(destination: &str) -> Option<(String, usize)> {
+    let path = destination.split(['#', '?']).next().unwrap_or_default();
+    let has_scheme = path
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)));
+    if has_scheme || !path.to_ascii_lowercase().ends_with(".md") {
+        return None;
+    }
+    Some((percent_decode(path)?, path.len()))
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%').then(|| text.get(i + 1..i + 3)).flatten();
+        if let Some(byte) = escaped.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Resolves link targets against the set of indexed note paths.
+pub(crate) struct Resolver {
+    /// Lowercased path to path.
+    paths: HashMap<String, String>,
+    /// Lowercased file stem to the paths with that stem.
+    by_name: HashMap<String, Vec<String>>,
+}
+
+impl Resolver {
+    pub fn new(paths: impl IntoIterator<Item = String>) -> Self {
+        let mut resolver = Resolver { paths: HashMap::new(), by_name: HashMap::new() };
+        for path in paths {
+            let name = file_name(&path);
+            let stem = name.strip_suffix(".md").unwrap_or(name);
+            resolver.by_name.entry(stem.to_lowercase()).or_default().push(path.clone());
+            resolver.paths.insert(path.to_lowercase(), path);
+        }
+        resolver
+    }
+
+    /// The note `target` points at when written in `source`.
+    pub fn resolve(&self, source: &str, kind: LinkKind, target: &str) -> Option<&str> {
+        match kind {
+            LinkKind::Wiki => self.resolve_wiki(source, target),
+            LinkKind::Markdown => {
+                let base = if target.starts_with('/') { "" } else { parent(source) };
+                let path = normalize(base, target)?;
+                self.paths.get(&path.to_lowercase()).map(String::as_str)
+            }
+        }
+    }
+
+    fn resolve_wiki(&self, source: &str, target: &str) -> Option<&str> {
+        let key = target.trim_start_matches('/').to_lowercase();
+        let key = key.strip_suffix(".md").unwrap_or(&key);
+        if key.contains('/') {
+            let path = format!("{key}.md");
+            if let Some(found) = self.paths.get(&path) {
+                return Some(found);
+            }
+            let suffix = format!("/{path}");
+            return self
+                .paths
+                .iter()
+                .filter(|(lower, _)| lower.ends_with(&suffix))
+                .map(|(_, path)| path.as_str())
+                .min_by_key(|path| (path.len(), *path));
+        }
+
+        let candidates = self.by_name.get(key)?;
+        let dir = parent(source);
+        candidates
+            .iter()
+            .find(|path| parent(path) == dir)
+            .or_else(|| candidates.iter().min_by_key(|path| (path.len(), path.as_str())))
+            .map(String::as_str)
+    }
+}
+
+/// Target text that, written in `source`, points at note `to`. `written` is
+/// the old target as it appears in the file, whose style is kept: wikilinks
+/// stay short names where that is unambiguous, and markdown links stay
+/// relative or root-based.
+pub(crate) fn retarget(kind: LinkKind, written: &str, source: &str, to: &str, resolver: &Resolver) -> String {
+    let keep_extension = written.to_ascii_lowercase().ends_with(".md");
+    let extension = if keep_extension { ".md" } else { "" };
+    match kind {
+        LinkKind::Wiki => {
+            let path = to.strip_suffix(".md").unwrap_or(to);
+            let name = file_name(path);
+            if !written.contains('/') && resolver.resolve(source, kind, name) == Some(to) {
+                format!("{name}{extension}")
+            } else {
+                format!("{path}{extension}")
+            }
+        }
+        LinkKind::Markdown => {
+            let path = if written.starts_with('/') { format!("/{to}") } else { relative(parent(source), to) };
+            // A literal space means the destination was in `<...>`; otherwise
+            // it has to stay a single percent-encoded word.
+            if written.contains(' ') {
+                path
+            } else {
+                path.replace('%', "%25").replace(' ', "%20")
+            }
+        }
+    }
+}
+
+/// Path of `to` relative to directory `dir`, both from the vault root.
+fn relative(dir: &str, to: &str) -> String {
+    let dir: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    let to: Vec<&str> = to.split('/').collect();
+    let common = dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    // Never share the file name itself.
+    let common = common.min(to.len() - 1);
+    let mut parts = vec![".."; dir.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Directory part of a vault path, without the trailing `/`.
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Joins `target` onto directory `base` and resolves `.` and `..`, or
+/// `None` if the result would leave the vault.
+fn normalize(base: &str, target: &str) -> Option<String> {
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(body: &str) -> Vec<(LinkKind, usize, String)> {
+        parse(body).into_iter().map(|l| (l.kind, l.line, l.target)).collect()
+    }
+
+    #[test]
+    fn test_parse_links() {
+        let body = "\
+See [[Plan]] and [[work/Plan#Goals|the goals]], ![[diagram]].
+A [relative](../ideas/Big%20Idea.md#top) link and [another](<notes/a b.md> \"Title\").
+Not links: [site](https://x.io/a.md), [image](pic.png), `[[code]]`, [[#Heading]], [[unclosed
+```
+[[fenced]]
+```
+Last [[ Spaced ]]";
+        assert_eq!(
+            targets(body),
+            [
+                (LinkKind::Wiki, 1, "Plan".to_string()),
+                (LinkKind::Wiki, 1, "work/Plan".to_string()),
+                (LinkKind::Wiki, 1, "diagram".to_string()),
+                (LinkKind::Markdown, 2, "../ideas/Big Idea.md".to_string()),
+                (LinkKind::Markdown, 2, "notes/a b.md".to_string()),
+                (LinkKind::Wiki, 7, "Spaced".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_wikilinks() {
+        let resolver = Resolver::new(["plan.md", "work/Plan.md", "work/deep/plan.md", "archive/work/old.md"].map(String::from));
+
+        assert_eq!(resolver.resolve("inbox/a.md", LinkKind::Wiki, "Plan"), Some("plan.md"));
+        // Prefers the note next to the source.
+        assert_eq!(resolver.resolve("work/b.md", LinkKind::Wiki, "plan"), Some("work/Plan.md"));
+        assert_eq!(resolver.resolve("a.md", LinkKind::Wiki, "work/deep/plan.md"), Some("work/deep/plan.md"));
+        assert_eq!(resolver.resolve("a.md", LinkKind::Wiki, "work/old"), Some("archive/work/old.md"));
+        assert_eq!(resolver.resolve("a.md", LinkKind::Wiki, "missing"), None);
+    }
+
+    #[test]
+    fn test_spans_cover_written_targets() {
+        let body = "x\n[[ Plan#Goals|alias ]] and [a](<b c.md#top> \"T\") and [d](e%20f.md)\n";
+        let written: Vec<_> = parse(body).into_iter().map(|l| &body[l.span]).collect();
+        assert_eq!(written, ["Plan", "b c.md", "e%20f.md"]);
+    }
+
+    #[test]
+    fn test_retarget() {
+        let resolver = Resolver::new(["archive/Plan.md", "work/plan.md", "notes/a.md", "new name.md"].map(String::from));
+
+        assert_eq!(retarget(LinkKind::Wiki, "Old", "notes/a.md", "new name.md", &resolver), "new name");
+        assert_eq!(retarget(LinkKind::Wiki, "Old.md", "notes/a.md", "new name.md", &resolver), "new name.md");
+        // `plan` alone would resolve to work/plan.md from there.
+        assert_eq!(retarget(LinkKind::Wiki, "Old", "work/x.md", "archive/Plan.md", &resolver), "archive/Plan");
+        assert_eq!(retarget(LinkKind::Wiki, "x/Old", "notes/a.md", "work/plan.md", &resolver), "work/plan");
+
+        assert_eq!(retarget(LinkKind::Markdown, "old.md", "notes/a.md", "work/plan.md", &resolver), "../work/plan.md");
+        assert_eq!(retarget(LinkKind::Markdown, "old.md", "notes/a.md", "new name.md", &resolver), "../new%20name.md");
+        assert_eq!(retarget(LinkKind::Markdown, "my old.md", "a.md", "new name.md", &resolver), "new name.md");
+        assert_eq!(retarget(LinkKind::Markdown, "/old.md", "notes/a.md", "work/plan.md", &resolver), "/work/plan.md");
+        assert_eq!(relative("work", "work/plan.md"), "plan.md");
+        assert_eq!(relative("a/b", "a/c/d.md"), "../c/d.md");
+    }
+
+    #[test]
+    fn test_resolve_markdown_links() {
+        let resolver = Resolver::new(["a.md", "notes/b.md", "notes/sub/c.md"].map(String::from));
+
+        assert_eq!(resolver.resolve("notes/x.md", LinkKind::Markdown, "b.md"), Some("notes/b.md"));
+        assert_eq!(resolver.resolve("notes/x.md", LinkKind::Markdown, "./sub/../sub/C.md"), Some("notes/sub/c.md"));
+        assert_eq!(resolver.resolve("notes/sub/c.md", LinkKind::Markdown, "../../a.md"), Some("a.md"));
+        assert_eq!(resolver.resolve("notes/sub/c.md", LinkKind::Markdown, "/notes/b.md"), Some("notes/b.md"));
+        assert_eq!(resolver.resolve("a.md", LinkKind::Markdown, "../a.md"), None);
+        assert_eq!(resolver.resolve("a.md", LinkKind::Markdown, "b.md"), None);
+    }
+}
@@ -0,0 +1,84 @@
+//! Advisory lock that coordinates processes sharing a vault.
+//!
+//! Single-note writes hold `.extt/lock` shared, so any number of them can run
+//! side by side. Operations that touch many notes or the whole index (sync,
+//! rebuild, moves that rewrite links) hold it exclusively. The lock is
+//! released when the [`VaultLock`] is dropped, or by the OS if the process
+//! dies.
+
+use crate::error::{Error, Result};
+use crate::vault::META_DIR;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait between attempts to take a contended lock.
+const RETRY: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Shared,
+    Exclusive,
+}
+
+pub(crate) struct VaultLock {
+    _file: File,
+}
+
+/// Takes the vault lock, waiting up to `timeout` for other processes to
+/// release it before failing with [`Error::VaultBusy`].
+pub(crate) fn acquire(root: &Path, mode: Mode, timeout: Duration) -> Result<VaultLock> {
+    let dir = root.join(META_DIR);
+    fs::create_dir_all(&dir)?;
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(dir.join("lock"))?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let attempt = match mode {
+            Mode::Shared => file.try_lock_shared(),
+            Mode::Exclusive => file.try_lock(),
+        };
+        match attempt {
+            Ok(()) => return Ok(VaultLock { _file: file }),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::sleep(RETRY),
+            Err(TryLockError::WouldBlock) => return Err(Error::VaultBusy),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_and_exclusive() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        let wait = Duration::from_millis(50);
+
+        let first = acquire(root, Mode::Shared, wait)?;
+        let second = acquire(root, Mode::Shared, wait)?;
+        assert!(matches!(acquire(root, Mode::Exclusive, wait), Err(Error::VaultBusy)));
+        drop((first, second));
+
+        let exclusive = acquire(root, Mode::Exclusive, wait)?;
+        assert!(matches!(acquire(root, Mode::Shared, wait), Err(Error::VaultBusy)));
+        drop(exclusive);
+        acquire(root, Mode::Shared, wait)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_waits_for_release() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().to_path_buf();
+        let held = acquire(&root, Mode::Exclusive, Duration::ZERO)?;
+
+        let waiter = thread::spawn(move || acquire(&root, Mode::Exclusive, Duration::from_secs(5)).map(|_| ()));
+        thread::sleep(Duration::from_millis(100));
+        drop(held);
+        waiter.join().unwrap()
+    }
+}
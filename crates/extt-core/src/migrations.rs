@@ -0,0 +1,221 @@
+//! Versioned schema for the index database.
+//!
+//! The schema version lives in SQLite's `PRAGMA user_version`. Each entry in
+//! [`MIGRATIONS`] upgrades the database by exactly one version, and all
+//! pending migrations run inside a single transaction so a failure leaves the
+//! index at its previous version.
+
+use crate::error::{Error, Result};
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// Entry `i` upgrades a database from version `i` to `i + 1`.
+///
+/// Released migrations must never be edited or reordered; append a new one
+/// instead.
+const MIGRATIONS: &[Migration] = &[baseline, note_tags, links, note_times, diagnostics];
+
+/// Schema version this build of extt reads and writes.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Brings the database up to [`SCHEMA_VERSION`].
+///
+/// Refuses to touch a database written by a newer extt, since downgrading
+/// its schema could silently drop data the newer version relies on.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let current = user_version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(Error::SchemaTooNew { found: current, supported: SCHEMA_VERSION });
+    }
+    if current == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Takes the write lock before re-reading the version: another process
+    // may have migrated while this one was waiting.
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let current = user_version(&tx)?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", version as i64 + 1)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn user_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Version 1: notes table with change-detection stamps plus the FTS index.
+///
+/// Indexes created before versioning existed report version 0 but may
+/// already hold a `notes` table, possibly without the stamp columns. Those
+/// are upgraded in place; NULL stamps make the next sync re-read the files.
+fn baseline(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            title TEXT,
+            created_at TEXT,
+            updated_at TEXT
+        );
+        CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(path, title, body);",
+    )?;
+
+    let columns: Vec<String> = tx
+        .prepare("SELECT name FROM pragma_table_info('notes')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (column, ty) in [("size", "INTEGER"), ("mtime", "INTEGER"), ("content_hash", "TEXT")] {
+        if !columns.iter().any(|c| c == column) {
+            tx.execute(&format!("ALTER TABLE notes ADD COLUMN {column} {ty}"), [])?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 2: tags per note, including inline `#tags` from the body.
+///
+/// Existing rows lose their stamps so the next sync re-reads every note and
+/// fills the new table.
+fn note_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE note_tags (
+            note_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (note_id, tag)
+        ) WITHOUT ROWID;
+        CREATE INDEX note_tags_by_tag ON note_tags (tag, note_id);
+        UPDATE notes SET size = NULL, mtime = NULL, content_hash = NULL;",
+    )
+}
+
+/// Version 3: wikilinks and markdown links between notes.
+///
+/// `target` is the link as written; `target_path` is the note it resolves
+/// to, or NULL while it points nowhere. Like version 2, clears the stamps so
+/// the next sync fills the table.
+fn links(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE links (
+            source_id INTEGER NOT NULL,
+            line INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            target TEXT NOT NULL,
+            target_path TEXT
+        );
+        CREATE INDEX links_by_source ON links (source_id);
+        CREATE INDEX links_by_target ON links (target_path);
+        UPDATE notes SET size = NULL, mtime = NULL, content_hash = NULL;",
+    )
+}
+
+/// Version 4: `created_at` and `updated_at` are filled from the frontmatter
+/// or the file times, and indexed for sorting and filtering. Clears the
+/// stamps so the next sync fills them.
+fn note_times(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX notes_by_created ON notes (created_at);
+        CREATE INDEX notes_by_updated ON notes (updated_at);
+        UPDATE notes SET size = NULL, mtime = NULL, content_hash = NULL;",
+    )
+}
+
+/// Version 5: problems found while reading a note's frontmatter, kept so
+/// they can be reported without re-reading the file. Clears the stamps so
+/// the next sync records them for existing notes.
+fn diagnostics(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE diagnostics (
+            note_id INTEGER NOT NULL,
+            line INTEGER,
+            column INTEGER,
+            message TEXT NOT NULL
+        );
+        CREATE INDEX diagnostics_by_note ON diagnostics (note_id);
+        UPDATE notes SET size = NULL, mtime = NULL, content_hash = NULL;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT name FROM pragma_table_info('notes')")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrate_fresh_database() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        migrate(&mut conn)?;
+
+        assert_eq!(user_version(&conn)?, SCHEMA_VERSION);
+        assert!(columns(&conn).contains(&"content_hash".to_string()));
+
+        // Re-running is a no-op.
+        migrate(&mut conn)?;
+        assert_eq!(user_version(&conn)?, SCHEMA_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_unversioned_database_keeps_rows() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE notes (
+                id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL UNIQUE,
+                title TEXT,
+                created_at TEXT,
+                updated_at TEXT
+            );
+            INSERT INTO notes (path, title) VALUES ('old.md', 'Old');",
+        )?;
+
+        migrate(&mut conn)?;
+
+        assert_eq!(user_version(&conn)?, SCHEMA_VERSION);
+        assert!(columns(&conn).contains(&"mtime".to_string()));
+        let title: String = conn.query_row("SELECT title FROM notes WHERE path = 'old.md'", [], |row| row.get(0))?;
+        assert_eq!(title, "Old");
+        Ok(())
+    }
+
+    #[test]
+    fn test_note_tags_migration_forces_reindex() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        let tx = conn.transaction()?;
+        baseline(&tx)?;
+        tx.pragma_update(None, "user_version", 1)?;
+        tx.execute("INSERT INTO notes (path, size, mtime, content_hash) VALUES ('a.md', 1, 2, 'h')", [])?;
+        tx.commit()?;
+
+        migrate(&mut conn)?;
+
+        let hash: Option<String> = conn.query_row("SELECT content_hash FROM notes", [], |row| row.get(0))?;
+        assert_eq!(hash, None);
+        let tags: i64 = conn.query_row("SELECT COUNT(*) FROM note_tags", [], |row| row.get(0))?;
+        assert_eq!(tags, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_refuses_newer_database() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)?;
+
+        let err = migrate(&mut conn).unwrap_err();
+        assert!(matches!(err, Error::SchemaTooNew { found, .. } if found == SCHEMA_VERSION + 1));
+        assert_eq!(user_version(&conn)?, SCHEMA_VERSION + 1);
+        Ok(())
+    }
+}
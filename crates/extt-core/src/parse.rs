@@ -0,0 +1,266 @@
+//! Reading notes.
+//!
+//! Every reader (the store's `get` and sync, and [`Note::load`]) splits and
+//! parses notes here, so they all agree on where the frontmatter ends and on
+//! what counts as malformed. Parsing never fails. Frontmatter that is not
+//! valid YAML (or TOML) leaves the metadata empty, and a key that does not have the
+//! expected shape (such as `title: [a, b]`) is passed through untyped, so a
+//! single bad value never hides the rest. Either way the problem is reported
+//! as a [`Diagnostic`], and [`Note::frontmatter`] still holds the raw text.
+
+use crate::error::{Error, Result};
+use crate::frontmatter::{self, NoteFile};
+use crate::store::content_hash;
+use crate::timestamps;
+use crate::types::{Diagnostic, FrontmatterFormat, Metadata, Note};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads and parses the note at `path`, naming it `relative_path` in the
+/// result and in errors.
+pub(crate) fn read(path: &Path, relative_path: &Path) -> Result<Note> {
+    let text = fs::read_to_string(path).map_err(|e| Error::from_io(e, relative_path))?;
+    Ok(Note::parse(relative_path.to_path_buf(), &text))
+}
+
+impl Note {
+    /// Parses note text, reporting problems with the frontmatter in
+    /// [`Note::diagnostics`].
+    pub fn parse(path: PathBuf, text: &str) -> Self {
+        let file = NoteFile::parse(text);
+        let mut diagnostics = Vec::new();
+        let metadata = match (file.format(), file.frontmatter()) {
+            (Some(format), Some(text)) => metadata(format, text, &mut diagnostics),
+            _ => Metadata::default(),
+        };
+        Self {
+            path,
+            metadata,
+            frontmatter: file.frontmatter().map(str::to_string),
+            frontmatter_format: file.format(),
+            content: file.body().to_string(),
+            revision: content_hash(text.as_bytes()),
+            diagnostics,
+        }
+    }
+
+    /// Reads a note file.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        read(&path, &path)
+    }
+}
+
+/// Typed view of the frontmatter, as much of it as makes sense.
+fn metadata(format: FrontmatterFormat, text: &str, diagnostics: &mut Vec<Diagnostic>) -> Metadata {
+    // File line the frontmatter text starts on: after the opening `---` or
+    // `+++`, while JSON starts right away.
+    let first_line = if format == FrontmatterFormat::Json { 1 } else { 2 };
+    let mapping = match frontmatter::parse_value(format, text) {
+        Ok(serde_yaml::Value::Null) => return Metadata::default(),
+        Ok(value @ serde_yaml::Value::Mapping(_)) => match serde_json::to_value(&value) {
+            Ok(Value::Object(mapping)) => mapping,
+            Ok(_) => unreachable!("a YAML mapping converts to a JSON object"),
+            Err(err) => {
+                diagnostics.push(Diagnostic::new(err.to_string(), Some((first_line, 1))));
+                return Metadata::default();
+            }
+        },
+        Ok(_) => {
+            diagnostics.push(Diagnostic::new("frontmatter is not a mapping of keys to values", Some((first_line, 1))));
+            return Metadata::default();
+        }
+        Err(err) => {
+            let location = err.location.map(|(line, column)| (line + first_line - 1, column));
+            diagnostics.push(Diagnostic::new(strip_locations(&err.message), location));
+            return Metadata::default();
+        }
+    };
+
+    let mut metadata = Metadata::default();
+    for (key, value) in mapping {
+        if value.is_null() {
+            continue;
+        }
+        let typed = match key.as_str() {
+            "title" => scalar(&value).map(|title| metadata.title = Some(title)).ok_or("expected text"),
+            "tags" => tag_list(&value).map(|tags| metadata.tags = Some(tags)).ok_or("expected a list of tags"),
+            "created_at" | "updated_at" => match value.as_str() {
+                Some(time) if timestamps::parse(time).is_some() => {
+                    let field = if key == "created_at" { &mut metadata.created_at } else { &mut metadata.updated_at };
+                    *field = Some(time.to_string());
+                    Ok(())
+                }
+                _ => Err("expected an RFC 3339 time or a date"),
+            },
+            _ => {
+                metadata.extra.insert(key, value);
+                continue;
+            }
+        };
+        if let Err(expected) = typed {
+            let line = key_line(format, text, &key).map(|line| (line + first_line - 1, 1));
+            diagnostics.push(Diagnostic::new(format!("`{key}`: {expected}"), line));
+            metadata.extra.insert(key, value);
+        }
+    }
+    // Keys come out sorted by name; report them in file order.
+    diagnostics.sort_by_key(|d| d.line);
+    metadata
+}
+
+/// Text of a string, number or boolean.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Tags given as a list, or as one string separated by commas or spaces.
+fn tag_list(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(items) => items.iter().map(scalar).collect(),
+        Value::String(text) => Some(text.split([',', ' ']).filter(|tag| !tag.is_empty()).map(str::to_string).collect()),
+        _ => None,
+    }
+}
+
+/// Line of the frontmatter text, counted from 1, where `key` is set: a
+/// top-level `key:` in YAML, `key =` in TOML or `"key":` in JSON.
+fn key_line(format: FrontmatterFormat, text: &str, key: &str) -> Option<usize> {
+    let sets_key = |line: &str| {
+        // Nested YAML keys are indented; TOML and JSON keys may be anyway.
+        let line = if format == FrontmatterFormat::Yaml { line } else { line.trim_start() };
+        ["", "\"", "'"].iter().any(|quote| {
+            let rest = line.strip_prefix(quote).and_then(|l| l.strip_prefix(key)).and_then(|l| l.strip_prefix(quote));
+            match (format, rest) {
+                (_, None) => false,
+                (FrontmatterFormat::Yaml, Some(rest)) => {
+                    rest.trim_end().strip_prefix(':').is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+                }
+                (FrontmatterFormat::Toml, Some(rest)) => rest.trim_start().starts_with('='),
+                (FrontmatterFormat::Json, Some(rest)) => rest.trim_start().starts_with(':'),
+            }
+        })
+    };
+    text.lines().position(sets_key).map(|index| index + 1)
+}
+
+/// Drops the ` at line 3 column 1` parts of a YAML or JSON error message. They are
+/// relative to the frontmatter rather than the file, and the diagnostic
+/// carries the location separately.
+fn strip_locations(message: &str) -> String {
+    let mut out = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find(" at line ") {
+        let after = &rest[start + " at line ".len()..];
+        let digits = |s: &str| s.bytes().take_while(u8::is_ascii_digit).count();
+        let line = digits(after);
+        let Some(column) = after[line..].strip_prefix(" column ").filter(|_| line > 0) else {
+            out.push_str(&rest[..start + 1]);
+            rest = &rest[start + 1..];
+            continue;
+        };
+        out.push_str(&rest[..start]);
+        rest = &column[digits(column)..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse() {
+        let note = Note::parse("plan.md".into(), "---\ntitle: Plan\ntags: [a, b]\nstatus: draft\n---\n\n# Plan\nBody\n");
+        assert_eq!(note.frontmatter.as_deref(), Some("title: Plan\ntags: [a, b]\nstatus: draft\n"));
+        assert_eq!(note.metadata.title.as_deref(), Some("Plan"));
+        assert_eq!(note.metadata.tags, Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(note.metadata.extra["status"], "draft");
+        assert_eq!(note.content, "# Plan\nBody\n");
+        assert!(note.diagnostics.is_empty());
+
+        for text in ["", "# Just content\nNo frontmatter here.", "---\nnever closed\n"] {
+            let note = Note::parse("a.md".into(), text);
+            assert_eq!((note.frontmatter, note.content.as_str()), (None, text));
+        }
+
+        let note = Note::parse("only.md".into(), "---\ntitle: Only FM\n---");
+        assert_eq!((note.metadata.title.as_deref(), note.content.as_str()), (Some("Only FM"), ""));
+        let note = Note::parse("utf8.md".into(), "---\ntitle: \"Заголовок\"\n---\n# Привет, мир! 🚀🦀\n");
+        assert_eq!(note.metadata.title.as_deref(), Some("Заголовок"));
+        let note = Note::parse("loose.md".into(), "---\ntitle: 1984\ntags: work, project/alpha idea\n---\n");
+        assert_eq!(note.metadata.title.as_deref(), Some("1984"));
+        assert_eq!(note.metadata.tags, Some(vec!["work".into(), "project/alpha".into(), "idea".into()]));
+        assert!(note.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let note = Note::parse("bad.md".into(), "---\ntitle: Fine\nbroken: [unclosed\n---\nContent\n");
+        assert_eq!(note.content, "Content\n");
+        assert_eq!(note.frontmatter.as_deref(), Some("title: Fine\nbroken: [unclosed\n"));
+        assert_eq!(note.metadata.title, None);
+        assert_eq!(note.diagnostics.len(), 1);
+        assert_eq!(note.diagnostics[0].line, Some(4));
+        assert!(!note.diagnostics[0].message.contains(" at line "));
+
+        // Bad values are reported but keep the rest of the metadata.
+        let note = Note::parse("keys.md".into(), "---\ntitle: Kept\ntags: {a: 1}\ncreated_at: yesterday\n---\n");
+        assert_eq!(note.metadata.title.as_deref(), Some("Kept"));
+        assert_eq!(note.metadata.tags, None);
+        assert_eq!(note.metadata.extra["created_at"], "yesterday");
+        let found: Vec<_> = note.diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+        assert_eq!(found, [
+            (Some(3), "`tags`: expected a list of tags"),
+            (Some(4), "`created_at`: expected an RFC 3339 time or a date"),
+        ]);
+
+        let note = Note::parse("list.md".into(), "---\n- a list\n---\n");
+        assert_eq!(note.diagnostics[0].line, Some(2));
+        assert!(Note::parse("c.md".into(), "---\n# only a comment\n---\n").diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_formats() {
+        let note = Note::parse("post.md".into(), "+++\ntitle = \"Hugo post\"\ntags = [\"go\"]\ncreated_at = 2024-05-01\ndraft = true\n+++\nBody\n");
+        assert_eq!(note.frontmatter_format, Some(FrontmatterFormat::Toml));
+        assert_eq!(note.metadata.title.as_deref(), Some("Hugo post"));
+        assert_eq!(note.metadata.tags, Some(vec!["go".to_string()]));
+        assert_eq!(note.metadata.created_at.as_deref(), Some("2024-05-01"));
+        assert_eq!(note.metadata.extra["draft"], true);
+        assert_eq!(note.content, "Body\n");
+
+        let note = Note::parse("post.md".into(), "{\n  \"title\": \"JSON post\",\n  \"tags\": 3\n}\nBody\n");
+        assert_eq!(note.frontmatter_format, Some(FrontmatterFormat::Json));
+        assert_eq!(note.metadata.title.as_deref(), Some("JSON post"));
+        assert_eq!((note.diagnostics[0].line, note.content.as_str()), (Some(3), "Body\n"));
+
+        let note = Note::parse("bad.md".into(), "+++\ntitle = \"ok\"\ntags = [\n+++\nBody");
+        assert_eq!(note.metadata.title, None);
+        assert_eq!(note.diagnostics.len(), 1);
+        assert_eq!(note.diagnostics[0].line, Some(4));
+        let note = Note::parse("keys.md".into(), "+++\ntitle = \"ok\"\n  tags = { a = 1 }\n+++\n");
+        assert_eq!(note.diagnostics[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_load() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("test.md");
+        fs::write(&path, "---\ntitle: Test Note\ntags: [test, notes]\n---\n# Hello World\n")?;
+        let note = Note::load(path.clone())?;
+        assert_eq!((note.path.as_path(), note.content.as_str()), (path.as_path(), "# Hello World\n"));
+
+        fs::write(&path, "---\ntitle:\n\tinvalid\n---\nContent")?;
+        let note = Note::load(path)?;
+        assert_eq!((note.content.as_str(), note.diagnostics.len()), ("Content", 1));
+        assert!(matches!(Note::load(dir.path().join("missing.md")), Err(Error::NotFound(_))));
+        Ok(())
+    }
+}
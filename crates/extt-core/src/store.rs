@@ -1,343 +1,2391 @@
-use crate::types::{Metadata, Note, NoteSummary};
-use anyhow::Result;
-use gray_matter::engine::YAML;
-use gray_matter::Matter;
-use rusqlite::{params, Connection};
+use crate::atomic::{self, Staged};
+use crate::error::{Error, Result};
+use crate::body::{self, BodyEditError};
+use crate::frontmatter::{self, FrontmatterError, NoteFile};
+use crate::git::Git;
+use crate::history;
+use crate::links::{self, Resolver};
+use crate::lock::{self, Mode, VaultLock};
+use crate::migrations;
+use crate::parse;
+use crate::types::{
+    BodyEdit, GitCommit, GitOptions, HistoryEntry, Link, LinkKind, LinkRewrite, ListQuery, Metadata, MetadataEdit, MoveOptions, Note, NoteSummary, Page,
+    Diagnostic, FrontmatterFormat, NoteDiagnostic, NoteEdit, Problem, SortKey, SyncReport, TagCount, TrashEntry,
+};
+use crate::vault::{self, is_meta_path, secure_join, Vault};
+use crate::watcher::Change;
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use crate::tags;
+use crate::timestamps;
+use crate::trash;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 use walkdir::WalkDir;
 
+/// How long to wait for other processes using the same index or vault
+/// before giving up, unless changed with [`Store::set_busy_timeout`].
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct Store {
     conn: Connection,
     root_dir: PathBuf,
+    git: Option<Git>,
+    busy_timeout: Duration,
+    stamp_times: bool,
+    frontmatter_format: FrontmatterFormat,
 }
 
 impl Store {
     pub fn new(root_dir: PathBuf, db_path: PathBuf) -> Result<Self> {
+        let mut store = Self::open(root_dir, db_path)?;
+        // Rows without a stamp predate a migration that added derived data
+        // (such as tags); re-read them so queries see the whole vault.
+        let stale: bool = store
+            .conn
+            .query_row("SELECT EXISTS (SELECT 1 FROM notes WHERE content_hash IS NULL)", [], |row| row.get(0))?;
+        if stale {
+            store.sync()?;
+        }
+        Ok(store)
+    }
+
+    /// Opens and migrates the index without syncing it.
+    ///
+    /// WAL mode lets readers in other processes carry on while one process
+    /// writes, and the busy timeout makes writers queue up instead of failing
+    /// straight away.
+    fn open(root_dir: PathBuf, db_path: PathBuf) -> Result<Self> {
         if let Some(parent) = db_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let conn = Connection::open(db_path)?;
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS notes (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL UNIQUE,
-                title TEXT,
-                created_at TEXT,
-                updated_at TEXT
-            )",
-            [],
-        )?;
+        let mut conn = Connection::open(db_path)?;
+        conn.busy_timeout(DEFAULT_BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        migrations::migrate(&mut conn)?;
+        Ok(Self {
+            conn,
+            root_dir,
+            git: None,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            stamp_times: true,
+            frontmatter_format: FrontmatterFormat::default(),
+        })
+    }
+
+    /// Sets how long to wait for other processes holding the index or the
+    /// vault lock before failing with [`Error::DatabaseLocked`] or
+    /// [`Error::VaultBusy`].
+    pub fn set_busy_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.conn.busy_timeout(timeout)?;
+        self.busy_timeout = timeout;
+        Ok(())
+    }
+
+    /// Whether writes keep `created_at` and `updated_at` in the frontmatter
+    /// up to date. On by default; with it off, notes are only given the
+    /// times of their files in the index.
+    pub fn set_stamp_times(&mut self, stamp: bool) {
+        self.stamp_times = stamp;
+    }
+
+    /// Format of the frontmatter written to new notes, and to notes without
+    /// frontmatter that gain some. YAML by default. Notes that have
+    /// frontmatter always keep its format.
+    pub fn set_frontmatter_format(&mut self, format: FrontmatterFormat) {
+        self.frontmatter_format = format;
+    }
 
-        Ok(Self { conn, root_dir })
+    /// Opens the index, rebuilding it from the notes on disk if the database
+    /// file turns out to be corrupt.
+    ///
+    /// Returns the sync report when a rebuild happened.
+    pub fn open_or_rebuild(root_dir: PathBuf, db_path: PathBuf) -> Result<(Self, Option<SyncReport>)> {
+        match Self::new(root_dir.clone(), db_path.clone()) {
+            Ok(store) => Ok((store, None)),
+            Err(Error::IndexCorrupt) => {
+                let (store, report) = Self::rebuild(root_dir, db_path)?;
+                Ok((store, Some(report)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Deletes the index database and rebuilds it from the notes on disk.
+    ///
+    /// The index only caches what is already in the notes folder, so this
+    /// never loses user data. Holds the vault lock throughout, so no other
+    /// sync, rebuild or move runs against a half-built index.
+    pub fn rebuild(root_dir: PathBuf, db_path: PathBuf) -> Result<(Self, SyncReport)> {
+        let _lock = lock::acquire(&root_dir, Mode::Exclusive, DEFAULT_BUSY_TIMEOUT)?;
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let mut path = db_path.clone().into_os_string();
+            path.push(suffix);
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut store = Self::open(root_dir, db_path)?;
+        let report = store.sync_files()?;
+        Ok((store, report))
+    }
+
+    /// Brings the index in line with the notes on disk.
+    ///
+    /// Files whose size and mtime match the indexed row are skipped without
+    /// being read. Files that were touched but hash to the same content only
+    /// get their stamp refreshed; everything else is re-parsed.
+    pub fn sync(&mut self) -> Result<SyncReport> {
+        let _lock = self.lock(Mode::Exclusive)?;
+        self.sync_files()
     }
 
-    pub fn sync(&mut self) -> Result<()> {
-        let tx = self.conn.transaction()?;
-        // For simplicity, we'll clear and rebuild. Optimizations can come later.
-        tx.execute("DELETE FROM notes", [])?;
+    fn sync_files(&mut self) -> Result<SyncReport> {
+        let tx = write_tx(&mut self.conn)?;
+        let mut report = SyncReport::default();
+
+        let mut indexed: HashMap<String, IndexedStamp> = HashMap::new();
+        {
+            let mut stmt = tx.prepare("SELECT path, size, mtime, content_hash FROM notes")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, IndexedStamp::from_row(row, 1)?)))?;
+            for row in rows {
+                let (path, stamp) = row?;
+                indexed.insert(path, stamp);
+            }
+        }
 
-        for entry in WalkDir::new(&self.root_dir).into_iter().filter_map(|e| e.ok()) {
-            if entry.file_type().is_file() && entry.path().extension().map_or(false, |e| e == "md") {
+        for entry in vault::walk(&self.root_dir) {
+            if entry.file_type().is_file() && is_note_file(entry.path()) {
                 let path = entry.path();
-                let relative_path = path.strip_prefix(&self.root_dir)?.to_string_lossy().to_string();
-                
-                let content = fs::read_to_string(path)?;
-                let matter = Matter::<YAML>::new();
-                let parsed = matter.parse(&content);
-                
-                let metadata: Option<Metadata> = parsed.data.map(|d| d.deserialize()).transpose().unwrap_or(None);
-                
-                let title = metadata.as_ref()
-                    .and_then(|m| m.title.clone())
-                    .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()));
-
-                tx.execute(
-                    "INSERT INTO notes (path, title) VALUES (?1, ?2)",
-                    params![relative_path, title],
-                )?;
+                let Ok(relative_path) = path.strip_prefix(&self.root_dir) else {
+                    continue;
+                };
+                let relative_path = relative_path.to_string_lossy().to_string();
+                let previous = indexed.remove(&relative_path);
+                let outcome = index_file(&tx, path, &relative_path, &entry.metadata()?, previous.as_ref())?;
+                report.record(outcome);
+            }
+        }
+
+        for relative_path in indexed.keys() {
+            unindex_note(&tx, relative_path)?;
+            report.removed += 1;
+        }
+
+        if report.added + report.updated + report.removed + report.skipped > 0 {
+            resolve_links(&tx)?;
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Applies filesystem changes reported by a [`Watcher`](crate::watcher::Watcher)
+    /// in a single transaction.
+    ///
+    /// Paths are relative to the notes root and may name directories, in
+    /// which case every note below them is affected. Upserts of paths that no
+    /// longer exist are treated as removals, so callers do not need to
+    /// reconcile create/delete races themselves. Renames count as updates.
+    pub fn apply_changes(&mut self, changes: &[Change]) -> Result<SyncReport> {
+        let _lock = self.lock(Mode::Shared)?;
+        let tx = write_tx(&mut self.conn)?;
+        let mut report = SyncReport::default();
+
+        for change in changes {
+            match change {
+                Change::Upsert(relative_path) => {
+                    upsert_path(&tx, &self.root_dir, relative_path, &mut report)?;
+                }
+                Change::Remove(relative_path) => {
+                    report.removed += remove_path(&tx, relative_path)?;
+                }
+                Change::Rename { from, to } if is_meta_path(to) => {
+                    report.removed += remove_path(&tx, from)?;
+                }
+                Change::Rename { from, to } => {
+                    // A rename can replace a file that is already indexed.
+                    if from != to {
+                        report.removed += remove_path(&tx, to)?;
+                    }
+                    let from = from.to_string_lossy();
+                    let to_str = to.to_string_lossy();
+                    let prefix = format!("{}/", from);
+                    let renamed = tx.execute(
+                        "UPDATE notes SET path = ?2 || substr(path, length(?1) + 1)
+                         WHERE path = ?1 OR substr(path, 1, length(?3)) = ?3",
+                        params![from, to_str, prefix],
+                    )?;
+                    tx.execute(
+                        "UPDATE notes_fts SET path = ?2 || substr(path, length(?1) + 1)
+                         WHERE path = ?1 OR substr(path, 1, length(?3)) = ?3",
+                        params![from, to_str, prefix],
+                    )?;
+                    report.updated += renamed;
+
+                    // Picks up notes that were not indexed under the old name
+                    // and content edited together with the rename.
+                    let mut refreshed = SyncReport::default();
+                    upsert_path(&tx, &self.root_dir, to, &mut refreshed)?;
+                    report.added += refreshed.added;
+                    report.updated += refreshed.updated;
+                    report.removed += refreshed.removed;
+                }
+            }
+        }
+
+        resolve_links(&tx)?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root_dir
+    }
+
+    pub fn list(&self) -> Result<Vec<NoteSummary>> {
+        Ok(self.query(&ListQuery::default())?.items)
+    }
+
+    /// Lists notes one page at a time, by path unless the query sorts them
+    /// otherwise.
+    pub fn query(&self, query: &ListQuery) -> Result<Page> {
+        let tag = query_tag(query)?;
+        let (since, until) = query_bounds(query);
+        let filter = query_filter(query);
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM notes n WHERE {filter}"),
+            params![tag, since, until],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT n.path, n.title, n.created_at, n.updated_at FROM notes n WHERE {filter}
+             ORDER BY {} LIMIT ?4 OFFSET ?5",
+            query_order(query.sort.unwrap_or(SortKey::Path))
+        ))?;
+        let items = stmt
+            .query_map(params![tag, since, until, sql_limit(query), query.offset as i64], |row| {
+                Ok(NoteSummary {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    title: row.get(1)?,
+                    snippet: None,
+                    created_at: row.get(2)?,
+                    updated_at: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Page { items, total: total as usize })
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<NoteSummary>> {
+        Ok(self.search_with(query, &ListQuery::default())?.items)
+    }
+
+    /// Full-text search narrowed and paged by `filter`, best matches first
+    /// unless the filter sorts them otherwise.
+    pub fn search_with(&self, query: &str, filter: &ListQuery) -> Result<Page> {
+        let Some(fts_query) = fts_query(query) else {
+            return self.query(filter);
+        };
+        let tag = query_tag(filter)?;
+        let (since, until) = query_bounds(filter);
+        let condition = query_filter(filter);
+
+        let total: i64 = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM notes_fts JOIN notes n ON n.id = notes_fts.rowid
+                 WHERE notes_fts MATCH ?4 AND {condition}"
+            ),
+            params![tag, since, until, fts_query],
+            |row| row.get(0),
+        )?;
+
+        // bm25 weights: path, title, body. A hit in the title should outrank
+        // a passing mention deep inside some other note's body.
+        let order = match filter.sort {
+            Some(key) => query_order(key),
+            None => "bm25(notes_fts, 5.0, 10.0, 1.0)",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT n.path, n.title, snippet(notes_fts, -1, '**', '**', '…', 12), n.created_at, n.updated_at
+             FROM notes_fts
+             JOIN notes n ON n.id = notes_fts.rowid
+             WHERE notes_fts MATCH ?4 AND {condition}
+             ORDER BY {order}
+             LIMIT ?5 OFFSET ?6"
+        ))?;
+        let items = stmt
+            .query_map(params![tag, since, until, fts_query, sql_limit(filter), filter.offset as i64], |row| {
+                let snippet: Option<String> = row.get(2)?;
+                Ok(NoteSummary {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    title: row.get(1)?,
+                    snippet: snippet.map(|s| s.split_whitespace().collect::<Vec<_>>().join(" ")),
+                    created_at: row.get(3)?,
+                    updated_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Page { items, total: total as usize })
+    }
+
+    /// Every tag with the number of notes carrying it. Parent tags count the
+    /// notes of their children, so `project` includes `project/alpha`.
+    pub fn tags(&self) -> Result<Vec<TagCount>> {
+        let mut stmt = self.conn.prepare("SELECT note_id, tag FROM note_tags")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut notes: BTreeMap<String, BTreeSet<i64>> = BTreeMap::new();
+        for row in rows {
+            let (note_id, tag) = row?;
+            for tag in tags::with_ancestors(&tag) {
+                notes.entry(tag.to_string()).or_default().insert(note_id);
+            }
+        }
+        Ok(notes.into_iter().map(|(tag, ids)| TagCount { tag, count: ids.len() }).collect())
+    }
+
+    /// Notes with `tag` or one of its children, ordered by path.
+    pub fn list_by_tag(&self, tag: &str) -> Result<Vec<NoteSummary>> {
+        Ok(self.query(&ListQuery { tag: Some(tag.to_string()), ..Default::default() })?.items)
+    }
+
+    /// Links written in a note, in the order they appear.
+    pub fn outgoing_links(&self, relative_path: &Path) -> Result<Vec<Link>> {
+        let id = self.note_id(relative_path)?;
+        self.links_where("l.source_id = ?1", params![id])
+    }
+
+    /// Links from any note, itself included, that resolve to this note.
+    pub fn backlinks(&self, relative_path: &Path) -> Result<Vec<Link>> {
+        self.note_id(relative_path)?;
+        self.links_where("l.target_path = ?1", params![relative_path.to_string_lossy()])
+    }
+
+    /// Links across the vault that point at no existing note.
+    pub fn unresolved_links(&self) -> Result<Vec<Link>> {
+        self.links_where("l.target_path IS NULL", [])
+    }
+
+    /// Frontmatter problems found in the last sync, ordered by path and line.
+    pub fn diagnostics(&self) -> Result<Vec<NoteDiagnostic>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT n.path, d.message, d.line, d.column
+             FROM diagnostics d JOIN notes n ON n.id = d.note_id
+             ORDER BY n.path, d.line, d.rowid",
+        )?;
+        let diagnostics = stmt
+            .query_map([], |row| {
+                Ok(NoteDiagnostic {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    diagnostic: Diagnostic {
+                        message: row.get(1)?,
+                        line: row.get::<_, Option<i64>>(2)?.map(|l| l as usize),
+                        column: row.get::<_, Option<i64>>(3)?.map(|c| c as usize),
+                    },
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(diagnostics)
+    }
+
+    /// Audits the vault and its index without changing either.
+    ///
+    /// Problems come grouped by kind, in the order of [`Problem`]'s variants,
+    /// and sorted by path within a kind. Frontmatter and link problems are
+    /// as of the last sync.
+    pub fn check(&self) -> Result<Vec<Problem>> {
+        let mut indexed: HashMap<String, Option<String>> = self
+            .conn
+            .prepare("SELECT path, content_hash FROM notes")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let (mut unindexed, mut outdated, mut invalid_utf8) = (Vec::new(), Vec::new(), Vec::new());
+        let mut on_disk = Vec::new();
+        for path in Vault::new(&self.root_dir).files().iter().filter(|path| is_note_file(path)) {
+            let Ok(relative_path) = path.strip_prefix(&self.root_dir) else {
+                continue;
+            };
+            let bytes = match fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let hash = indexed.remove(relative_path.to_string_lossy().as_ref());
+            if std::str::from_utf8(&bytes).is_err() {
+                invalid_utf8.push(Problem::InvalidUtf8 { path: relative_path.to_path_buf() });
+            } else if hash.is_none() {
+                unindexed.push(Problem::Unindexed { path: relative_path.to_path_buf() });
+            } else if hash.flatten().as_deref() != Some(content_hash(&bytes).as_str()) {
+                outdated.push(Problem::Outdated { path: relative_path.to_path_buf() });
+            }
+            on_disk.push(relative_path.to_path_buf());
+        }
+        let mut stale: Vec<String> = indexed.into_keys().collect();
+        stale.sort();
+
+        let mut problems = unindexed;
+        problems.append(&mut outdated);
+        problems.extend(stale.into_iter().map(|path| Problem::Stale { path: path.into() }));
+        problems.append(&mut invalid_utf8);
+        problems.extend(
+            self.diagnostics()?
+                .into_iter()
+                .map(|d| Problem::InvalidFrontmatter { path: d.path, diagnostic: d.diagnostic }),
+        );
+        problems.extend(
+            self.unresolved_links()?
+                .into_iter()
+                .map(|link| Problem::BrokenLink { source: link.source, line: link.line, target: link.target }),
+        );
+
+        // Wikilinks and links match names and paths ignoring case.
+        let mut by_name: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        let mut by_path: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for path in &on_disk {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            by_name.entry(stem.to_lowercase()).or_default().push(path.clone());
+            by_path.entry(path.to_string_lossy().to_lowercase()).or_default().push(path.clone());
+        }
+        for paths in by_name.into_values().filter(|paths| paths.len() > 1) {
+            let name = paths[0].file_stem().unwrap_or_default().to_string_lossy().to_string();
+            problems.push(Problem::AmbiguousName { name, paths });
+        }
+        problems.extend(by_path.into_values().filter(|paths| paths.len() > 1).map(|paths| Problem::CaseCollision { paths }));
+
+        problems.extend(self.outside_links()?);
+        Ok(problems)
+    }
+
+    /// Symlinks in the vault that lead to note files outside it. Sync never
+    /// follows them, but reading a note through one would.
+    fn outside_links(&self) -> Result<Vec<Problem>> {
+        let root = fs::canonicalize(&self.root_dir)?;
+        let mut problems = Vec::new();
+        for entry in vault::walk(&self.root_dir).filter(|e| e.path_is_symlink()) {
+            // Dangling links lead nowhere.
+            let Ok(target) = fs::canonicalize(entry.path()) else {
+                continue;
+            };
+            let Ok(relative_path) = entry.path().strip_prefix(&self.root_dir) else {
+                continue;
+            };
+            let has_notes = if target.is_dir() {
+                WalkDir::new(&target).into_iter().filter_map(|e| e.ok()).any(|e| e.file_type().is_file() && is_note_file(e.path()))
+            } else {
+                is_note_file(entry.path()) || is_note_file(&target)
+            };
+            if has_notes && !target.starts_with(&root) {
+                problems.push(Problem::OutsideRoot { path: relative_path.to_path_buf(), target });
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Fixes the problems that concern only the index by re-reading the notes
+    /// behind them. Notes themselves are never changed, so the rest is left
+    /// to the user.
+    pub fn repair(&mut self, problems: &[Problem]) -> Result<SyncReport> {
+        let mut changes = Vec::new();
+        for problem in problems {
+            match problem {
+                Problem::Unindexed { path } => changes.push(Change::Upsert(path.clone())),
+                Problem::Outdated { path } => {
+                    // The stamp may still match if the file kept its size and
+                    // mtime; clear it so the note is read again.
+                    self.conn.execute("UPDATE notes SET mtime = NULL WHERE path = ?1", params![path.to_string_lossy()])?;
+                    changes.push(Change::Upsert(path.clone()));
+                }
+                Problem::Stale { path } => changes.push(Change::Remove(path.clone())),
+                _ => {}
+            }
+        }
+        if changes.is_empty() {
+            return Ok(SyncReport::default());
+        }
+        self.apply_changes(&changes)
+    }
+
+    fn links_where(&self, condition: &str, params: impl rusqlite::Params) -> Result<Vec<Link>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT n.path, l.line, l.kind, l.target, l.target_path
+             FROM links l JOIN notes n ON n.id = l.source_id
+             WHERE {condition}
+             ORDER BY n.path, l.line, l.rowid"
+        ))?;
+        let links = stmt
+            .query_map(params, |row| {
+                Ok(Link {
+                    source: PathBuf::from(row.get::<_, String>(0)?),
+                    line: row.get::<_, i64>(1)? as usize,
+                    kind: link_kind(&row.get::<_, String>(2)?),
+                    target: row.get(3)?,
+                    resolved: row.get::<_, Option<String>>(4)?.map(PathBuf::from),
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(links)
+    }
+
+    fn note_id(&self, relative_path: &Path) -> Result<i64> {
+        self.conn
+            .query_row("SELECT id FROM notes WHERE path = ?1", params![relative_path.to_string_lossy()], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| Error::NotFound(relative_path.to_path_buf()))
+    }
+
+    /// Reads a note. Malformed frontmatter does not fail the read; it is
+    /// reported in [`Note::diagnostics`], with the raw text in
+    /// [`Note::frontmatter`].
+    pub fn get(&self, relative_path: &Path) -> Result<Note> {
+        let path = self.secure_join(relative_path)?;
+        parse::read(&path, relative_path)
+    }
+
+    /// The note file exactly as it is on disk, frontmatter included.
+    pub fn read_raw(&self, relative_path: &Path) -> Result<String> {
+        let path = self.secure_join(relative_path)?;
+        fs::read_to_string(&path).map_err(|e| Error::from_io(e, relative_path))
+    }
+
+    pub fn create(&mut self, relative_path: &Path, content: &str, metadata: Option<Metadata>) -> Result<()> {
+        let _lock = self.lock(Mode::Shared)?;
+        let path = self.secure_join(relative_path)?;
+        if path.exists() {
+            return Err(Error::AlreadyExists(relative_path.to_path_buf()));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut metadata = metadata;
+        if self.stamp_times {
+            let now = timestamps::format(timestamps::now());
+            let meta = metadata.get_or_insert_with(Metadata::default);
+            meta.created_at.get_or_insert_with(|| now.clone());
+            meta.updated_at.get_or_insert(now);
+        }
+
+        let mut file_content = String::new();
+        if let Some(ref meta) = metadata {
+            let block = frontmatter::render_block(self.frontmatter_format, meta).map_err(|e| Error::InvalidFrontmatter {
+                path: relative_path.to_path_buf(),
+                message: e.to_string(),
+            })?;
+            file_content.push_str(&block);
+        }
+        file_content.push_str(content);
+
+        self.write_version(relative_path, &path, None, file_content.as_bytes())?;
+        self.commit(format!("create: {}", relative_path.display()), &[relative_path])
+    }
+
+    /// Replaces the body and/or sets the frontmatter title.
+    ///
+    /// Frontmatter text is kept exactly as it was apart from the `title` line,
+    /// and notes without frontmatter only gain a block when a title is set.
+    pub fn update(
+        &mut self,
+        relative_path: &Path,
+        content: Option<&str>,
+        title: Option<&str>,
+        expected: Option<&str>,
+    ) -> Result<()> {
+        self.rewrite(relative_path, expected, |file| {
+            if let Some(content) = content {
+                file.set_body(content);
+            }
+            if let Some(title) = title {
+                file.set(&["title"], &serde_yaml::Value::String(title.to_string()))?;
             }
+            Ok(())
+        })
+        .map(|_| ())
+    }
+
+    /// Applies frontmatter edits in order and re-indexes the note.
+    pub fn update_metadata(&mut self, relative_path: &Path, edits: &[MetadataEdit], expected: Option<&str>) -> Result<()> {
+        self.rewrite(relative_path, expected, |file| {
+            for edit in edits {
+                file.apply(edit)?;
+            }
+            Ok(())
+        })
+        .map(|_| ())
+    }
+
+    /// Edits part of the body, leaving the frontmatter and the rest of the
+    /// body untouched.
+    pub fn edit_body(&mut self, relative_path: &Path, edit: &BodyEdit, expected: Option<&str>) -> Result<()> {
+        self.rewrite(relative_path, expected, |file| {
+            let body = body::apply(file.body(), edit)?;
+            file.set_body(&body);
+            Ok(())
+        })
+        .map(|_| ())
+    }
+
+    /// Applies every part of `edit` in one write, so the note is never left
+    /// half-edited and `expected` covers all of it. Returns the note's
+    /// revision afterwards.
+    pub fn edit(&mut self, relative_path: &Path, edit: &NoteEdit, expected: Option<&str>) -> Result<String> {
+        self.rewrite(relative_path, expected, |file| {
+            if let Some(content) = &edit.content {
+                file.set_body(content);
+            }
+            if let Some(body_edit) = &edit.body_edit {
+                let body = body::apply(file.body(), body_edit)?;
+                file.set_body(&body);
+            }
+            if let Some(title) = &edit.title {
+                file.set(&["title"], &serde_yaml::Value::String(title.clone()))?;
+            }
+            for metadata_edit in &edit.metadata {
+                file.apply(metadata_edit)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Reads a note, lets `edit` change it, writes it back and re-indexes it.
+    /// Returns the revision the note ends up at.
+    ///
+    /// With `expected` set, fails with [`Error::Conflict`] unless the file
+    /// still has that revision.
+    fn rewrite<F>(&mut self, relative_path: &Path, expected: Option<&str>, edit: F) -> Result<String>
+    where
+        F: FnOnce(&mut NoteFile) -> std::result::Result<(), EditError>,
+    {
+        let _lock = self.lock(Mode::Shared)?;
+        let path = self.secure_join(relative_path)?;
+        let raw = fs::read_to_string(&path).map_err(|e| Error::from_io(e, relative_path))?;
+        check_revision(relative_path, raw.as_bytes(), expected)?;
+
+        let mut file = NoteFile::parse(&raw).with_default_format(self.frontmatter_format);
+        let updated_before = file.get(&["updated_at"]).ok().flatten();
+        edit(&mut file).map_err(|e| e.into_error(relative_path))?;
+        let mut file_content = file.to_string();
+        if file_content == raw {
+            return Ok(content_hash(raw.as_bytes()));
+        }
+        if self.stamp_times {
+            // Frontmatter that is not a mapping is written as edited,
+            // without times.
+            let created = NoteTimes::of_file(&fs::metadata(&path)?).created;
+            if stamp_note(&mut file, updated_before, created).is_ok() {
+                file_content = file.to_string();
+            }
+        }
+        self.write_version(relative_path, &path, Some(raw.as_bytes()), file_content.as_bytes())?;
+        self.commit(format!("update: {}", relative_path.display()), &[relative_path])?;
+        Ok(content_hash(file_content.as_bytes()))
+    }
+
+    /// Writes `content` to a note atomically and re-indexes it, keeping the
+    /// version it replaces (if any) and the new one in its history.
+    fn write_version(&mut self, relative_path: &Path, path: &Path, previous: Option<&[u8]>, content: &[u8]) -> Result<()> {
+        if let Some(previous) = previous {
+            history::record(&self.root_dir, relative_path, previous)?;
+        }
+        let staged = Staged::new(path, content)?;
+        // Indexed from the staged file, which the rename keeps as is, and
+        // committed only once the rename succeeded.
+        let tx = write_tx(&mut self.conn)?;
+        let mut report = SyncReport::default();
+        upsert_file(&tx, staged.temp(), relative_path, &fs::metadata(staged.temp())?, &mut report)?;
+        resolve_links(&tx)?;
+        staged.commit()?;
+        tx.commit()?;
+        history::record(&self.root_dir, relative_path, content)
+    }
+
+    /// Saved versions of a note, newest first, including those from before
+    /// it was moved. Only versions written by extt are kept.
+    pub fn history(&self, relative_path: &Path) -> Result<Vec<HistoryEntry>> {
+        let path = self.secure_join(relative_path)?;
+        let entries = history::entries(&self.root_dir, relative_path)?;
+        if entries.is_empty() && !path.exists() {
+            return Err(Error::NotFound(relative_path.to_path_buf()));
+        }
+        Ok(entries)
+    }
+
+    /// Content of a saved version of a note. `revision` may be shortened to
+    /// any unambiguous prefix of at least four characters.
+    pub fn read_revision(&self, relative_path: &Path, revision: &str) -> Result<(HistoryEntry, String)> {
+        self.secure_join(relative_path)?;
+        let entry = history::find(&self.root_dir, relative_path, revision)?;
+        let bytes = history::load(&self.root_dir, &entry.revision)?;
+        let content = String::from_utf8(bytes).map_err(|_| Error::InvalidUtf8(relative_path.to_path_buf()))?;
+        Ok((entry, content))
+    }
+
+    /// Unified diff from a saved version to the note as it is now. A note
+    /// that no longer exists diffs against an empty file.
+    pub fn diff(&self, relative_path: &Path, revision: &str) -> Result<String> {
+        let (entry, old) = self.read_revision(relative_path, revision)?;
+        let new = match self.read_raw(relative_path) {
+            Ok(text) => text,
+            Err(Error::NotFound(_)) => String::new(),
+            Err(err) => return Err(err),
+        };
+        let old_header = format!("{}@{}", entry.path.display(), &entry.revision[..12]);
+        let new_header = relative_path.display().to_string();
+        Ok(similar::TextDiff::from_lines(&old, &new).unified_diff().header(&old_header, &new_header).to_string())
+    }
+
+    /// Writes a saved version back as the note's current content, recreating
+    /// the note if it was deleted. The version it replaces stays in history,
+    /// so a revert can itself be reverted.
+    pub fn revert(&mut self, relative_path: &Path, revision: &str, expected: Option<&str>) -> Result<()> {
+        let _lock = self.lock(Mode::Shared)?;
+        let path = self.secure_join(relative_path)?;
+        let (_, content) = self.read_revision(relative_path, revision)?;
+        let current = match fs::read(&path) {
+            Ok(bytes) => Some(bytes),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        match current {
+            Some(raw) => {
+                check_revision(relative_path, &raw, expected)?;
+                if raw == content.as_bytes() {
+                    return Ok(());
+                }
+                self.write_version(relative_path, &path, Some(&raw), content.as_bytes())?;
+            }
+            None if expected.is_some() => return Err(Error::NotFound(relative_path.to_path_buf())),
+            None => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                self.write_version(relative_path, &path, None, content.as_bytes())?;
+            }
+        }
+        self.commit(format!("revert: {}", relative_path.display()), &[relative_path])
+    }
+
+    /// Moves a note to the vault's trash, from where [`Store::restore`] can
+    /// bring it back.
+    pub fn delete(&mut self, relative_path: &Path, expected: Option<&str>) -> Result<()> {
+        self.remove_note(relative_path, expected, false)
+    }
+
+    /// Deletes a note without keeping a copy in the trash.
+    pub fn delete_permanently(&mut self, relative_path: &Path, expected: Option<&str>) -> Result<()> {
+        self.remove_note(relative_path, expected, true)
+    }
+
+    fn remove_note(&mut self, relative_path: &Path, expected: Option<&str>, permanent: bool) -> Result<()> {
+        let _lock = self.lock(Mode::Shared)?;
+        let path = self.secure_join(relative_path)?;
+        if is_meta_path(relative_path) {
+            return Err(Error::NotFound(relative_path.to_path_buf()));
+        }
+        let existed = path.exists();
+        if existed || expected.is_some() {
+            let raw = fs::read(&path).map_err(|e| Error::from_io(e, relative_path))?;
+            check_revision(relative_path, &raw, expected)?;
+            // Keeps the last version even once the trash is emptied.
+            history::record(&self.root_dir, relative_path, &raw)?;
+        }
+        if existed && permanent {
+            fs::remove_file(path)?;
+        } else if existed {
+            trash::put(&self.root_dir, &path, relative_path)?;
+        }
+        // A note that was already deleted on disk may still linger in the index.
+        let unindexed = unindex_note(&self.conn, &relative_path.to_string_lossy())?;
+        if !existed && unindexed == 0 {
+            return Err(Error::NotFound(relative_path.to_path_buf()));
+        }
+        resolve_links(&self.conn)?;
+        self.commit(format!("delete: {}", relative_path.display()), &[relative_path])
+    }
+    
+    pub fn move_note(&mut self, from: &Path, to: &Path, expected: Option<&str>) -> Result<()> {
+        self.move_note_with(from, to, expected, &MoveOptions::default()).map(|_| ())
+    }
+
+    /// Moves a note and, with [`MoveOptions::rewrite_links`], rewrites the
+    /// links that would otherwise break: wikilinks and relative markdown links
+    /// to it in other notes, and relative markdown links in the note itself.
+    ///
+    /// Either the move and every rewrite land, or none of them do. Returns
+    /// the changed lines; with [`MoveOptions::dry_run`] it only returns them.
+    pub fn move_note_with(
+        &mut self,
+        from: &Path,
+        to: &Path,
+        expected: Option<&str>,
+        options: &MoveOptions,
+    ) -> Result<Vec<LinkRewrite>> {
+        let _lock = self.lock(Mode::Exclusive)?;
+        let from_path = self.secure_join(from)?;
+        let to_path = self.secure_join(to)?;
+        if !from_path.exists() {
+            return Err(Error::NotFound(from.to_path_buf()));
+        }
+        if to_path.exists() {
+            return Err(Error::AlreadyExists(to.to_path_buf()));
+        }
+        let moved = fs::read(&from_path).map_err(|e| Error::from_io(e, from))?;
+        check_revision(from, &moved, expected)?;
+
+        let plan = if options.rewrite_links { self.plan_link_rewrites(from, to)? } else { Vec::new() };
+        let rewrites: Vec<LinkRewrite> = plan.iter().flat_map(|file| file.lines.iter().cloned()).collect();
+        if options.dry_run {
+            return Ok(rewrites);
         }
+
+        history::record(&self.root_dir, from, &moved)?;
+        for file in &plan {
+            history::record(&self.root_dir, &file.relative_path, file.original.as_bytes())?;
+        }
+
+        // Stage every rewritten file next to its original before touching
+        // anything, so most failures leave the vault exactly as it was.
+        let mut staged = Vec::new();
+        for file in &plan {
+            staged.push(Staged::new(&self.secure_join(&file.relative_path)?, file.content.as_bytes())?);
+        }
+        if let Err((err, committed)) = commit_move(staged, &from_path, &to_path) {
+            for file in plan.iter().take(committed) {
+                let _ = atomic::write(&self.secure_join(&file.relative_path)?, file.original.as_bytes());
+            }
+            return Err(err.into());
+        }
+
+        for file in &plan {
+            if file.relative_path != from {
+                history::record(&self.root_dir, &file.relative_path, file.content.as_bytes())?;
+            }
+        }
+        history::record_move(&self.root_dir, from, to, &fs::read(&to_path)?)?;
+
+        let tx = write_tx(&mut self.conn)?;
+        // `to` may still have a stale index entry from a file deleted behind
+        // the index's back.
+        unindex_note(&tx, &to.to_string_lossy())?;
+        tx.execute("UPDATE notes SET path = ?1 WHERE path = ?2", params![to.to_string_lossy(), from.to_string_lossy()])?;
+        tx.execute("UPDATE notes_fts SET path = ?1 WHERE path = ?2", params![to.to_string_lossy(), from.to_string_lossy()])?;
+        let mut report = SyncReport::default();
+        for file in &plan {
+            let relative_path = if file.relative_path == from { to } else { &file.relative_path };
+            let path = secure_join(&self.root_dir, relative_path)?;
+            upsert_file(&tx, &path, relative_path, &fs::metadata(&path)?, &mut report)?;
+        }
+        resolve_links(&tx)?;
         tx.commit()?;
+
+        let mut paths = vec![from, to];
+        paths.extend(plan.iter().map(|file| file.relative_path.as_path()).filter(|path| *path != from));
+        self.commit(format!("move: {} -> {}", from.display(), to.display()), &paths)?;
+        Ok(rewrites)
+    }
+
+    /// New contents for every note whose links to `from` (or, for `from`
+    /// itself, whose relative links) would stop resolving after the move.
+    fn plan_link_rewrites(&self, from: &Path, to: &Path) -> Result<Vec<FileRewrite>> {
+        let (from_str, to_str) = (from.to_string_lossy().into_owned(), to.to_string_lossy().into_owned());
+        let paths: Vec<String> = self
+            .conn
+            .prepare("SELECT path FROM notes")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let before = Resolver::new(paths.iter().cloned().chain([from_str.clone()]));
+        let after = Resolver::new(paths.into_iter().filter(|p| *p != from_str).chain([to_str.clone()]));
+
+        let mut sources: Vec<String> = self
+            .conn
+            .prepare(
+                "SELECT DISTINCT n.path FROM links l JOIN notes n ON n.id = l.source_id
+                 WHERE l.target_path = ?1 ORDER BY n.path",
+            )?
+            .query_map(params![from_str], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        if !sources.contains(&from_str) {
+            sources.insert(0, from_str.clone());
+        }
+
+        let mut plan = Vec::new();
+        for source in sources {
+            let relative_path = PathBuf::from(&source);
+            let original = self.read_raw(&relative_path)?;
+            let mut file = NoteFile::parse(&original);
+            let body = file.body().to_string();
+            let source_after = if source == from_str { to_str.as_str() } else { source.as_str() };
+
+            let mut edits = Vec::new();
+            for link in links::parse(&body) {
+                let Some(target) = before.resolve(&source, link.kind, &link.target) else {
+                    continue;
+                };
+                let intended = if target == from_str { to_str.as_str() } else { target };
+                if after.resolve(source_after, link.kind, &link.target) == Some(intended) {
+                    continue;
+                }
+                let text = links::retarget(link.kind, &body[link.span.clone()], source_after, intended, &after);
+                edits.push((link.span, link.line, text));
+            }
+            if edits.is_empty() {
+                continue;
+            }
+
+            let mut new_body = body.clone();
+            for (span, _, text) in edits.iter().rev() {
+                new_body.replace_range(span.clone(), text);
+            }
+            let (old_lines, new_lines): (Vec<&str>, Vec<&str>) = (body.lines().collect(), new_body.lines().collect());
+            let mut changed: Vec<usize> = edits.iter().map(|(_, line, _)| *line).collect();
+            changed.dedup();
+            let lines = changed
+                .into_iter()
+                .map(|line| LinkRewrite {
+                    path: relative_path.clone(),
+                    line,
+                    before: old_lines[line - 1].to_string(),
+                    after: new_lines[line - 1].to_string(),
+                })
+                .collect();
+
+            file.set_body(&new_body);
+            plan.push(FileRewrite { content: file.to_string(), relative_path, original, lines });
+        }
+        Ok(plan)
+    }
+
+    /// Notes in the trash, most recently deleted first.
+    pub fn trash(&self) -> Result<Vec<TrashEntry>> {
+        trash::list(&self.root_dir)
+    }
+
+    /// Brings a note back from the trash. `name` is a trash id or the note's
+    /// old path (`.md` optional), which picks its most recent deletion.
+    ///
+    /// The note goes to `to`, or else to its old path; if a note already
+    /// lives there, it is restored next to it as `name (restored).md`.
+    /// Returns the path it was restored to.
+    pub fn restore(&mut self, name: &str, to: Option<&Path>) -> Result<PathBuf> {
+        let _lock = self.lock(Mode::Shared)?;
+        let entry = trash::list(&self.root_dir)?
+            .into_iter()
+            .find(|e| e.id == name || e.path == Path::new(name) || e.path == Path::new(&format!("{name}.md")))
+            .ok_or_else(|| Error::NotFound(PathBuf::from(name)))?;
+
+        let relative_path = match to {
+            Some(to) if self.secure_join(to)?.exists() => return Err(Error::AlreadyExists(to.to_path_buf())),
+            Some(to) => to.to_path_buf(),
+            None => self.free_path(&entry.path)?,
+        };
+        let path = self.secure_join(&relative_path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        trash::take(&self.root_dir, &entry, &path)?;
+        history::record(&self.root_dir, &relative_path, &fs::read(&path)?)?;
+
+        let mut report = SyncReport::default();
+        upsert_file(&self.conn, &path, &relative_path, &fs::metadata(&path)?, &mut report)?;
+        resolve_links(&self.conn)?;
+        self.commit(format!("restore: {}", relative_path.display()), &[&relative_path])?;
+        Ok(relative_path)
+    }
+
+    /// Permanently deletes trashed notes, or with `older_than` only those
+    /// deleted at least that long ago. Returns how many were removed.
+    pub fn empty_trash(&mut self, older_than: Option<Duration>) -> Result<usize> {
+        let _lock = self.lock(Mode::Shared)?;
+        let cutoff = match older_than {
+            None => None,
+            // Nothing can have been deleted longer ago than time can say.
+            Some(age) => match i64::try_from(age.as_secs()).ok().and_then(|age| timestamps::now().checked_sub(age)) {
+                Some(cutoff) => Some(cutoff),
+                None => return Ok(0),
+            },
+        };
+        let mut removed = 0;
+        for entry in trash::list(&self.root_dir)? {
+            if cutoff.is_none_or(|cutoff| entry.deleted_at <= cutoff) {
+                trash::purge(&self.root_dir, &entry)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// `relative_path`, or the first `stem (restored N).md` next to it that
+    /// is not taken.
+    fn free_path(&self, relative_path: &Path) -> Result<PathBuf> {
+        let stem = relative_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut candidate = relative_path.to_path_buf();
+        for n in 1.. {
+            if !self.secure_join(&candidate)?.exists() {
+                break;
+            }
+            let suffix = if n == 1 { String::new() } else { format!(" {n}") };
+            candidate = relative_path.with_file_name(format!("{stem} (restored{suffix}).md"));
+        }
+        Ok(candidate)
+    }
+
+    /// Commits every change made through this store to the git repository
+    /// the vault is in, creating one in the vault if there is none.
+    pub fn enable_git(&mut self, options: GitOptions) -> Result<()> {
+        self.git = Some(Git::open(&self.root_dir, options)?);
+        Ok(())
+    }
+
+    /// Commits from the vault's git history, newest first; with
+    /// `relative_path`, only those that changed that note.
+    pub fn git_log(&self, relative_path: Option<&Path>) -> Result<Vec<GitCommit>> {
+        let git = self.git.as_ref().ok_or(Error::GitDisabled)?;
+        if let Some(relative_path) = relative_path {
+            self.secure_join(relative_path)?;
+        }
+        git.log(relative_path)
+    }
+
+    fn commit(&self, message: String, paths: &[&Path]) -> Result<()> {
+        match &self.git {
+            Some(git) => git.commit(&message, paths),
+            None => Ok(()),
+        }
+    }
+
+    fn lock(&self, mode: Mode) -> Result<VaultLock> {
+        lock::acquire(&self.root_dir, mode, self.busy_timeout)
+    }
+
+    fn secure_join(&self, relative_path: &Path) -> Result<PathBuf> {
+        secure_join(&self.root_dir, relative_path)
+    }
+}
+
+/// A note rewritten by [`Store::move_note_with`].
+struct FileRewrite {
+    relative_path: PathBuf,
+    original: String,
+    content: String,
+    lines: Vec<LinkRewrite>,
+}
+
+/// Starts a transaction that takes SQLite's write lock up front. A deferred
+/// one that reads first can fail with `SQLITE_BUSY` when it later tries to
+/// write, without waiting for the busy timeout.
+fn write_tx(conn: &mut Connection) -> Result<Transaction<'_>> {
+    Ok(conn.transaction_with_behavior(TransactionBehavior::Immediate)?)
+}
+
+/// Swaps staged files in and then renames the note. On failure, returns how
+/// many staged files had already replaced their originals; the rest are
+/// discarded.
+fn commit_move(staged: Vec<Staged>, from: &Path, to: &Path) -> std::result::Result<(), (std::io::Error, usize)> {
+    let count = staged.len();
+    for (committed, file) in staged.into_iter().enumerate() {
+        file.commit().map_err(|err| (err, committed))?;
+    }
+    let moved = to.parent().map_or(Ok(()), fs::create_dir_all).and_then(|()| fs::rename(from, to));
+    moved.map_err(|err| (err, count))
+}
+
+/// Failure inside a [`Store::rewrite`] callback, before the note path is
+/// attached.
+enum EditError {
+    Frontmatter(FrontmatterError),
+    Body(BodyEditError),
+}
+
+impl From<FrontmatterError> for EditError {
+    fn from(err: FrontmatterError) -> Self {
+        EditError::Frontmatter(err)
+    }
+}
+
+impl From<BodyEditError> for EditError {
+    fn from(err: BodyEditError) -> Self {
+        EditError::Body(err)
+    }
+}
+
+impl EditError {
+    fn into_error(self, relative_path: &Path) -> Error {
+        let path = relative_path.to_path_buf();
+        match self {
+            EditError::Frontmatter(err) => Error::InvalidFrontmatter { path, message: err.to_string() },
+            EditError::Body(BodyEditError::InvalidRange { from, to, lines }) => Error::InvalidRange { path, from, to, lines },
+            EditError::Body(BodyEditError::SectionNotFound(heading)) => Error::SectionNotFound { path, heading },
+        }
+    }
+}
+
+/// Change-detection stamp of a file as it exists on disk.
+struct FileStamp {
+    size: i64,
+    mtime: i64,
+    hash: String,
+}
+
+/// Stamp as last recorded in the index. Columns are NULL for rows written
+/// before change tracking existed.
+struct IndexedStamp {
+    size: Option<i64>,
+    mtime: Option<i64>,
+    hash: Option<String>,
+}
+
+impl IndexedStamp {
+    fn from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Self> {
+        Ok(Self {
+            size: row.get(first)?,
+            mtime: row.get(first + 1)?,
+            hash: row.get(first + 2)?,
+        })
+    }
+}
+
+/// A note's canonical `created_at` and `updated_at`.
+struct NoteTimes {
+    created: Option<String>,
+    updated: Option<String>,
+}
+
+impl NoteTimes {
+    /// Times set in the frontmatter.
+    fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            created: metadata.created_at.as_deref().and_then(timestamps::normalize),
+            updated: metadata.updated_at.as_deref().and_then(timestamps::normalize),
+        }
+    }
+
+    /// Times of the file itself, for notes whose frontmatter has none. Not
+    /// every filesystem records creation times; the modification time
+    /// stands in for those.
+    fn of_file(metadata: &fs::Metadata) -> Self {
+        let modified = metadata.modified().ok().map(timestamps::from_system);
+        let created = metadata.created().ok().map(timestamps::from_system).or(modified);
+        Self { created: created.map(timestamps::format), updated: modified.map(timestamps::format) }
+    }
+}
+
+/// Sets `updated_at` to now unless the edit already changed it, and gives
+/// notes without a `created_at` the time their file was `created`.
+fn stamp_note(file: &mut NoteFile, updated_before: Option<serde_yaml::Value>, created: Option<String>) -> std::result::Result<(), FrontmatterError> {
+    if file.get(&["created_at"])?.is_none() {
+        if let Some(created) = created {
+            file.set(&["created_at"], &serde_yaml::Value::String(created))?;
+        }
+    }
+    if file.get(&["updated_at"])? == updated_before {
+        file.set(&["updated_at"], &serde_yaml::Value::String(timestamps::format(timestamps::now())))?;
+    }
+    Ok(())
+}
+
+enum FileOutcome {
+    Added,
+    Updated,
+    Unchanged,
+    Skipped,
+}
+
+impl SyncReport {
+    fn record(&mut self, outcome: FileOutcome) {
+        match outcome {
+            FileOutcome::Added => self.added += 1,
+            FileOutcome::Updated => self.updated += 1,
+            FileOutcome::Unchanged => self.unchanged += 1,
+            FileOutcome::Skipped => self.skipped += 1,
+        }
+    }
+}
+
+fn is_note_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "md")
+}
+
+/// Indexes one note file, skipping the read when its stamp is unchanged and
+/// the parse when its content hash is unchanged.
+fn index_file(
+    conn: &Connection,
+    path: &Path,
+    relative_path: &str,
+    metadata: &fs::Metadata,
+    previous: Option<&IndexedStamp>,
+) -> Result<FileOutcome> {
+    let (size, mtime) = file_times(metadata);
+    if let Some(prev) = previous {
+        if prev.size == Some(size) && prev.mtime == Some(mtime) {
+            return Ok(FileOutcome::Unchanged);
+        }
+    }
+
+    let bytes = fs::read(path)?;
+    let stamp = FileStamp { size, mtime, hash: content_hash(&bytes) };
+
+    if previous.and_then(|p| p.hash.as_deref()) == Some(stamp.hash.as_str()) {
+        conn.execute(
+            "UPDATE notes SET size = ?1, mtime = ?2 WHERE path = ?3",
+            params![stamp.size, stamp.mtime, relative_path],
+        )?;
+        return Ok(FileOutcome::Unchanged);
+    }
+
+    // Such a file cannot be indexed, but it should not stop the rest of the
+    // vault from being; `Store::check` reports it.
+    let Ok(content) = String::from_utf8(bytes) else {
+        if previous.is_some() {
+            unindex_note(conn, relative_path)?;
+        }
+        return Ok(FileOutcome::Skipped);
+    };
+    // Malformed frontmatter does not keep a note out of the index; whatever
+    // could be read of it is used, and the problems are recorded.
+    let note = Note::parse(PathBuf::from(relative_path), &content);
+    index_note(conn, relative_path, &note, &stamp, &NoteTimes::of_file(metadata))?;
+
+    Ok(if previous.is_some() { FileOutcome::Updated } else { FileOutcome::Added })
+}
+
+/// Re-indexes whatever currently lives at `relative_path`: a single note, every
+/// note below a directory, or nothing at all if the path is gone.
+fn upsert_path(conn: &Connection, root_dir: &Path, relative_path: &Path, report: &mut SyncReport) -> Result<()> {
+    if is_meta_path(relative_path) {
+        return Ok(());
+    }
+    let path = root_dir.join(relative_path);
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            report.removed += remove_path(conn, relative_path)?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    if metadata.is_dir() {
+        for entry in WalkDir::new(&path).into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_file() && is_note_file(entry.path()) {
+                let Ok(relative) = entry.path().strip_prefix(root_dir) else {
+                    continue;
+                };
+                upsert_file(conn, entry.path(), relative, &entry.metadata()?, report)?;
+            }
+        }
+    } else if metadata.is_file() && is_note_file(&path) {
+        upsert_file(conn, &path, relative_path, &metadata, report)?;
+    }
+    Ok(())
+}
+
+fn upsert_file(conn: &Connection, path: &Path, relative_path: &Path, metadata: &fs::Metadata, report: &mut SyncReport) -> Result<()> {
+    let relative_path = relative_path.to_string_lossy();
+    let previous = conn
+        .query_row(
+            "SELECT size, mtime, content_hash FROM notes WHERE path = ?1",
+            params![relative_path],
+            |row| IndexedStamp::from_row(row, 0),
+        )
+        .optional()?;
+    let outcome = index_file(conn, path, &relative_path, metadata, previous.as_ref())?;
+    report.record(outcome);
+    Ok(())
+}
+
+/// Drops a note, or every note below a directory, from the index.
+fn remove_path(conn: &Connection, relative_path: &Path) -> Result<usize> {
+    let relative_path = relative_path.to_string_lossy();
+    let prefix = format!("{}/", relative_path);
+    conn.execute(
+        "DELETE FROM note_tags WHERE note_id IN (
+            SELECT id FROM notes WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2
+         )",
+        params![relative_path, prefix],
+    )?;
+    conn.execute(
+        "DELETE FROM links WHERE source_id IN (
+            SELECT id FROM notes WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2
+         )",
+        params![relative_path, prefix],
+    )?;
+    conn.execute(
+        "DELETE FROM diagnostics WHERE note_id IN (
+            SELECT id FROM notes WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2
+         )",
+        params![relative_path, prefix],
+    )?;
+    conn.execute(
+        "DELETE FROM notes_fts WHERE rowid IN (
+            SELECT id FROM notes WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2
+         )",
+        params![relative_path, prefix],
+    )?;
+    let removed = conn.execute(
+        "DELETE FROM notes WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+        params![relative_path, prefix],
+    )?;
+    Ok(removed)
+}
+
+fn file_times(metadata: &fs::Metadata) -> (i64, i64) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as i64);
+    (metadata.len() as i64, mtime)
+}
+
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// Fails with [`Error::Conflict`] when `raw` is not at the `expected`
+/// revision.
+fn check_revision(relative_path: &Path, raw: &[u8], expected: Option<&str>) -> Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let actual = content_hash(raw);
+    if actual != expected {
+        return Err(Error::Conflict { path: relative_path.to_path_buf(), expected: expected.to_string(), actual });
+    }
+    Ok(())
+}
+
+/// Upserts a note's summary row and replaces its full-text entry, tags,
+/// links and diagnostics.
+///
+/// Times from the frontmatter win over the `file` times. A note that lost
+/// its `created_at` keeps the one already indexed, since rewriting a file
+/// resets its creation time.
+fn index_note(conn: &Connection, relative_path: &str, note: &Note, stamp: &FileStamp, file: &NoteTimes) -> Result<()> {
+    let title = note
+        .metadata
+        .title
+        .clone()
+        .or_else(|| Path::new(relative_path).file_stem().map(|s| s.to_string_lossy().to_string()));
+    let tags = tags::collect(note.metadata.tags.as_deref().unwrap_or_default(), &note.content);
+    let times = NoteTimes::from_metadata(&note.metadata);
+    let body = note.content.as_str();
+
+    conn.execute(
+        "INSERT INTO notes (path, title, size, mtime, content_hash, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, coalesce(?6, ?8), coalesce(?7, ?9))
+         ON CONFLICT(path) DO UPDATE SET
+            title = excluded.title,
+            size = excluded.size,
+            mtime = excluded.mtime,
+            content_hash = excluded.content_hash,
+            created_at = coalesce(?6, notes.created_at, ?8),
+            updated_at = excluded.updated_at",
+        params![relative_path, title, stamp.size, stamp.mtime, stamp.hash, times.created, times.updated, file.created, file.updated],
+    )?;
+    let id: i64 = conn.query_row("SELECT id FROM notes WHERE path = ?1", params![relative_path], |row| row.get(0))?;
+
+    conn.execute("DELETE FROM notes_fts WHERE rowid = ?1", params![id])?;
+    conn.execute(
+        "INSERT INTO notes_fts (rowid, path, title, body) VALUES (?1, ?2, ?3, ?4)",
+        params![id, relative_path, title, body],
+    )?;
+
+    conn.execute("DELETE FROM note_tags WHERE note_id = ?1", params![id])?;
+    let mut insert = conn.prepare_cached("INSERT INTO note_tags (note_id, tag) VALUES (?1, ?2)")?;
+    for tag in tags {
+        insert.execute(params![id, tag])?;
+    }
+
+    // Targets are resolved by `resolve_links` once the set of notes is known.
+    conn.execute("DELETE FROM links WHERE source_id = ?1", params![id])?;
+    let mut insert = conn.prepare_cached("INSERT INTO links (source_id, line, kind, target) VALUES (?1, ?2, ?3, ?4)")?;
+    for link in links::parse(body) {
+        insert.execute(params![id, link.line as i64, link.kind.as_str(), link.target])?;
+    }
+
+    conn.execute("DELETE FROM diagnostics WHERE note_id = ?1", params![id])?;
+    let mut insert = conn.prepare_cached("INSERT INTO diagnostics (note_id, line, column, message) VALUES (?1, ?2, ?3, ?4)")?;
+    for diagnostic in &note.diagnostics {
+        insert.execute(params![id, diagnostic.line.map(|l| l as i64), diagnostic.column.map(|c| c as i64), diagnostic.message])?;
+    }
+    Ok(())
+}
+
+/// Points every link at the note it resolves to now. Needed after any
+/// change to which notes exist or what they link to.
+fn resolve_links(conn: &Connection) -> Result<()> {
+    let paths: Vec<String> = conn
+        .prepare("SELECT path FROM notes")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let resolver = Resolver::new(paths);
+
+    let rows: Vec<(i64, String, String, String, Option<String>)> = conn
+        .prepare("SELECT l.rowid, n.path, l.kind, l.target, l.target_path FROM links l JOIN notes n ON n.id = l.source_id")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut update = conn.prepare_cached("UPDATE links SET target_path = ?2 WHERE rowid = ?1")?;
+    for (rowid, source, kind, target, current) in rows {
+        let resolved = resolver.resolve(&source, link_kind(&kind), &target);
+        if resolved != current.as_deref() {
+            update.execute(params![rowid, resolved])?;
+        }
+    }
+    Ok(())
+}
+
+fn link_kind(text: &str) -> LinkKind {
+    match text {
+        "wiki" => LinkKind::Wiki,
+        _ => LinkKind::Markdown,
+    }
+}
+
+fn unindex_note(conn: &Connection, relative_path: &str) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM links WHERE source_id IN (SELECT id FROM notes WHERE path = ?1)",
+        params![relative_path],
+    )?;
+    conn.execute(
+        "DELETE FROM note_tags WHERE note_id IN (SELECT id FROM notes WHERE path = ?1)",
+        params![relative_path],
+    )?;
+    conn.execute(
+        "DELETE FROM diagnostics WHERE note_id IN (SELECT id FROM notes WHERE path = ?1)",
+        params![relative_path],
+    )?;
+    conn.execute(
+        "DELETE FROM notes_fts WHERE rowid IN (SELECT id FROM notes WHERE path = ?1)",
+        params![relative_path],
+    )?;
+    let removed = conn.execute("DELETE FROM notes WHERE path = ?1", params![relative_path])?;
+    Ok(removed)
+}
+
+/// SQL condition on notes aliased `n`, true when `?1` is NULL or the note has
+/// tag `?1` or one of its children.
+const TAG_FILTER: &str = "(?1 IS NULL OR n.id IN (
+    SELECT note_id FROM note_tags WHERE tag = ?1 OR substr(tag, 1, length(?1) + 1) = ?1 || '/'
+))";
+
+/// The query's tag in canonical form. A tag that can never match is an empty
+/// result rather than an error, so it maps to a value no note carries.
+fn query_tag(query: &ListQuery) -> Result<Option<String>> {
+    Ok(query.tag.as_deref().map(|tag| tags::normalize(tag).unwrap_or_default()))
+}
+
+/// SQL condition on notes aliased `n` for a query's tag (`?1`) and time
+/// range (`?2` and `?3`).
+fn query_filter(query: &ListQuery) -> String {
+    let column = match query.sort {
+        Some(SortKey::Created) => "n.created_at",
+        _ => "n.updated_at",
+    };
+    format!("{TAG_FILTER} AND (?2 IS NULL OR {column} >= ?2) AND (?3 IS NULL OR {column} < ?3)")
+}
+
+/// The query's time range as canonical timestamps, which compare like the
+/// times they stand for.
+fn query_bounds(query: &ListQuery) -> (Option<String>, Option<String>) {
+    (query.since.map(timestamps::format), query.until.map(timestamps::format))
+}
+
+/// `ORDER BY` clause for notes aliased `n`. Notes without a time sort last.
+fn query_order(key: SortKey) -> &'static str {
+    match key {
+        SortKey::Path => "n.path",
+        SortKey::Title => "coalesce(n.title, '') COLLATE NOCASE, n.path",
+        SortKey::Created => "n.created_at DESC, n.path",
+        SortKey::Updated => "n.updated_at DESC, n.path",
+    }
+}
+
+/// `LIMIT` value for a query; SQLite treats a negative limit as none.
+fn sql_limit(query: &ListQuery) -> i64 {
+    query.limit.map_or(-1, |limit| limit as i64)
+}
+
+/// Turns free-form user input into an FTS5 query.
+///
+/// Every term is quoted so punctuation like `-` or `:` is matched literally
+/// instead of being parsed as FTS syntax, and the last term is a prefix match
+/// so partially typed words still find something. Returns `None` when there is
+/// nothing to search for.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", terms.join(" ")))
+}
+
+#[cfg(test)]
+mod store_path_tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_secure_join() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        let store = Store {
+            conn,
+            root_dir: PathBuf::from("/notes"),
+            git: None,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            stamp_times: true,
+            frontmatter_format: FrontmatterFormat::default(),
+        };
+
+        // Valid paths
+        assert!(store.secure_join(Path::new("note.md")).is_ok());
+        assert!(store.secure_join(Path::new("sub/note.md")).is_ok());
+        assert!(store.secure_join(Path::new("./note.md")).is_ok());
+
+        // Invalid paths (traversal)
+        assert!(store.secure_join(Path::new("../etc/passwd")).is_err());
+        assert!(store.secure_join(Path::new("sub/../../etc/passwd")).is_err());
+        assert!(store.secure_join(Path::new("sub/..")).is_ok());
+        assert!(store.secure_join(Path::new("sub/../..")).is_err());
+
+        // Absolute paths
+        assert!(store.secure_join(Path::new("/etc/passwd")).is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use std::fs;
+    use std::env;
+
+    #[test]
+    fn test_store_workflow() -> Result<()> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+
+        let mut store = Store::new(notes_dir.clone(), db_path)?;
+
+        // Test Create
+        store.create(Path::new("note1.md"), "content1", Some(Metadata {
+            title: Some("Note 1".to_string()),
+            tags: None,
+            created_at: None,
+            updated_at: None,
+            extra: Default::default(),
+        }))?;
+
+        // Test List
+        let notes = store.list()?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].title.as_deref(), Some("Note 1"));
+        assert_eq!(notes[0].path, Path::new("note1.md"));
+
+        // Test Get
+        let note = store.get(Path::new("note1.md"))?;
+        assert!(note.content.contains("content1"));
+        assert_eq!(note.metadata.title.as_deref(), Some("Note 1"));
+
+        // Test Search
+        let results = store.search("Note 1")?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title.as_deref(), Some("Note 1"));
+
+        let results = store.search("content1")?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].snippet.as_deref(), Some("**content1**"));
+
+        let results = store.search("note1")?;
+        assert_eq!(results.len(), 1);
+
+        // Test Update
+        store.update(Path::new("note1.md"), Some("updated content"), None, None)?;
+        let note = store.get(Path::new("note1.md"))?;
+        assert!(note.content.contains("updated content"));
+        assert_eq!(note.metadata.title.as_deref(), Some("Note 1")); // Title preserved
+
+        // Test Move
+        store.move_note(Path::new("note1.md"), Path::new("renamed.md"), None)?;
+        assert!(!notes_dir.join("note1.md").exists());
+        assert!(notes_dir.join("renamed.md").exists());
+
+        let notes = store.list()?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].path, Path::new("renamed.md"));
+        assert_eq!(store.search("renamed")?.len(), 1);
+        assert_eq!(store.search("updated")?[0].path, Path::new("renamed.md"));
+
+        // Test Delete
+        store.delete(Path::new("renamed.md"), None)?;
+        assert!(!notes_dir.join("renamed.md").exists());
+        let notes = store.list()?;
+        assert_eq!(notes.len(), 0);
+        assert!(store.search("updated")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_errors() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("bad.md"), "---\ntags: 42\n---\nBody")?;
+
+        let mut store = Store::new(notes_dir, dir.path().join("test.db"))?;
+        store.create(Path::new("a.md"), "Alpha", None)?;
+        store.create(Path::new("b.md"), "Beta", None)?;
+
+        assert!(matches!(store.create(Path::new("a.md"), "again", None), Err(Error::AlreadyExists(_))));
+        assert!(matches!(store.get(Path::new("missing.md")), Err(Error::NotFound(p)) if p == Path::new("missing.md")));
+        assert!(matches!(store.get(Path::new("../outside.md")), Err(Error::PathTraversal(_))));
+        assert!(matches!(store.get(Path::new("/etc/passwd")), Err(Error::AbsolutePath(_))));
+        let bad = store.get(Path::new("bad.md"))?;
+        assert_eq!((bad.content.as_str(), bad.frontmatter.as_deref()), ("Body", Some("tags: 42\n")));
+        assert_eq!(bad.diagnostics[0].line, Some(2));
+        assert!(matches!(store.delete(Path::new("missing.md"), None), Err(Error::NotFound(_))));
+        assert!(matches!(store.move_note(Path::new("missing.md"), Path::new("c.md"), None), Err(Error::NotFound(_))));
+        assert!(matches!(store.move_note(Path::new("a.md"), Path::new("b.md"), None), Err(Error::AlreadyExists(_))));
+        assert_eq!(store.get(Path::new("b.md"))?.content, "Beta");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_onto_stale_entry() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.create(Path::new("a.md"), "Alpha", None)?;
+        store.create(Path::new("b.md"), "Beta", None)?;
+        fs::remove_file(notes_dir.join("b.md"))?;
+
+        store.move_note(Path::new("a.md"), Path::new("b.md"), None)?;
+        let paths: Vec<_> = store.list()?.into_iter().map(|n| n.path).collect();
+        assert_eq!(paths, vec![PathBuf::from("b.md")]);
+        assert_eq!(store.search("Alpha")?[0].path, Path::new("b.md"));
+        assert!(store.search("Beta")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_records_diagnostics() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("bad.md"), "---\ntitle: Kept\ntags: {a: 1}\n---\nBody #inline")?;
+        fs::write(notes_dir.join("broken.md"), "---\ntitle: [unclosed\n---\nBody")?;
+        fs::write(notes_dir.join("good.md"), "---\ntitle: Good\n---\nBody")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.sync()?;
+
+        let found: Vec<_> = store.diagnostics()?.into_iter().map(|d| (d.path, d.diagnostic.line)).collect();
+        assert_eq!(found, [(PathBuf::from("bad.md"), Some(3)), (PathBuf::from("broken.md"), Some(3))]);
+        // What could be read still reaches the index.
+        let titles: Vec<_> = store.list()?.into_iter().map(|n| n.title).collect();
+        assert_eq!(titles, [Some("Kept".into()), Some("broken".into()), Some("Good".into())]);
+        assert_eq!(store.list_by_tag("inline")?.len(), 1);
+
+        fs::write(notes_dir.join("bad.md"), "---\ntitle: Kept\ntags: [a]\n---\nBody")?;
+        fs::remove_file(notes_dir.join("broken.md"))?;
+        store.sync()?;
+        assert!(store.diagnostics()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_and_repair() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir_all(notes_dir.join("sub"))?;
+        fs::write(notes_dir.join("plan.md"), "See [[nowhere]].")?;
+        fs::write(notes_dir.join("sub/Plan.md"), "Other plan")?;
+        fs::write(notes_dir.join("Case.md"), "upper")?;
+        fs::write(notes_dir.join("case.md"), "lower")?;
+        fs::write(notes_dir.join("gone.md"), "soon gone")?;
+        fs::write(notes_dir.join("edited.md"), "before")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.sync()?;
+        assert!(store.check()?.iter().all(|p| !p.is_fixable()));
+
+        fs::remove_file(notes_dir.join("gone.md"))?;
+        fs::write(notes_dir.join("edited.md"), "after")?;
+        fs::write(notes_dir.join("new.md"), "new")?;
+        fs::write(notes_dir.join("binary.md"), [0xff, 0xfe, 0x00])?;
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside)?;
+        fs::write(outside.join("secret.md"), "secret")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outside, notes_dir.join("linked"))?;
+
+        let path = |p: &str| PathBuf::from(p);
+        let mut expected = vec![
+            Problem::Unindexed { path: path("new.md") },
+            Problem::Outdated { path: path("edited.md") },
+            Problem::Stale { path: path("gone.md") },
+            Problem::InvalidUtf8 { path: path("binary.md") },
+            Problem::BrokenLink { source: path("plan.md"), line: 1, target: "nowhere".into() },
+            Problem::AmbiguousName { name: "Case".into(), paths: vec![path("Case.md"), path("case.md")] },
+            Problem::AmbiguousName { name: "plan".into(), paths: vec![path("plan.md"), path("sub/Plan.md")] },
+            Problem::CaseCollision { paths: vec![path("Case.md"), path("case.md")] },
+        ];
+        #[cfg(unix)]
+        expected.push(Problem::OutsideRoot { path: path("linked"), target: fs::canonicalize(&outside)? });
+        let problems = store.check()?;
+        assert_eq!(problems, expected);
+
+        let report = store.repair(&problems)?;
+        assert_eq!((report.added, report.updated, report.removed), (1, 1, 1));
+        let left = store.check()?;
+        assert!(left.iter().all(|p| !p.is_fixable()));
+        assert_eq!(left.len(), expected.len() - 3);
+        assert_eq!(store.get(Path::new("edited.md"))?.content, "after");
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_ranks_and_highlights() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("groceries.md"), "---\ntitle: Groceries\n---\nBuy milk and eggs.\nAlso bread.")?;
+        fs::write(notes_dir.join("rust.md"), "---\ntitle: Rust ownership\n---\nBorrowing rules and lifetimes.")?;
+        fs::write(notes_dir.join("journal.md"), "Talked about rust at lunch.")?;
+
+        let mut store = Store::new(notes_dir, dir.path().join("test.db"))?;
+        store.sync()?;
+
+        let results = store.search("milk")?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, Path::new("groceries.md"));
+        assert!(results[0].snippet.as_deref().unwrap().contains("**milk**"));
+
+        // Title hits rank above body hits.
+        let results = store.search("rust")?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].path, Path::new("rust.md"));
+        assert_eq!(results[1].path, Path::new("journal.md"));
+
+        // Prefix match on the last term and literal punctuation.
+        assert_eq!(store.search("lifet")?.len(), 1);
+        assert_eq!(store.search("milk -eggs")?.len(), 1);
+        assert!(store.search("\"unbalanced")?.is_empty());
+
+        // Blank queries fall back to listing everything.
+        assert_eq!(store.search("  ")?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_body_preserves_frontmatter_text() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let frontmatter = "---\n# reviewed\ntitle: 'Quoted Title'\nzeta: 1\nalpha: [x, y]\n---\n\n";
+        fs::write(notes_dir.join("fm.md"), format!("{frontmatter}Old body\n"))?;
+        fs::write(notes_dir.join("plain.md"), "Plain old body\n")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.sync()?;
+
+        store.update(Path::new("fm.md"), Some("New body\n"), None, None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("fm.md"))?, format!("{frontmatter}New body\n"));
+        assert_eq!(store.list()?[0].title.as_deref(), Some("Quoted Title"));
+
+        store.update(Path::new("plain.md"), Some("Plain new body\n"), None, None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("plain.md"))?, "Plain new body\n");
+
         Ok(())
     }
 
-    pub fn list(&self) -> Result<Vec<NoteSummary>> {
-        let mut stmt = self.conn.prepare("SELECT path, title FROM notes ORDER BY path")?;
-        let note_iter = stmt.query_map([], |row| {
-            Ok(NoteSummary {
-                path: PathBuf::from(row.get::<_, String>(0)?),
-                title: row.get(1)?,
-            })
-        })?;
+    #[test]
+    fn test_update_title_and_metadata() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("plan.md"), "---\ntitle: Plan\nmetadata:\n  status: draft\n---\nBody\n")?;
+        fs::write(notes_dir.join("plain.md"), "No frontmatter\n")?;
 
-        let mut notes = Vec::new();
-        for note in note_iter {
-            notes.push(note?);
-        }
-        Ok(notes)
-    }
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.sync()?;
 
-    pub fn search(&self, query: &str) -> Result<Vec<NoteSummary>> {
-        let mut stmt = self.conn.prepare("SELECT path, title FROM notes WHERE title LIKE ?1 OR path LIKE ?1")?;
-         let pattern = format!("%{}%", query);
-        let note_iter = stmt.query_map(params![pattern], |row| {
-             Ok(NoteSummary {
-                path: PathBuf::from(row.get::<_, String>(0)?),
-                title: row.get(1)?,
-            })
-        })?;
+        store.update(Path::new("plan.md"), None, Some("Roadmap"), None)?;
+        store.update_metadata(Path::new("plan.md"), &[
+            MetadataEdit::Set { key: "metadata.status".into(), value: "active".into() },
+            MetadataEdit::Append { key: "tags".into(), value: "work".into() },
+        ], None)?;
+        assert_eq!(
+            fs::read_to_string(notes_dir.join("plan.md"))?,
+            "---\ntitle: Roadmap\nmetadata:\n  status: active\ntags:\n- work\n---\nBody\n"
+        );
+
+        let note = store.get(Path::new("plan.md"))?;
+        assert_eq!(note.metadata.tags, Some(vec!["work".to_string()]));
+        assert_eq!(note.metadata.extra["metadata"]["status"], "active");
+        assert_eq!(store.search("Roadmap")?[0].path, Path::new("plan.md"));
+
+        // Setting a title on a plain note adds a frontmatter block.
+        store.update(Path::new("plain.md"), Some("New body\n"), Some("Plain"), None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("plain.md"))?, "---\ntitle: Plain\n---\nNew body\n");
+        let titles: Vec<_> = store.list()?.into_iter().filter_map(|n| n.title).collect();
+        assert_eq!(titles, vec!["Plain", "Roadmap"]);
+
+        assert!(matches!(
+            store.update_metadata(Path::new("plan.md"), &[MetadataEdit::Set { key: "title.nested".into(), value: 1.into() }], None),
+            Err(Error::InvalidFrontmatter { .. })
+        ));
 
-        let mut notes = Vec::new();
-        for note in note_iter {
-            notes.push(note?);
-        }
-        Ok(notes)
+        Ok(())
     }
 
-    pub fn get(&self, relative_path: &Path) -> Result<Note> {
-        let path = self.secure_join(relative_path)?;
-        let content = fs::read_to_string(&path)?;
-        let matter = Matter::<YAML>::new();
-        let parsed = matter.parse(&content);
-
-        let metadata: Metadata = parsed.data.map(|d| d.deserialize()).transpose()?.unwrap_or(Metadata {
-             title: None, tags: None, created_at: None, updated_at: None, extra: Default::default()
-        });
-
-        Ok(Note {
-            path: relative_path.to_path_buf(),
-            metadata,
-            content: parsed.content,
-        })
+    #[test]
+    fn test_edit_body_keeps_frontmatter() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let frontmatter = "---\ntitle: Plan # keep\n---\n\n";
+        fs::write(notes_dir.join("plan.md"), format!("{frontmatter}intro\n## Tasks\n- old\n"))?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.sync()?;
+
+        store.edit_body(Path::new("plan.md"), &BodyEdit::Replace { from: 1, to: 1, content: "Intro".into() }, None)?;
+        store.edit_body(Path::new("plan.md"), &BodyEdit::ReplaceSection { heading: "Tasks".into(), content: "- shiny".into() }, None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("plan.md"))?, format!("{frontmatter}Intro\n## Tasks\n- shiny\n"));
+        assert_eq!(store.search("shiny")?.len(), 1);
+
+        assert!(matches!(
+            store.edit_body(Path::new("plan.md"), &BodyEdit::Delete { from: 2, to: 9 }, None),
+            Err(Error::InvalidRange { from: 2, to: 9, lines: 3, .. })
+        ));
+        assert!(matches!(
+            store.edit_body(Path::new("plan.md"), &BodyEdit::ReplaceSection { heading: "Done".into(), content: String::new() }, None),
+            Err(Error::SectionNotFound { .. })
+        ));
+        Ok(())
     }
 
-    pub fn create(&mut self, relative_path: &Path, content: &str, metadata: Option<Metadata>) -> Result<()> {
-        let path = self.secure_join(relative_path)?;
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
+    #[test]
+    fn test_edit_writes_once() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("plan.md"), "---\ntitle: Plan\n---\nintro\n- old\n")?;
 
-        // TODO: Serialize metadata to YAML frontmatter
-        // For now, just write content. Proper Frontmatter serialization needed.
-        // We'll trust the user executes sync() or we update DB here.
-        // Let's keep it simple: Write file -> Update DB.
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.sync()?;
+        let seen = store.get(Path::new("plan.md"))?.revision;
 
-        let mut file_content = String::new();
-        if let Some(ref meta) = metadata {
-            let yaml = serde_yaml::to_string(meta)?;
-            file_content.push_str("---\n");
-            file_content.push_str(&yaml);
-            file_content.push_str("---\n");
-        }
-        file_content.push_str(content);
+        let edit = NoteEdit {
+            body_edit: Some(BodyEdit::Replace { from: 2, to: 2, content: "- new".into() }),
+            title: Some("Roadmap".into()),
+            metadata: vec![MetadataEdit::Append { key: "tags".into(), value: "work".into() }],
+            ..Default::default()
+        };
+        let revision = store.edit(Path::new("plan.md"), &edit, Some(&seen))?;
+        assert_eq!(fs::read_to_string(notes_dir.join("plan.md"))?, "---\ntitle: Roadmap\ntags:\n- work\n---\nintro\n- new\n");
+        assert_eq!(store.get(Path::new("plan.md"))?.revision, revision);
+        // The version before and the one after, nothing in between.
+        assert_eq!(store.history(Path::new("plan.md"))?.len(), 2);
+
+        // A failing part leaves the note alone, and so does a stale revision.
+        let bad = NoteEdit { title: Some("Other".into()), body_edit: Some(BodyEdit::Delete { from: 5, to: 9 }), ..Default::default() };
+        assert!(matches!(store.edit(Path::new("plan.md"), &bad, None), Err(Error::InvalidRange { .. })));
+        assert!(matches!(store.edit(Path::new("plan.md"), &edit, Some(&seen)), Err(Error::Conflict { .. })));
+        assert_eq!(store.get(Path::new("plan.md"))?.revision, revision);
+
+        // An empty edit only checks the revision.
+        assert_eq!(store.edit(Path::new("plan.md"), &NoteEdit::default(), Some(&revision))?, revision);
+        assert!(matches!(store.edit(Path::new("plan.md"), &NoteEdit::default(), Some(&seen)), Err(Error::Conflict { .. })));
+        Ok(())
+    }
 
-        fs::write(&path, file_content)?;
-        
-        let title = metadata.as_ref()
-            .and_then(|m| m.title.clone())
-            .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()));
+    #[test]
+    fn test_expected_revision_detects_conflicts() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("shared.md"), "---\ntitle: Shared\n---\nv1\n")?;
 
-        self.conn.execute(
-            "INSERT OR REPLACE INTO notes (path, title) VALUES (?1, ?2)",
-             params![relative_path.to_string_lossy(), title],
-        )?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.sync()?;
 
+        let seen = store.get(Path::new("shared.md"))?.revision;
+        store.update(Path::new("shared.md"), Some("v2\n"), None, Some(&seen))?;
+        let current = store.get(Path::new("shared.md"))?.revision;
+        assert_ne!(seen, current);
+
+        // Writers still holding the old revision are turned away.
+        let stale = Some(seen.as_str());
+        let conflict = |result: Result<()>| matches!(result, Err(Error::Conflict { expected, actual, .. }) if expected == seen && actual == current);
+        assert!(conflict(store.update(Path::new("shared.md"), Some("v3\n"), None, stale)));
+        assert!(conflict(store.update_metadata(Path::new("shared.md"), &[MetadataEdit::Unset { key: "title".into() }], stale)));
+        assert!(conflict(store.edit_body(Path::new("shared.md"), &BodyEdit::Delete { from: 1, to: 1 }, stale)));
+        assert!(conflict(store.move_note(Path::new("shared.md"), Path::new("moved.md"), stale)));
+        assert!(conflict(store.delete(Path::new("shared.md"), stale)));
+        assert_eq!(fs::read_to_string(notes_dir.join("shared.md"))?, "---\ntitle: Shared\n---\nv2\n");
+
+        store.move_note(Path::new("shared.md"), Path::new("moved.md"), Some(&current))?;
+        store.delete(Path::new("moved.md"), Some(&current))?;
+        assert!(store.list()?.is_empty());
         Ok(())
     }
 
-    pub fn update(&mut self, relative_path: &Path, content: Option<&str>, title: Option<&str>) -> Result<()> {
-         // This requires reading, modifying frontmatter/content, writing back.
-         // For the MVP, we might just overwrite or append?
-         // The prompt says: "update note: can update title (rename), can update metadata field, can update body"
-         // Rename -> move_note.
-         // Update title -> metadata update.
-
-         // Let's implement full body overwrite for now as per prompt "full overwrite".
-         // Partial update by lines range is tricky without reading first.
-         
-         // Implementation for "update body (full overwrite)"
-         if let Some(c) = content {
-             // We need to preserve metadata if we overwrite body?
-             // Prompt says "update body (full overwrite, partial by specifying lines range)"
-             // Let's just do full overwrite of FILE for now to be safe, or read -> replace body -> write.
-             let mut note = self.get(relative_path)?;
-             note.content = c.to_string();
-             
-             // Reconstruct file
-             // TODO: We need a proper Note -> String serializer.
-             // Simulating:
-             let mut file_content = String::new();
-             file_content.push_str("---\n");
-             // Re-serialize existing metadata
-             let yaml = serde_yaml::to_string(&note.metadata)?;
-             file_content.push_str(&yaml);
-             file_content.push_str("---\n");
-             file_content.push_str(&note.content);
-             
-             fs::write(self.secure_join(relative_path)?, file_content)?;
-         }
-         
-         if let Some(_t) = title {
-             // Update metadata title
-             // This is complex without a YAML writer.
-             // I'll skip this specific detail for this iteration or hack it.
-        }
-         
-         Ok(())
-    }
-
-    pub fn delete(&mut self, relative_path: &Path) -> Result<()> {
-        let path = self.secure_join(relative_path)?;
-        if path.exists() {
-            fs::remove_file(path)?;
+    #[test]
+    fn test_query_pages() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        for name in ["a", "b", "c", "d", "e"] {
+            fs::write(notes_dir.join(format!("{name}.md")), name)?;
         }
-        self.conn.execute("DELETE FROM notes WHERE path = ?1", params![relative_path.to_string_lossy()])?;
+        let mut store = Store::new(notes_dir, dir.path().join("test.db"))?;
+        store.sync()?;
+
+        let paths = |page: Page| page.items.into_iter().map(|n| n.path.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        let page = store.query(&ListQuery { offset: 1, limit: Some(2), ..Default::default() })?;
+        assert_eq!(page.total, 5);
+        assert_eq!(paths(page), ["b.md", "c.md"]);
+        assert_eq!(paths(store.query(&ListQuery { offset: 3, ..Default::default() })?), ["d.md", "e.md"]);
+        assert!(store.query(&ListQuery { offset: 9, limit: Some(2), ..Default::default() })?.items.is_empty());
         Ok(())
     }
-    
-    pub fn move_note(&mut self, from: &Path, to: &Path) -> Result<()> {
-        let from_path = self.secure_join(from)?;
-        let to_path = self.secure_join(to)?;
-        
-        if let Some(parent) = to_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        
-        fs::rename(from_path, to_path)?;
-        
-        self.conn.execute(
-            "UPDATE notes SET path = ?1 WHERE path = ?2",
-            params![to.to_string_lossy(), from.to_string_lossy()]
-        )?;
+
+    #[test]
+    fn test_note_times() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("old.md"), "---\ntitle: Zebra\ncreated_at: 2023-01-05\nupdated_at: 2023-03-01T10:00:00+01:00\n---\nold note\n")?;
+        fs::write(notes_dir.join("plain.md"), "plain note\n")?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.sync()?;
+
+        // Frontmatter times are indexed in canonical form; notes without
+        // them fall back to their file times.
+        let listed = store.list()?;
+        assert_eq!(listed[0].created_at.as_deref(), Some("2023-01-05T00:00:00Z"));
+        assert_eq!(listed[0].updated_at.as_deref(), Some("2023-03-01T09:00:00Z"));
+        let modified = timestamps::from_system(fs::metadata(notes_dir.join("plain.md"))?.modified()?);
+        assert_eq!(listed[1].updated_at, Some(timestamps::format(modified)));
+
+        let before = timestamps::now();
+        store.create(Path::new("new.md"), "new note\n", None)?;
+        let note = store.get(Path::new("new.md"))?;
+        let created = note.metadata.created_at.clone().unwrap();
+        assert!(timestamps::parse(&created).unwrap() >= before);
+        assert_eq!(note.metadata.updated_at.as_deref(), Some(created.as_str()));
+
+        // Updates bump `updated_at`, unless they set it themselves.
+        store.update(Path::new("old.md"), Some("changed\n"), None, None)?;
+        let note = store.get(Path::new("old.md"))?;
+        assert_eq!(note.metadata.created_at.as_deref(), Some("2023-01-05"));
+        assert!(timestamps::parse(note.metadata.updated_at.as_deref().unwrap()).unwrap() >= before);
+        store.update_metadata(Path::new("old.md"), &[
+            MetadataEdit::Set { key: "updated_at".into(), value: "2023-06-01T00:00:00Z".into() },
+        ], None)?;
+        assert_eq!(store.get(Path::new("old.md"))?.metadata.updated_at.as_deref(), Some("2023-06-01T00:00:00Z"));
+
+        let paths = |query: &ListQuery| -> Result<Vec<PathBuf>> {
+            Ok(store.query(query)?.items.into_iter().map(|n| n.path).collect())
+        };
+        let sorted = |sort| ListQuery { sort: Some(sort), ..Default::default() };
+        assert_eq!(paths(&sorted(SortKey::Title))?, ["new.md", "plain.md", "old.md"].map(PathBuf::from));
+        assert_eq!(paths(&sorted(SortKey::Created))?.last(), Some(&PathBuf::from("old.md")));
+        assert_eq!(paths(&sorted(SortKey::Updated))?.last(), Some(&PathBuf::from("old.md")));
+
+        let june = timestamps::parse("2023-06-01");
+        let range = |sort, since, until| ListQuery { sort: Some(sort), since, until, ..Default::default() };
+        assert_eq!(paths(&range(SortKey::Updated, None, june.map(|t| t + 1)))?, [PathBuf::from("old.md")]);
+        assert_eq!(paths(&range(SortKey::Created, timestamps::parse("2023-01-06"), None))?.len(), 2);
+        assert_eq!(paths(&range(SortKey::Path, june, june))?.len(), 0);
+
+        let found = store.search_with("zebra", &range(SortKey::Created, None, Some(before)))?;
+        assert_eq!((found.total, found.items[0].path.as_path()), (1, Path::new("old.md")));
+
+        // With stamping off, writes leave the frontmatter alone.
+        store.set_stamp_times(false);
+        store.create(Path::new("bare.md"), "bare\n", None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("bare.md"))?, "bare\n");
+        store.update(Path::new("plain.md"), Some("still plain\n"), None, None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("plain.md"))?, "still plain\n");
         Ok(())
     }
 
-    fn secure_join(&self, relative_path: &Path) -> Result<PathBuf> {
-        if relative_path.is_absolute() {
-            return Err(anyhow::anyhow!("Absolute paths are not allowed"));
-        }
-
-        let mut depth = 0;
-        for component in relative_path.components() {
-            match component {
-                std::path::Component::Normal(_) => depth += 1,
-                std::path::Component::ParentDir => {
-                    depth -= 1;
-                    if depth < 0 {
-                        return Err(anyhow::anyhow!("Path traversal detected"));
-                    }
-                }
-                std::path::Component::CurDir => {}
-                std::path::Component::RootDir | std::path::Component::Prefix(_) => {
-                    return Err(anyhow::anyhow!("Absolute paths or prefixes are not allowed"));
-                }
-            }
-        }
+    #[test]
+    fn test_tags() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("a.md"), "---\ntags: [Project/Alpha, work]\n---\nAlpha kickoff")?;
+        fs::write(notes_dir.join("b.md"), "Beta kickoff #project/beta")?;
+        fs::write(notes_dir.join("c.md"), "Unrelated, see issue #12 and `#code`")?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.sync()?;
 
-        Ok(self.root_dir.join(relative_path))
+        let counts: Vec<_> = store.tags()?.into_iter().map(|t| (t.tag, t.count)).collect();
+        assert_eq!(
+            counts,
+            [
+                ("project".to_string(), 2),
+                ("project/alpha".to_string(), 1),
+                ("project/beta".to_string(), 1),
+                ("work".to_string(), 1)
+            ]
+        );
+
+        let paths = |notes: Vec<NoteSummary>| notes.into_iter().map(|n| n.path.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(paths(store.list_by_tag("#Project")?), ["a.md", "b.md"]);
+        assert_eq!(paths(store.list_by_tag("project/alpha")?), ["a.md"]);
+        assert!(store.list_by_tag("proj")?.is_empty());
+        assert!(store.list_by_tag("not a tag")?.is_empty());
+
+        let filter = ListQuery { tag: Some("project/beta".into()), ..Default::default() };
+        let page = store.search_with("kickoff", &filter)?;
+        assert_eq!(page.total, 1);
+        assert_eq!(paths(page.items), ["b.md"]);
+
+        // Tags follow edits, creates and deletes.
+        store.update_metadata(Path::new("a.md"), &[MetadataEdit::Remove { key: "tags".into(), value: "work".into() }], None)?;
+        store.create(Path::new("d.md"), "#work again", None)?;
+        store.delete(Path::new("b.md"), None)?;
+        let counts: Vec<_> = store.tags()?.into_iter().map(|t| (t.tag, t.count)).collect();
+        assert_eq!(
+            counts,
+            [("project".to_string(), 1), ("project/alpha".to_string(), 1), ("work".to_string(), 1)]
+        );
+        assert_eq!(paths(store.list_by_tag("work")?), ["d.md"]);
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod store_path_tests {
-    use super::*;
-    use std::path::Path;
 
     #[test]
-    fn test_secure_join() {
-        let conn = rusqlite::Connection::open_in_memory().unwrap();
-        let store = Store {
-            conn,
-            root_dir: PathBuf::from("/notes"),
-        };
+    fn test_links() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir_all(notes_dir.join("work"))?;
+        fs::write(notes_dir.join("index.md"), "---\ntitle: Index\n---\nSee [[Plan]] and [ideas](work/ideas.md).\nAlso [[Someday]].\n")?;
+        fs::write(notes_dir.join("work/plan.md"), "Back to [index](../index.md)\n")?;
+        fs::write(notes_dir.join("work/ideas.md"), "Nothing here")?;
+        let mut store = Store::new(notes_dir, dir.path().join("test.db"))?;
+        store.sync()?;
 
-        // Valid paths
-        assert!(store.secure_join(Path::new("note.md")).is_ok());
-        assert!(store.secure_join(Path::new("sub/note.md")).is_ok());
-        assert!(store.secure_join(Path::new("./note.md")).is_ok());
+        let outgoing = store.outgoing_links(Path::new("index.md"))?;
+        let summary: Vec<_> = outgoing
+            .iter()
+            .map(|l| (l.line, l.kind, l.target.as_str(), l.resolved.as_ref().map(|p| p.to_string_lossy().into_owned())))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, LinkKind::Wiki, "Plan", Some("work/plan.md".to_string())),
+                (1, LinkKind::Markdown, "work/ideas.md", Some("work/ideas.md".to_string())),
+                (2, LinkKind::Wiki, "Someday", None),
+            ]
+        );
+
+        let sources = |links: Vec<Link>| links.into_iter().map(|l| l.source.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(sources(store.backlinks(Path::new("index.md"))?), ["work/plan.md"]);
+        assert_eq!(sources(store.backlinks(Path::new("work/plan.md"))?), ["index.md"]);
+        assert_eq!(store.unresolved_links()?.len(), 1);
+        assert!(matches!(store.backlinks(Path::new("missing.md")), Err(Error::NotFound(_))));
+
+        // Resolution follows notes as they come, move and go.
+        store.create(Path::new("someday.md"), "", None)?;
+        assert!(store.unresolved_links()?.is_empty());
+        store.move_note(Path::new("work/plan.md"), Path::new("plan.md"), None)?;
+        assert_eq!(sources(store.backlinks(Path::new("plan.md"))?), ["index.md"]);
+        assert!(store.backlinks(Path::new("index.md"))?.is_empty(), "relative link from the moved note now misses");
+        store.delete(Path::new("work/ideas.md"), None)?;
+        let unresolved: Vec<_> = store.unresolved_links()?.into_iter().map(|l| l.target).collect();
+        assert_eq!(unresolved, ["work/ideas.md", "../index.md"]);
+        Ok(())
+    }
 
-        // Invalid paths (traversal)
-        assert!(store.secure_join(Path::new("../etc/passwd")).is_err());
-        assert!(store.secure_join(Path::new("sub/../../etc/passwd")).is_err());
-        assert!(store.secure_join(Path::new("sub/..")).is_ok());
-        assert!(store.secure_join(Path::new("sub/../..")).is_err());
+    fn link_vault() -> Result<(tempfile::TempDir, Store)> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir_all(notes_dir.join("other"))?;
+        fs::write(notes_dir.join("plan.md"), "---\ntitle: Plan\n---\nSelf [link](plan.md) and [up](other/ref.md)\n")?;
+        fs::write(notes_dir.join("index.md"), "[[Plan]] and [[Plan#Goals|goals]]\n\n[p](plan.md)\n")?;
+        fs::write(notes_dir.join("other/ref.md"), "See [plan](../plan.md) and `[[Plan]]`\n")?;
+        fs::write(notes_dir.join("unrelated.md"), "[[index]]\n")?;
+        let mut store = Store::new(notes_dir, dir.path().join("test.db"))?;
+        store.sync()?;
+        Ok((dir, store))
+    }
 
-        // Absolute paths
-        assert!(store.secure_join(Path::new("/etc/passwd")).is_err());
+    #[test]
+    fn test_move_rewrites_links() -> Result<()> {
+        let (dir, mut store) = link_vault()?;
+        let notes_dir = dir.path().join("notes");
+        let read = |path: &str| fs::read_to_string(notes_dir.join(path)).unwrap();
+        let (from, to) = (Path::new("plan.md"), Path::new("work/Roadmap.md"));
+
+        let dry_run = MoveOptions { rewrite_links: true, dry_run: true };
+        let planned = store.move_note_with(from, to, None, &dry_run)?;
+        let changed: Vec<_> = planned.iter().map(|r| (r.path.to_string_lossy().into_owned(), r.line)).collect();
+        assert_eq!(changed, [("index.md".into(), 1), ("index.md".into(), 3), ("other/ref.md".into(), 1), ("plan.md".into(), 1)]);
+        assert_eq!(planned[0].before, "[[Plan]] and [[Plan#Goals|goals]]");
+        assert_eq!(planned[0].after, "[[Roadmap]] and [[Roadmap#Goals|goals]]");
+        assert!(notes_dir.join("plan.md").exists() && !notes_dir.join("work").exists());
+        assert_eq!(read("index.md"), "[[Plan]] and [[Plan#Goals|goals]]\n\n[p](plan.md)\n");
+
+        let options = MoveOptions { rewrite_links: true, dry_run: false };
+        assert_eq!(store.move_note_with(from, to, None, &options)?, planned);
+        assert_eq!(read("work/Roadmap.md"), "---\ntitle: Plan\n---\nSelf [link](Roadmap.md) and [up](../other/ref.md)\n");
+        assert_eq!(read("index.md"), "[[Roadmap]] and [[Roadmap#Goals|goals]]\n\n[p](work/Roadmap.md)\n");
+        assert_eq!(read("other/ref.md"), "See [plan](../work/Roadmap.md) and `[[Plan]]`\n");
+        assert_eq!(read("unrelated.md"), "[[index]]\n");
+
+        assert!(store.unresolved_links()?.is_empty());
+        let sources: Vec<_> = store.backlinks(to)?.into_iter().map(|l| l.source.to_string_lossy().into_owned()).collect();
+        assert_eq!(sources, ["index.md", "index.md", "index.md", "other/ref.md", "work/Roadmap.md"]);
+        assert_eq!(store.search("Roadmap")?.len(), 3);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::tempdir;
-    use std::fs;
-    use std::env;
+    #[test]
+    fn test_failed_move_changes_nothing() -> Result<()> {
+        let (dir, mut store) = link_vault()?;
+        let notes_dir = dir.path().join("notes");
+        let before: Vec<_> = ["plan.md", "index.md", "other/ref.md"].iter().map(|p| fs::read(notes_dir.join(p)).unwrap()).collect();
+        // Staging the rewrite of index.md fails part-way through the move.
+        fs::create_dir(notes_dir.join(".index.md.extt-tmp"))?;
+
+        let options = MoveOptions { rewrite_links: true, dry_run: false };
+        assert!(matches!(store.move_note_with(Path::new("plan.md"), Path::new("roadmap.md"), None, &options), Err(Error::Io(_))));
+
+        let after: Vec<_> = ["plan.md", "index.md", "other/ref.md"].iter().map(|p| fs::read(notes_dir.join(p)).unwrap()).collect();
+        assert_eq!(before, after);
+        assert!(!notes_dir.join("roadmap.md").exists());
+        assert!(!notes_dir.join(".plan.md.extt-tmp").exists());
+        assert_eq!(store.backlinks(Path::new("plan.md"))?.len(), 5);
+        Ok(())
+    }
 
     #[test]
-    fn test_store_workflow() -> Result<()> {
+    fn test_trash_and_restore() -> Result<()> {
         let dir = tempdir()?;
-        let db_path = dir.path().join("test.db");
         let notes_dir = dir.path().join("notes");
         fs::create_dir(&notes_dir)?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.create(Path::new("a.md"), "first", None)?;
+        store.create(Path::new("b.md"), "second", None)?;
+
+        store.delete(Path::new("a.md"), None)?;
+        assert!(!notes_dir.join("a.md").exists());
+        let trashed = store.trash()?;
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].path, Path::new("a.md"));
+        // The trash is never indexed.
+        store.sync()?;
+        assert_eq!(store.list()?.len(), 1);
+
+        store.delete_permanently(Path::new("b.md"), None)?;
+        assert_eq!(store.trash()?.len(), 1);
+
+        // A new note took the old name, so the restored one goes next to it.
+        store.create(Path::new("a.md"), "replacement", None)?;
+        assert_eq!(store.restore("a", None)?, Path::new("a (restored).md"));
+        assert_eq!(fs::read_to_string(notes_dir.join("a (restored).md"))?, "first");
+        assert_eq!(store.search("first")?.len(), 1);
+        assert!(store.trash()?.is_empty());
+        assert!(matches!(store.restore("a.md", None), Err(Error::NotFound(_))));
+
+        store.delete(Path::new("a.md"), None)?;
+        let id = store.trash()?[0].id.clone();
+        assert!(matches!(store.restore(&id, Some(Path::new("a (restored).md"))), Err(Error::AlreadyExists(_))));
+        assert_eq!(store.restore(&id, Some(Path::new("archive/a.md")))?, Path::new("archive/a.md"));
+
+        store.delete(Path::new("archive/a.md"), None)?;
+        assert_eq!(store.empty_trash(Some(Duration::from_secs(86400)))?, 0);
+        assert_eq!(store.empty_trash(Some(Duration::from_secs(u64::MAX)))?, 0);
+        assert_eq!(store.empty_trash(Some(Duration::from_secs(i64::MAX as u64)))?, 0);
+        assert_eq!(store.trash()?.len(), 1);
+        assert_eq!(store.empty_trash(None)?, 1);
+        assert!(store.trash()?.is_empty());
+        Ok(())
+    }
 
-        let mut store = Store::new(notes_dir.clone(), db_path)?;
-
-        // Test Create
-        store.create(Path::new("note1.md"), "content1", Some(Metadata {
-            title: Some("Note 1".to_string()),
-            tags: None,
-            created_at: None,
-            updated_at: None,
-            extra: Default::default(),
-        }))?;
-
-        // Test List
-        let notes = store.list()?;
-        assert_eq!(notes.len(), 1);
-        assert_eq!(notes[0].title.as_deref(), Some("Note 1"));
-        assert_eq!(notes[0].path, Path::new("note1.md"));
-
-        // Test Get
-        let note = store.get(Path::new("note1.md"))?;
-        assert!(note.content.contains("content1"));
-        assert_eq!(note.metadata.title.as_deref(), Some("Note 1"));
+    #[test]
+    fn test_failed_writes_leave_note_and_index() -> Result<()> {
+        use crate::atomic::{inject_fault, Step};
 
-        // Test Search
-        let results = store.search("Note 1")?;
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].title.as_deref(), Some("Note 1"));
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.create(Path::new("plan.md"), "original wording", None)?;
+        let revision = store.get(Path::new("plan.md"))?.revision;
+
+        for step in [Step::Create, Step::Write, Step::Sync, Step::Rename] {
+            inject_fault(Some(step));
+            let updated = store.update(Path::new("plan.md"), Some("replacement wording"), None, None);
+            let created = store.create(Path::new("new.md"), "replacement wording", None);
+            inject_fault(None);
+
+            assert!(matches!(updated, Err(Error::Io(_))), "{step:?}");
+            assert!(matches!(created, Err(Error::Io(_))), "{step:?}");
+            assert_eq!(store.get(Path::new("plan.md"))?.revision, revision, "{step:?}");
+            assert!(!notes_dir.join("new.md").exists(), "{step:?}");
+            assert_eq!(store.search("replacement")?.len(), 0, "{step:?}");
+            // Just plan.md and .extt: no temp file left behind.
+            assert_eq!(fs::read_dir(&notes_dir)?.count(), 2, "{step:?}");
+        }
+        // The index still matches the files, so a sync has nothing to do.
+        let report = store.sync()?;
+        assert_eq!((report.added, report.updated, report.removed), (0, 0, 0));
 
-        let results = store.search("content1")?;
-         // Search currently only searches title/path, not content (based on implementation).
-        assert_eq!(results.len(), 0); // Expected fail if search was full-text, but current impl is title/path only.
+        store.update(Path::new("plan.md"), Some("replacement wording"), None, None)?;
+        assert_eq!(store.search("replacement")?[0].title.as_deref(), Some("plan"));
+        Ok(())
+    }
 
-        let results = store.search("note1")?;
-        assert_eq!(results.len(), 1);
+    #[test]
+    fn test_concurrent_stores() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let db_path = dir.path().join("test.db");
+        let store = Store::new(notes_dir.clone(), db_path.clone())?;
+        let mode: String = store.conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        assert_eq!(mode, "wal");
+
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let (notes_dir, db_path) = (notes_dir.clone(), db_path.clone());
+                std::thread::spawn(move || -> Result<()> {
+                    let mut store = Store::new(notes_dir, db_path)?;
+                    for i in 0..10 {
+                        store.create(Path::new(&format!("w{writer}-{i}.md")), "text", None)?;
+                        store.sync()?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap()?;
+        }
+        assert_eq!(store.list()?.len(), 40);
+
+        // While another process holds the vault for a structural operation,
+        // writes wait and then report the vault as busy; reads carry on.
+        let mut store = store;
+        store.set_busy_timeout(Duration::from_millis(50))?;
+        let held = lock::acquire(&notes_dir, Mode::Exclusive, Duration::ZERO)?;
+        assert!(matches!(store.sync(), Err(Error::VaultBusy)));
+        assert!(matches!(store.create(Path::new("late.md"), "", None), Err(Error::VaultBusy)));
+        assert_eq!(store.list()?.len(), 40);
+        drop(held);
+        store.create(Path::new("late.md"), "", None)?;
+        Ok(())
+    }
 
-        // Test Update
-        store.update(Path::new("note1.md"), Some("updated content"), None)?;
-        let note = store.get(Path::new("note1.md"))?;
-        assert!(note.content.contains("updated content"));
-        assert_eq!(note.metadata.title.as_deref(), Some("Note 1")); // Title preserved
+    #[test]
+    fn test_git_commits_each_change() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        assert!(matches!(store.git_log(None), Err(Error::GitDisabled)));
+        store.enable_git(GitOptions { author: crate::types::Author::Agent, push: false })?;
+
+        store.create(Path::new("a.md"), "[[b]]", None)?;
+        store.create(Path::new("b.md"), "two", None)?;
+        store.update(Path::new("a.md"), Some("[[b]] again"), None, None)?;
+        store.move_note_with(Path::new("b.md"), Path::new("c.md"), None, &MoveOptions { rewrite_links: true, dry_run: false })?;
+        store.delete(Path::new("c.md"), None)?;
+
+        let summaries = |log: Vec<GitCommit>| log.into_iter().map(|c| c.summary).collect::<Vec<_>>();
+        assert_eq!(
+            summaries(store.git_log(None)?),
+            ["delete: c.md", "move: b.md -> c.md", "update: a.md", "create: b.md", "create: a.md"]
+        );
+        assert_eq!(summaries(store.git_log(Some(Path::new("a.md")))?), ["move: b.md -> c.md", "update: a.md", "create: a.md"]);
+        assert_eq!(store.git_log(None)?[0].author, "extt agent");
+        Ok(())
+    }
 
-        // Test Move
-        store.move_note(Path::new("note1.md"), Path::new("renamed.md"))?;
-        assert!(!notes_dir.join("note1.md").exists());
-        assert!(notes_dir.join("renamed.md").exists());
+    #[test]
+    fn test_history_diff_and_revert() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        let path = Path::new("plan.md");
+
+        store.create(path, "one\ntwo\n", None)?;
+        let first = store.get(path)?.revision;
+        // Edited behind extt's back; the next write still keeps this version.
+        fs::write(notes_dir.join(path), "one\ntwo\nthree\n")?;
+        store.update(path, Some("one\n2\nthree\n"), None, None)?;
+
+        let history = store.history(path)?;
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[2].revision, first);
+        assert_eq!(history[0].revision, store.get(path)?.revision);
+
+        let diff = store.diff(path, &first[..8])?;
+        assert!(diff.starts_with(&format!("--- plan.md@{}\n+++ plan.md\n", &first[..12])), "{diff}");
+        assert!(diff.contains("-two\n+2\n+three\n"), "{diff}");
+
+        store.revert(path, &first[..8], None)?;
+        assert_eq!(store.read_raw(path)?, "one\ntwo\n");
+        assert_eq!(store.history(path)?.len(), 4);
+        assert_eq!(store.search("three")?.len(), 0);
+        assert!(matches!(store.revert(path, "0000", None), Err(Error::UnknownRevision { .. })));
+
+        // History follows moves, and outlives deletes.
+        store.move_note(path, Path::new("done/plan.md"), None)?;
+        store.delete_permanently(Path::new("done/plan.md"), None)?;
+        assert_eq!(store.history(Path::new("done/plan.md"))?.len(), 5);
+        store.revert(Path::new("done/plan.md"), &history[0].revision, None)?;
+        assert_eq!(store.read_raw(Path::new("done/plan.md"))?, "one\n2\nthree\n");
+        assert!(matches!(store.history(Path::new("nothing.md")), Err(Error::NotFound(_))));
+        Ok(())
+    }
 
-        let notes = store.list()?;
-        assert_eq!(notes.len(), 1);
-        assert_eq!(notes[0].path, Path::new("renamed.md"));
+    #[test]
+    fn test_new_reindexes_unstamped_notes() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        let db_path = dir.path().join("test.db");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("a.md"), "#todo")?;
+        Store::new(notes_dir.clone(), db_path.clone())?.sync()?;
 
-        // Test Delete
-        store.delete(Path::new("renamed.md"))?;
-        assert!(!notes_dir.join("renamed.md").exists());
-        let notes = store.list()?;
-        assert_eq!(notes.len(), 0);
+        // What the tag migration leaves behind for an existing index.
+        let conn = Connection::open(&db_path)?;
+        conn.execute("DELETE FROM note_tags", [])?;
+        conn.execute("UPDATE notes SET size = NULL, mtime = NULL, content_hash = NULL", [])?;
+        drop(conn);
 
+        let store = Store::new(notes_dir, db_path)?;
+        assert_eq!(store.tags()?.len(), 1);
         Ok(())
     }
 
@@ -367,6 +2415,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sync_is_incremental() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("keep.md"), "---\ntitle: Keep\n---\nStable")?;
+        fs::write(notes_dir.join("edit.md"), "---\ntitle: Edit\n---\nBefore")?;
+        fs::write(notes_dir.join("drop.md"), "Going away")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        let report = store.sync()?;
+        assert_eq!(report, SyncReport { added: 3, updated: 0, removed: 0, unchanged: 0, skipped: 0 });
+
+        let report = store.sync()?;
+        assert_eq!(report, SyncReport { added: 0, updated: 0, removed: 0, unchanged: 3, skipped: 0 });
+
+        fs::write(notes_dir.join("edit.md"), "---\ntitle: Edited\n---\nAfter the change")?;
+        fs::remove_file(notes_dir.join("drop.md"))?;
+        fs::write(notes_dir.join("new.md"), "Fresh")?;
+
+        // Touching a file without changing its bytes is not an update.
+        let keep = fs::File::options().write(true).open(notes_dir.join("keep.md"))?;
+        keep.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+        drop(keep);
+
+        let report = store.sync()?;
+        assert_eq!(report, SyncReport { added: 1, updated: 1, removed: 1, unchanged: 1, skipped: 0 });
+
+        let titles: Vec<_> = store.list()?.into_iter().filter_map(|n| n.title).collect();
+        assert_eq!(titles, vec!["Edited", "Keep", "new"]);
+        assert_eq!(store.search("change")?.len(), 1);
+        assert!(store.search("Going")?.is_empty());
+
+        // Notes written through the store are already up to date.
+        store.create(Path::new("created.md"), "Body", None)?;
+        store.update(Path::new("edit.md"), Some("Rewritten"), None, None)?;
+        let report = store.sync()?;
+        assert_eq!(report, SyncReport { added: 0, updated: 0, removed: 0, unchanged: 4, skipped: 0 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_skips_invalid_utf8() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("good.md"), "Fine")?;
+        fs::write(notes_dir.join("binary.md"), [0xff, 0xfe, 0x00])?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        let report = store.sync()?;
+        assert_eq!(report, SyncReport { added: 1, updated: 0, removed: 0, unchanged: 0, skipped: 1 });
+        assert_eq!(store.check()?, vec![Problem::InvalidUtf8 { path: "binary.md".into() }]);
+
+        // A note that stops being readable leaves the index.
+        fs::write(notes_dir.join("good.md"), [0xc3, 0x28])?;
+        let report = store.sync()?;
+        assert_eq!(report, SyncReport { added: 0, updated: 0, removed: 0, unchanged: 0, skipped: 2 });
+        assert!(store.list()?.is_empty());
+        assert!(store.search("Fine")?.is_empty());
+
+        Ok(())
+    }
+
     fn setup_temp_dir(suffix: &str) -> PathBuf {
         let mut temp_dir = env::temp_dir();
         temp_dir.push(format!("extt_test_{}_{}", std::process::id(), suffix));
@@ -433,6 +2546,30 @@ mod tests {
         let _ = fs::remove_dir_all(&temp_dir);
     }
 
+    #[test]
+    fn test_open_or_rebuild_recovers_corrupt_index() -> Result<()> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("a.md"), "Alpha")?;
+        fs::write(&db_path, "this is not a sqlite database, just some garbage bytes")?;
+
+        assert!(Store::new(notes_dir.clone(), db_path.clone()).is_err());
+
+        let (store, report) = Store::open_or_rebuild(notes_dir.clone(), db_path.clone())?;
+        assert_eq!(report.map(|r| r.added), Some(1));
+        assert_eq!(store.list()?.len(), 1);
+        drop(store);
+
+        // A healthy index is opened as is.
+        let (store, report) = Store::open_or_rebuild(notes_dir, db_path)?;
+        assert!(report.is_none());
+        assert_eq!(store.list()?.len(), 1);
+
+        Ok(())
+    }
+
     #[test]
     fn test_store_create_serialization() -> Result<()> {
         let dir = tempdir()?;
@@ -441,6 +2578,7 @@ mod tests {
         fs::create_dir(&notes_dir)?;
 
         let mut store = Store::new(notes_dir.clone(), db_path)?;
+        store.set_stamp_times(false);
 
         let metadata = Metadata {
             title: Some("My Title".to_string()),
@@ -467,4 +2605,34 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_frontmatter_formats() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("hugo.md"), "+++\ntitle = \"From Hugo\" # imported\ntags = [\"web\"]\n+++\nBody\n")?;
+        fs::write(notes_dir.join("json.md"), "{\n  \"title\": \"From JSON\"\n}\nBody\n")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.set_stamp_times(false);
+        store.set_frontmatter_format(FrontmatterFormat::Toml);
+        store.sync()?;
+        let titles: Vec<_> = store.list()?.into_iter().map(|n| n.title).collect();
+        assert_eq!(titles, [Some("From Hugo".into()), Some("From JSON".into())]);
+        assert_eq!(store.list_by_tag("web")?.len(), 1);
+
+        // Edits keep each note's format.
+        store.update_metadata(Path::new("hugo.md"), &[MetadataEdit::Set { key: "draft".into(), value: true.into() }], None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("hugo.md"))?, "+++\ntitle = \"From Hugo\" # imported\ntags = [\"web\"]\ndraft = true\n+++\nBody\n");
+        store.update(Path::new("json.md"), None, Some("Renamed"), None)?;
+        assert_eq!(fs::read_to_string(notes_dir.join("json.md"))?, "{\n  \"title\": \"Renamed\"\n}\nBody\n");
+
+        // New notes use the vault's default.
+        let metadata = Metadata { title: Some("New".into()), tags: Some(vec!["a".into()]), ..Default::default() };
+        store.create(Path::new("new.md"), "Body\n", Some(metadata))?;
+        assert_eq!(fs::read_to_string(notes_dir.join("new.md"))?, "+++\ntitle = \"New\"\ntags = [\"a\"]\n+++\nBody\n");
+        assert_eq!(store.get(Path::new("new.md"))?.frontmatter_format, Some(FrontmatterFormat::Toml));
+        Ok(())
+    }
 }
@@ -0,0 +1,111 @@
+//! Tags from frontmatter and from `#tag` mentions in note bodies.
+//!
+//! Tags are compared in lowercase and may be hierarchical: `project/alpha` is
+//! also a `project` note. A tag needs at least one character that is not a
+//! digit, so issue references like `#123` are left alone.
+
+use std::collections::BTreeSet;
+
+/// Canonical form of a tag typed by a user or found in a note, or `None` if
+/// it is not a valid tag.
+pub fn normalize(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#').trim_matches('/');
+    let valid = !tag.is_empty()
+        && tag.chars().all(is_tag_char)
+        && tag.chars().any(|c| !c.is_ascii_digit() && c != '/')
+        && !tag.contains("//");
+    valid.then(|| tag.to_lowercase())
+}
+
+/// `a/b/c` and its parents, `a` and `a/b`.
+pub fn with_ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices('/').map(|(i, _)| &tag[..i]).chain(std::iter::once(tag))
+}
+
+/// Every tag of a note, normalized: its frontmatter tags plus inline mentions.
+pub(crate) fn collect(frontmatter: &[String], body: &str) -> BTreeSet<String> {
+    let mut tags: BTreeSet<String> = frontmatter.iter().filter_map(|tag| normalize(tag)).collect();
+    tags.extend(inline(body));
+    tags
+}
+
+/// `#tag` mentions in a markdown body, skipping code blocks and code spans.
+pub(crate) fn inline(body: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    for (_, _, line) in crate::body::prose_lines(body) {
+        scan_line(line, &mut tags);
+    }
+    tags
+}
+
+fn scan_line(line: &str, tags: &mut Vec<String>) {
+    let mut in_code = false;
+    let mut previous = ' ';
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '`' => in_code = !in_code,
+            // Only at the start of a word, so `C#`, `a#b` and URL fragments
+            // are not tags.
+            '#' if !in_code && (previous.is_whitespace() || "([{,;".contains(previous)) => {
+                let rest = &line[i + 1..];
+                let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+                if let Some(tag) = normalize(&rest[..end]) {
+                    tags.push(tag);
+                }
+                while chars.peek().is_some_and(|&(j, _)| j <= i + end) {
+                    chars.next();
+                }
+                previous = 'x';
+                continue;
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("#Project/Alpha/").as_deref(), Some("project/alpha"));
+        assert_eq!(normalize(" todo ").as_deref(), Some("todo"));
+        assert_eq!(normalize("y2024").as_deref(), Some("y2024"));
+        for invalid in ["", "#", "123", "2024/10", "has space", "a//b", "semi;colon"] {
+            assert_eq!(normalize(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_with_ancestors() {
+        assert_eq!(with_ancestors("a/b/c").collect::<Vec<_>>(), ["a", "a/b", "a/b/c"]);
+        assert_eq!(with_ancestors("solo").collect::<Vec<_>>(), ["solo"]);
+    }
+
+    #[test]
+    fn test_inline_tags() {
+        let body = "\
+# Heading
+Plan for #project/alpha, see (#review) and #todo.
+Not tags: C#, issue #42, http://x.io/#anchor, `#code`, ##double
+```
+#fenced
+```
+#last";
+        assert_eq!(inline(body), ["project/alpha", "review", "todo", "last"]);
+    }
+
+    #[test]
+    fn test_collect_merges_and_dedupes() {
+        let frontmatter = ["todo", "Work", "#home", "3", "bad tag"].map(String::from);
+        let tags = collect(&frontmatter, "#work and #todo plus #new");
+        assert_eq!(tags.into_iter().collect::<Vec<_>>(), ["home", "new", "todo", "work"]);
+    }
+}
@@ -0,0 +1,160 @@
+//! RFC 3339 timestamps for `created_at` / `updated_at`.
+//!
+//! Notes are stamped, and the index stores times, in one canonical UTC form,
+//! `2024-05-01T09:30:00Z`, so that comparing the text compares the times.
+//! Reading is more forgiving: any RFC 3339 timestamp (with an offset or
+//! fractional seconds) is accepted, as is a bare `2024-05-01` date meaning
+//! midnight UTC.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The current time in seconds since the Unix epoch.
+pub fn now() -> i64 {
+    from_system(SystemTime::now())
+}
+
+pub(crate) fn from_system(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+/// Formats seconds since the Unix epoch in the canonical form.
+pub fn format(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let time = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Parses an RFC 3339 timestamp or a `YYYY-MM-DD` date into seconds since
+/// the Unix epoch. Fractional seconds are dropped.
+pub fn parse(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let bytes = text.as_bytes();
+    if !text.is_ascii() || bytes.len() < 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    let year = number(&text[0..4])?;
+    let month = number(&text[5..7])?;
+    let day = number(&text[8..10])?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let date = days_from_civil(year, month, day) * 86_400;
+    if bytes.len() == 10 {
+        return Some(date);
+    }
+
+    if !matches!(bytes[10], b'T' | b't' | b' ') || bytes.len() < 19 || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+    let (hour, minute, second) = (number(&text[11..13])?, number(&text[14..16])?, number(&text[17..19])?);
+    // 60 allows for a leap second.
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = &text[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes().first() {
+                Some(b'+') => 1,
+                Some(b'-') => -1,
+                _ => return None,
+            };
+            if rest.len() != 6 || rest.as_bytes()[3] != b':' {
+                return None;
+            }
+            let (hours, minutes) = (number(&rest[1..3])?, number(&rest[4..6])?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+    Some(date + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Rewrites a timestamp in the canonical form, or `None` if it is not one.
+pub fn normalize(text: &str) -> Option<String> {
+    parse(text).map(format)
+}
+
+fn number(text: &str) -> Option<i64> {
+    if !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Conversions between days since the epoch and proleptic Gregorian dates,
+// after Howard Hinnant's `days_from_civil` and `civil_from_days`.
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(format(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(parse("2024-05-01T09:30:00Z"), Some(1_714_555_800));
+        assert_eq!(parse("2024-05-01T11:30:00.250+02:00"), Some(1_714_555_800));
+        assert_eq!(parse("2024-05-01 09:30:00z"), Some(1_714_555_800));
+        assert_eq!(parse("2024-05-01"), Some(1_714_521_600));
+        assert_eq!(normalize("1969-12-31T23:00:00-01:00").as_deref(), Some("1970-01-01T00:00:00Z"));
+
+        for bad in ["", "2024-5-01", "2024-02-30", "2024-13-01", "2024-05-01T25:00:00Z", "2024-05-01T09:30:00", "yesterday"] {
+            assert_eq!(parse(bad), None, "{bad}");
+        }
+        for secs in [-86_401, 0, 1_700_000_000, 4_102_444_799] {
+            assert_eq!(parse(&format(secs)), Some(secs));
+        }
+    }
+}
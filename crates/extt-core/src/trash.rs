@@ -0,0 +1,83 @@
+//! Vault-local trash for deleted notes.
+//!
+//! A deleted note is moved to `.extt/trash/<id>.md`, next to `<id>.json`
+//! recording where it came from and when it was deleted. Ids start with the
+//! deletion time, so they sort oldest first.
+
+use crate::error::{Error, Result};
+use crate::timestamps::now;
+use crate::types::TrashEntry;
+use crate::vault::META_DIR;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) fn dir(root: &Path) -> PathBuf {
+    root.join(META_DIR).join("trash")
+}
+
+/// Moves the note at `path` (`relative_path` in the vault) into the trash.
+pub(crate) fn put(root: &Path, path: &Path, relative_path: &Path) -> Result<TrashEntry> {
+    let trash = dir(root);
+    fs::create_dir_all(&trash)?;
+
+    let deleted_at = now();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let hash = blake3::hash(format!("{}\0{nanos}", relative_path.display()).as_bytes());
+    let id = format!("{deleted_at}-{}", &hash.to_hex()[..8]);
+    let entry = TrashEntry { id, path: relative_path.to_path_buf(), deleted_at };
+
+    let record = serde_json::to_vec_pretty(&entry).map_err(std::io::Error::other)?;
+    fs::write(trash.join(format!("{}.json", entry.id)), record)?;
+    if let Err(err) = fs::rename(path, note_path(root, &entry)) {
+        let _ = fs::remove_file(trash.join(format!("{}.json", entry.id)));
+        return Err(Error::from_io(err, relative_path));
+    }
+    Ok(entry)
+}
+
+/// Every trashed note, most recently deleted first.
+pub(crate) fn list(root: &Path) -> Result<Vec<TrashEntry>> {
+    let entries = match fs::read_dir(dir(root)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut found = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            // A record that does not parse was not written by extt; leave it.
+            if let Ok(entry) = serde_json::from_slice::<TrashEntry>(&fs::read(&path)?) {
+                found.push(entry);
+            }
+        }
+    }
+    found.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(found)
+}
+
+/// Where the content of a trashed note is kept.
+pub(crate) fn note_path(root: &Path, entry: &TrashEntry) -> PathBuf {
+    dir(root).join(format!("{}.md", entry.id))
+}
+
+/// Moves a trashed note back to `path` and forgets its record.
+pub(crate) fn take(root: &Path, entry: &TrashEntry, path: &Path) -> Result<()> {
+    fs::rename(note_path(root, entry), path)?;
+    fs::remove_file(dir(root).join(format!("{}.json", entry.id)))?;
+    Ok(())
+}
+
+/// Deletes a trashed note for good.
+pub(crate) fn purge(root: &Path, entry: &TrashEntry) -> Result<()> {
+    for path in [note_path(root, entry), dir(root).join(format!("{}.json", entry.id))] {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
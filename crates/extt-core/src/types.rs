@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Metadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
@@ -12,18 +12,362 @@ pub struct Metadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
     #[serde(flatten)]
-    pub extra: std::collections::HashMap<String, serde_json::Value>,
+    pub extra: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+/// Syntax of a note's frontmatter block.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontmatterFormat {
+    /// Between `---` lines.
+    #[default]
+    Yaml,
+    /// Between `+++` lines, as used by Hugo and Zola.
+    Toml,
+    /// A JSON object at the very start of the note, as used by Hugo.
+    Json,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Note {
     pub path: PathBuf,
+    /// Typed view of the frontmatter; empty if it is malformed.
     pub metadata: Metadata,
+    /// The frontmatter exactly as written, without the `---` or `+++` lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontmatter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontmatter_format: Option<FrontmatterFormat>,
+    /// The body, after the frontmatter and the blank lines following it.
     pub content: String,
+    /// Hash of the file contents. Pass it back as the expected revision on
+    /// writes to fail instead of overwriting someone else's change.
+    pub revision: String,
+    /// Problems found while parsing the frontmatter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A problem with a note's frontmatter.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// 1-based line in the note file, when the problem can be pinned down.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// 1-based column on that line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(message: impl Into<String>, location: Option<(usize, usize)>) -> Self {
+        Self { message: message.into(), line: location.map(|l| l.0), column: location.map(|l| l.1) }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {line}, column {column}: {}", self.message),
+            (Some(line), None) => write!(f, "line {line}: {}", self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+/// A frontmatter problem recorded for a note when the index was synced.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NoteDiagnostic {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+}
+
+/// A change to a single frontmatter key. Keys are dot-separated paths, so
+/// `metadata.status` addresses `status` nested under `metadata`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum MetadataEdit {
+    Set { key: String, value: serde_json::Value },
+    Unset { key: String },
+    /// Appends to a list unless the value is already in it.
+    Append { key: String, value: serde_json::Value },
+    /// Removes every occurrence of the value from a list, dropping the key
+    /// once the list is empty.
+    Remove { key: String, value: serde_json::Value },
+}
+
+impl MetadataEdit {
+    pub fn key(&self) -> &str {
+        match self {
+            MetadataEdit::Set { key, .. }
+            | MetadataEdit::Unset { key }
+            | MetadataEdit::Append { key, .. }
+            | MetadataEdit::Remove { key, .. } => key,
+        }
+    }
+}
+
+/// A partial edit to a note body. Line numbers are 1-based, inclusive and
+/// relative to the body, so the frontmatter is never touched.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BodyEdit {
+    Replace { from: usize, to: usize, content: String },
+    /// Inserts before line `at`; one past the last line appends.
+    Insert { at: usize, content: String },
+    Delete { from: usize, to: usize },
+    /// Replaces everything under a markdown heading up to the next heading
+    /// of the same or a higher level, keeping the heading line.
+    ReplaceSection { heading: String, content: String },
+}
+
+/// Changes to one note that [`Store::edit`](crate::Store::edit) writes
+/// together, in field order.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NoteEdit {
+    /// Replaces the whole body.
+    pub content: Option<String>,
+    pub body_edit: Option<BodyEdit>,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub metadata: Vec<MetadataEdit>,
+}
+
+impl NoteEdit {
+    pub fn is_empty(&self) -> bool {
+        self.content.is_none() && self.body_edit.is_none() && self.title.is_none() && self.metadata.is_empty()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NoteSummary {
     pub path: PathBuf,
     pub title: Option<String>,
+    /// Highlighted excerpt around the match, only set for search results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// RFC 3339, from the frontmatter or else the file's creation time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// RFC 3339, from the frontmatter or else the file's modification time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+/// Order of listed or searched notes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Path,
+    Title,
+    /// Newest first.
+    Created,
+    /// Most recently updated first.
+    Updated,
+}
+
+/// Which notes [`Store::query`](crate::Store::query) returns.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ListQuery {
+    /// Number of matching notes to skip.
+    #[serde(default)]
+    pub offset: usize,
+    /// Maximum number of notes to return; `None` returns all of them.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Only notes with this tag or one of its children (`project` also
+    /// matches `project/alpha`).
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// `None` lists by path and ranks search results by relevance.
+    #[serde(default)]
+    pub sort: Option<SortKey>,
+    /// Only notes created (when sorting by [`SortKey::Created`]) or else
+    /// updated at or after this time, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub since: Option<i64>,
+    /// Only notes created or updated before this time, counted like `since`.
+    #[serde(default)]
+    pub until: Option<i64>,
+}
+
+/// One page of notes plus the number of notes matching the query overall.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Page {
+    pub items: Vec<NoteSummary>,
+    pub total: usize,
+}
+
+/// A tag and the number of notes carrying it or one of its children.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// How a link is written in the source note.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[Note Name]]`, `[[folder/Note|alias]]` or `![[embed]]`.
+    Wiki,
+    /// `[text](relative/path.md)`.
+    Markdown,
+}
+
+impl LinkKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Wiki => "wiki",
+            LinkKind::Markdown => "markdown",
+        }
+    }
+}
+
+/// A link from one note to another.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// Note the link is written in.
+    pub source: PathBuf,
+    /// 1-based body line of the link, counted like `extt read` counts them.
+    pub line: usize,
+    pub kind: LinkKind,
+    /// Link target as written, without alias or `#heading`.
+    pub target: String,
+    /// Note the target resolves to, or `None` if there is no such note.
+    pub resolved: Option<PathBuf>,
+}
+
+/// How [`Store::move_note_with`](crate::Store::move_note_with) treats links.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct MoveOptions {
+    /// Rewrite links to the note in other notes, and relative links in the
+    /// note itself, so they still resolve after the move.
+    #[serde(default)]
+    pub rewrite_links: bool,
+    /// Report the rewrites without moving or writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A body line changed by a link rewrite.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LinkRewrite {
+    /// Note containing the line, as it was named before the move.
+    pub path: PathBuf,
+    /// 1-based body line.
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+/// A deleted note kept in the vault's trash.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Where the note was before it was deleted.
+    pub path: PathBuf,
+    /// Deletion time in seconds since the Unix epoch.
+    pub deleted_at: i64,
+}
+
+/// One saved version of a note in the vault's history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Content hash of this version, as in [`Note::revision`].
+    pub revision: String,
+    /// Where the note was when this version was saved.
+    pub path: PathBuf,
+    /// Save time in seconds since the Unix epoch.
+    pub saved_at: i64,
+    pub size: u64,
+    /// Set when this version was saved by a move from that path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_from: Option<PathBuf>,
+}
+
+/// Who a change is attributed to in the vault's git history.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Author {
+    /// The user named in the repository's git config.
+    #[default]
+    Human,
+    /// An agent acting for the user, committed as `extt agent`.
+    Agent,
+}
+
+/// How [`Store::enable_git`](crate::Store::enable_git) commits changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GitOptions {
+    pub author: Author,
+    /// Push the current branch to `origin` after every commit.
+    pub push: bool,
+}
+
+/// A commit in the vault's git history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GitCommit {
+    pub id: String,
+    pub summary: String,
+    pub author: String,
+    pub email: String,
+    /// Commit time in seconds since the Unix epoch.
+    pub time: i64,
+}
+
+/// Something [`Store::check`](crate::Store::check) found wrong with the
+/// vault or its index.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// A note on disk that is missing from the index.
+    Unindexed { path: PathBuf },
+    /// A note that changed on disk since it was indexed.
+    Outdated { path: PathBuf },
+    /// An index entry for a note that is no longer on disk.
+    Stale { path: PathBuf },
+    /// A note file that is not valid UTF-8, so it cannot be indexed.
+    InvalidUtf8 { path: PathBuf },
+    /// Frontmatter that could not be fully read.
+    InvalidFrontmatter {
+        path: PathBuf,
+        #[serde(flatten)]
+        diagnostic: Diagnostic,
+    },
+    /// A link that points at no note.
+    BrokenLink { source: PathBuf, line: usize, target: String },
+    /// Notes sharing a file name, so `[[name]]` can mean any of them.
+    AmbiguousName { name: String, paths: Vec<PathBuf> },
+    /// Notes whose paths differ only in case. They clash on case-insensitive
+    /// file systems and links to them resolve to just one.
+    CaseCollision { paths: Vec<PathBuf> },
+    /// A symlink in the vault that leads to notes outside it.
+    OutsideRoot { path: PathBuf, target: PathBuf },
+}
+
+impl Problem {
+    /// Whether [`Store::repair`](crate::Store::repair) can fix this without
+    /// touching any note.
+    pub fn is_fixable(&self) -> bool {
+        matches!(self, Problem::Unindexed { .. } | Problem::Outdated { .. } | Problem::Stale { .. })
+    }
+}
+
+/// Outcome of an incremental [`Store::sync`](crate::Store::sync).
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Notes or changes that could not be indexed and were left out.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub skipped: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
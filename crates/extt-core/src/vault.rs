@@ -1,7 +1,12 @@
-use std::path::{Path, PathBuf};
+use crate::error::{Error, Result};
+use std::path::{Component, Path, PathBuf};
 use walkdir::WalkDir;
 
 
+/// Vault-local directory for extt's own data, such as the trash. Never
+/// indexed or watched.
+pub const META_DIR: &str = ".extt";
+
 #[derive(Debug, Clone)]
 pub struct Vault {
     path: PathBuf,
@@ -17,11 +22,9 @@ impl Vault {
     }
 
     pub fn refresh(&mut self) {
-        self.files = WalkDir::new(&self.path)
-            .into_iter()
-            .filter_map(|e| e.ok())
+        self.files = walk(&self.path)
             .filter(|e| e.file_type().is_file())
-            .filter(|e| e.path().extension().map_or(false, |ext| ext == "md" || ext == "txt"))
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "md" || ext == "txt"))
             .map(|e| e.path().to_path_buf())
             .collect();
         self.files.sort();
@@ -34,6 +37,50 @@ impl Vault {
     pub fn root(&self) -> &Path {
         &self.path
     }
+
+    /// Resolves a vault-relative path, rejecting anything that would escape
+    /// the vault root.
+    pub fn resolve(&self, relative_path: &Path) -> Result<PathBuf> {
+        secure_join(&self.path, relative_path)
+    }
+}
+
+/// Whether a vault-relative path is inside [`META_DIR`].
+pub(crate) fn is_meta_path(relative_path: &Path) -> bool {
+    relative_path.components().next().is_some_and(|c| c.as_os_str() == META_DIR)
+}
+
+/// Every entry below `root`, skipping [`META_DIR`] and unreadable entries.
+pub(crate) fn walk(root: &Path) -> impl Iterator<Item = walkdir::DirEntry> {
+    let meta_dir = root.join(META_DIR);
+    WalkDir::new(root).into_iter().filter_entry(move |e| e.path() != meta_dir).filter_map(|e| e.ok())
+}
+
+/// Joins `relative_path` onto `root`, refusing absolute paths and `..`
+/// components that climb above the root.
+pub(crate) fn secure_join(root: &Path, relative_path: &Path) -> Result<PathBuf> {
+    if relative_path.is_absolute() {
+        return Err(Error::AbsolutePath(relative_path.to_path_buf()));
+    }
+
+    let mut depth = 0;
+    for component in relative_path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir => {
+                depth -= 1;
+                if depth < 0 {
+                    return Err(Error::PathTraversal(relative_path.to_path_buf()));
+                }
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::AbsolutePath(relative_path.to_path_buf()));
+            }
+        }
+    }
+
+    Ok(root.join(relative_path))
 }
 
 #[cfg(test)]
@@ -63,6 +110,19 @@ mod tests {
         assert!(!files.contains(&root.join("ignore.pdf")));
     }
 
+    #[test]
+    fn test_vault_skips_meta_dir() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".extt/trash")).unwrap();
+        fs::write(root.join(".extt/trash/old.md"), "gone").unwrap();
+        fs::write(root.join("kept.md"), "here").unwrap();
+
+        assert_eq!(Vault::new(root).files(), [root.join("kept.md")]);
+        assert!(is_meta_path(Path::new(".extt/trash/old.md")));
+        assert!(!is_meta_path(Path::new("notes/.extt.md")));
+    }
+
     #[test]
     fn test_vault_init_non_existent() {
         let temp = tempdir().unwrap();
@@ -115,6 +175,14 @@ mod tests {
         assert!(!files.contains(&root.join("no_extension")));
     }
 
+    #[test]
+    fn test_vault_resolve() {
+        let vault = Vault::new("/notes");
+        assert_eq!(vault.resolve(Path::new("sub/note.md")).unwrap(), Path::new("/notes/sub/note.md"));
+        assert!(matches!(vault.resolve(Path::new("../etc/passwd")), Err(Error::PathTraversal(_))));
+        assert!(matches!(vault.resolve(Path::new("/etc/passwd")), Err(Error::AbsolutePath(_))));
+    }
+
     #[test]
     fn test_vault_hidden_files() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,409 @@
+//! Keeps the index current while notes are edited outside of extt.
+//!
+//! A [`Watcher`] subscribes to filesystem events under the notes root,
+//! coalesces bursts of events (editors often write a file several times per
+//! save), and applies the result through [`Store::apply_changes`].
+
+use crate::error::{Error, Result};
+use crate::store::Store;
+use crate::types::SyncReport;
+use crate::vault::is_meta_path;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher as _};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// A change to apply to the index, with paths relative to the notes root.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Change {
+    Upsert(PathBuf),
+    Remove(PathBuf),
+    Rename { from: PathBuf, to: PathBuf },
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Quiet period after the last event before a batch is applied.
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self { debounce: Duration::from_millis(250) }
+    }
+}
+
+/// Background watcher for embedding in long-running processes such as the
+/// desktop app. Stops when dropped.
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl Watcher {
+    /// Moves `store` onto a background thread and keeps it in sync with the
+    /// notes root. `on_batch` runs on that thread after each applied batch.
+    pub fn spawn<F>(mut store: Store, options: WatchOptions, mut on_batch: F) -> Result<Self>
+    where
+        F: FnMut(&[Change], &SyncReport) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            run_with_ready(&mut store, &options, &thread_stop, &mut on_batch, Some(ready_tx))
+        });
+
+        // Surface subscription errors to the caller instead of from `stop`,
+        // and make sure no event after `spawn` returns is missed.
+        match ready_rx.recv() {
+            Ok(()) => Ok(Self { stop, thread: Some(thread) }),
+            Err(_) => match thread.join() {
+                Ok(Err(err)) => Err(err),
+                _ => Err(Error::Watch(notify::Error::generic("watcher thread exited unexpectedly"))),
+            },
+        }
+    }
+
+    /// Stops watching and returns the first error the watcher hit, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Error::Watch(notify::Error::generic("watcher thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Watches the store's notes root on the current thread until `stop` is set.
+pub fn run<F>(store: &mut Store, options: &WatchOptions, stop: &AtomicBool, mut on_batch: F) -> Result<()>
+where
+    F: FnMut(&[Change], &SyncReport),
+{
+    run_with_ready(store, options, stop, &mut on_batch, None)
+}
+
+fn run_with_ready<F>(
+    store: &mut Store,
+    options: &WatchOptions,
+    stop: &AtomicBool,
+    on_batch: &mut F,
+    ready: Option<mpsc::Sender<()>>,
+) -> Result<()>
+where
+    F: FnMut(&[Change], &SyncReport),
+{
+    let root = store.root().to_path_buf();
+    // Some backends (FSEvents) report canonical paths, e.g. /private/var.
+    let canonical_root = root.canonicalize().unwrap_or_else(|_| root.clone());
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    if let Some(ready) = ready {
+        let _ = ready.send(());
+    }
+
+    // Short enough that `stop` is noticed promptly even without events.
+    let poll = options.debounce.min(Duration::from_millis(100));
+    let mut pending = Debouncer::default();
+
+    while !stop.load(Ordering::Relaxed) {
+        match rx.recv_timeout(poll) {
+            Ok(event) => {
+                let event = event?;
+                for change in translate(&event, &root, &canonical_root) {
+                    pending.push(change);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        if pending.is_ready(options.debounce) {
+            let changes = pending.take();
+            let report = apply(store, &changes);
+            on_batch(&changes, &report);
+        }
+    }
+    Ok(())
+}
+
+/// Applies a batch, falling back to one change at a time when the batch
+/// fails, so that a single bad file cannot stop the watcher. Changes that
+/// still fail are counted as skipped.
+fn apply(store: &mut Store, changes: &[Change]) -> SyncReport {
+    if let Ok(report) = store.apply_changes(changes) {
+        return report;
+    }
+    let mut report = SyncReport::default();
+    for change in changes {
+        match store.apply_changes(std::slice::from_ref(change)) {
+            Ok(one) => {
+                report.added += one.added;
+                report.updated += one.updated;
+                report.removed += one.removed;
+                report.unchanged += one.unchanged;
+                report.skipped += one.skipped;
+            }
+            Err(_) => report.skipped += 1,
+        }
+    }
+    report
+}
+
+/// Collects changes until no new event has arrived for the debounce period.
+#[derive(Default)]
+struct Debouncer {
+    changes: Vec<Change>,
+    seen: HashSet<Change>,
+    last_event: Option<Instant>,
+}
+
+impl Debouncer {
+    fn push(&mut self, change: Change) {
+        self.last_event = Some(Instant::now());
+        // Repeated writes to the same file only need one re-index. Renames are
+        // kept in order because later changes may refer to the new name.
+        if matches!(change, Change::Rename { .. }) || self.seen.insert(change.clone()) {
+            self.changes.push(change);
+        }
+    }
+
+    fn is_ready(&self, debounce: Duration) -> bool {
+        !self.changes.is_empty() && self.last_event.is_some_and(|t| t.elapsed() >= debounce)
+    }
+
+    fn take(&mut self) -> Vec<Change> {
+        self.seen.clear();
+        self.last_event = None;
+        std::mem::take(&mut self.changes)
+    }
+}
+
+/// Maps a raw notify event to index changes, dropping paths outside the root.
+fn translate(event: &Event, root: &Path, canonical_root: &Path) -> Vec<Change> {
+    let relative = |path: &PathBuf| {
+        path.strip_prefix(root)
+            .or_else(|_| path.strip_prefix(canonical_root))
+            .ok()
+            .filter(|p| !p.as_os_str().is_empty() && !is_meta_path(p))
+            .map(Path::to_path_buf)
+    };
+    let each = |make: fn(PathBuf) -> Change| event.paths.iter().filter_map(relative).map(make).collect();
+
+    match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            match (relative(&event.paths[0]), relative(&event.paths[1])) {
+                (Some(from), Some(to)) => vec![Change::Rename { from, to }],
+                (Some(from), None) => vec![Change::Remove(from)],
+                (None, Some(to)) => vec![Change::Upsert(to)],
+                (None, None) => Vec::new(),
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => each(Change::Remove),
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any => each(Change::Upsert),
+        EventKind::Access(_) | EventKind::Other => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        let mut event = Event::new(kind);
+        for path in paths {
+            event = event.add_path(PathBuf::from(path));
+        }
+        event
+    }
+
+    #[test]
+    fn test_translate_events() {
+        let root = Path::new("/notes");
+        let create = event(EventKind::Create(CreateKind::File), &["/notes/a.md", "/elsewhere/b.md"]);
+        assert_eq!(translate(&create, root, root), vec![Change::Upsert("a.md".into())]);
+
+        let rename = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/notes/a.md", "/notes/sub/b.md"],
+        );
+        assert_eq!(
+            translate(&rename, root, root),
+            vec![Change::Rename { from: "a.md".into(), to: "sub/b.md".into() }]
+        );
+
+        // Moving a note out of the vault is a removal.
+        let moved_out = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/notes/a.md", "/tmp/a.md"],
+        );
+        assert_eq!(translate(&moved_out, root, root), vec![Change::Remove("a.md".into())]);
+
+        // So is moving it into the trash, and nothing inside `.extt` is watched.
+        let trashed = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/notes/a.md", "/notes/.extt/trash/1-a.md"],
+        );
+        assert_eq!(translate(&trashed, root, root), vec![Change::Remove("a.md".into())]);
+        let internal = event(EventKind::Create(CreateKind::File), &["/notes/.extt/trash/1-a.json"]);
+        assert!(translate(&internal, root, root).is_empty());
+
+        let modify = event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), &["/notes/a.md"]);
+        assert_eq!(translate(&modify, root, root), vec![Change::Upsert("a.md".into())]);
+
+        let remove = event(EventKind::Remove(RemoveKind::File), &["/notes/a.md"]);
+        assert_eq!(translate(&remove, root, root), vec![Change::Remove("a.md".into())]);
+
+        let access = event(EventKind::Access(notify::event::AccessKind::Any), &["/notes/a.md"]);
+        assert!(translate(&access, root, root).is_empty());
+    }
+
+    #[test]
+    fn test_debouncer_coalesces() {
+        let mut debouncer = Debouncer::default();
+        assert!(!debouncer.is_ready(Duration::ZERO));
+
+        debouncer.push(Change::Upsert("a.md".into()));
+        debouncer.push(Change::Upsert("a.md".into()));
+        debouncer.push(Change::Remove("b.md".into()));
+        debouncer.push(Change::Upsert("a.md".into()));
+        assert!(!debouncer.is_ready(Duration::from_secs(60)));
+        assert!(debouncer.is_ready(Duration::ZERO));
+
+        assert_eq!(
+            debouncer.take(),
+            vec![Change::Upsert("a.md".into()), Change::Remove("b.md".into())]
+        );
+        assert!(!debouncer.is_ready(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_watcher_indexes_external_edits() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        let db_path = dir.path().join("test.db");
+
+        let store = Store::new(notes_dir.clone(), db_path.clone())?;
+        let (tx, rx) = mpsc::channel();
+        let watcher = Watcher::spawn(store, WatchOptions { debounce: Duration::from_millis(50) }, move |_, report| {
+            let _ = tx.send(*report);
+        })?;
+
+        fs::write(notes_dir.join("external.md"), "---\ntitle: External\n---\nWritten elsewhere")?;
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let reader = Store::new(notes_dir.clone(), db_path)?;
+        while reader.list()?.is_empty() {
+            assert!(Instant::now() < deadline, "watcher did not index the new note");
+            let _ = rx.recv_timeout(Duration::from_millis(100));
+        }
+        assert_eq!(reader.list()?[0].title.as_deref(), Some("External"));
+
+        fs::remove_file(notes_dir.join("external.md"))?;
+        while !reader.list()?.is_empty() {
+            assert!(Instant::now() < deadline, "watcher did not drop the removed note");
+            let _ = rx.recv_timeout(Duration::from_millis(100));
+        }
+
+        watcher.stop()
+    }
+
+    #[test]
+    fn test_apply_changes() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir_all(notes_dir.join("projects"))?;
+        fs::write(notes_dir.join("a.md"), "Alpha")?;
+        fs::write(notes_dir.join("projects/b.md"), "Beta")?;
+        fs::write(notes_dir.join("projects/c.md"), "Gamma")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        let report = store.apply_changes(&[Change::Upsert("a.md".into()), Change::Upsert("projects".into())])?;
+        assert_eq!(report.added, 3);
+
+        // Rename a file and a whole directory.
+        fs::rename(notes_dir.join("a.md"), notes_dir.join("renamed.md"))?;
+        fs::rename(notes_dir.join("projects"), notes_dir.join("archive"))?;
+        let report = store.apply_changes(&[
+            Change::Rename { from: "a.md".into(), to: "renamed.md".into() },
+            Change::Rename { from: "projects".into(), to: "archive".into() },
+        ])?;
+        assert_eq!(report.updated, 3);
+        let paths: Vec<_> = store.list()?.into_iter().map(|n| n.path).collect();
+        assert_eq!(paths, vec![PathBuf::from("archive/b.md"), "archive/c.md".into(), "renamed.md".into()]);
+        assert_eq!(store.search("Gamma")?[0].path, Path::new("archive/c.md"));
+
+        // An upsert of a vanished path removes it; removing a directory drops its notes.
+        fs::remove_file(notes_dir.join("renamed.md"))?;
+        let report = store.apply_changes(&[Change::Upsert("renamed.md".into()), Change::Remove("archive".into())])?;
+        assert_eq!(report.removed, 3);
+        assert!(store.list()?.is_empty());
+
+        // Events for non-note files are ignored.
+        fs::write(notes_dir.join("image.png"), "png")?;
+        let report = store.apply_changes(&[Change::Upsert("image.png".into())])?;
+        assert_eq!(report, SyncReport::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_over_indexed_note() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("a.md"), "Alpha")?;
+        fs::write(notes_dir.join("b.md"), "Beta")?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        store.sync()?;
+
+        fs::rename(notes_dir.join("a.md"), notes_dir.join("b.md"))?;
+        let report = store.apply_changes(&[Change::Rename { from: "a.md".into(), to: "b.md".into() }])?;
+        assert_eq!((report.updated, report.removed), (1, 1));
+        let notes = store.list()?;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].path, Path::new("b.md"));
+        assert_eq!(store.search("Alpha")?[0].path, Path::new("b.md"));
+        assert!(store.search("Beta")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_skips_failing_changes() -> Result<()> {
+        let dir = tempdir()?;
+        let notes_dir = dir.path().join("notes");
+        fs::create_dir(&notes_dir)?;
+        fs::write(notes_dir.join("good.md"), "Fine")?;
+        fs::write(notes_dir.join("binary.md"), [0xff, 0xfe, 0x00])?;
+
+        let mut store = Store::new(notes_dir.clone(), dir.path().join("test.db"))?;
+        let report = apply(&mut store, &[Change::Upsert("binary.md".into()), Change::Upsert("good.md".into())]);
+        assert_eq!((report.added, report.skipped), (1, 1));
+        assert_eq!(store.list()?[0].path, Path::new("good.md"));
+
+        Ok(())
+    }
+}
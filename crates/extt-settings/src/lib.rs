@@ -1,3 +1,4 @@
+use extt_core::types::FrontmatterFormat;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use anyhow::Result;
@@ -15,6 +16,10 @@ fn default_db_path() -> PathBuf {
     config_dir.join("index.db")
 }
 
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     #[serde(default = "default_notes_dir")]
@@ -25,6 +30,24 @@ pub struct Settings {
     pub vault_path: String,
     #[serde(default)]
     pub theme: String,
+    /// Bearer token required by `extt serve`. Generated on first use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+    /// Commit every change made through extt to the git repository the
+    /// notes folder is in (one is created if there is none).
+    #[serde(default)]
+    pub git: bool,
+    /// Push to `origin` after every commit.
+    #[serde(default)]
+    pub git_push: bool,
+    /// Keep `created_at` and `updated_at` in the frontmatter of notes
+    /// written through extt up to date.
+    #[serde(default = "default_true")]
+    pub stamp_times: bool,
+    /// Frontmatter format for new notes: `yaml`, `toml` or `json`. Existing
+    /// notes keep the format they have.
+    #[serde(default)]
+    pub frontmatter_format: FrontmatterFormat,
 }
 
 impl Default for Settings {
@@ -34,6 +57,11 @@ impl Default for Settings {
             db_path: default_db_path(),
             vault_path: String::from("."),
             theme: String::from("Dark"),
+            api_token: None,
+            git: false,
+            git_push: false,
+            stamp_times: true,
+            frontmatter_format: FrontmatterFormat::default(),
         }
     }
 }